@@ -77,6 +77,8 @@ local M = {
     host = "127.0.0.1",
     port = 30110,
     auto_server_start = true,
+    -- unl-server のログに出す最大レベル ("off", "error", "warn", "info", "debug", "trace")
+    log_level = "info",
   },
 }
 return M
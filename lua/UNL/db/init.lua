@@ -216,12 +216,94 @@ function M.get_project_items(opts, callback)
 end
 
 --- サーバーサイド補完候補取得
+--- opts.config: { timeout_ms = number } など (省略可)
 function M.get_completions(opts, callback)
     local content = opts.content
     local line = opts.line
     local character = opts.character
     local file_path = opts.file_path
-    remote.get_completions(content, line, character, file_path, callback)
+    remote.get_completions(content, line, character, file_path, opts.config, callback)
+end
+
+--- サーバーサイド補完候補 + レシーバー型 ({ receiverType, items }) 取得
+function M.get_completions_detailed(opts, callback)
+    remote.get_completions_detailed(opts.content, opts.line, opts.character, callback)
+end
+
+--- カーソル位置の識別子の LSP Hover ({ contents }) を取得。解決できなければ null
+function M.get_hover(opts, callback)
+    remote.get_hover(opts.content, opts.line, opts.character, callback)
+end
+
+--- カーソル位置のクラス/メンバーの宣言位置を LSP Location ({ uri, range }) で取得
+function M.get_definition(opts, callback)
+    remote.get_definition(opts.content, opts.line, opts.character, callback)
+end
+
+--- 保存したバッファの内容で 1 ファイル分のインデックスだけを更新 ({ classes })
+function M.reindex_file(file_path, content, callback)
+    remote.reindex_file(file_path, content, callback)
+end
+
+--- クラス/メンバー名のあいまい検索結果を LSP SymbolInformation ({ name, kind, containerName, location }) の配列で取得
+function M.get_workspace_symbols(query, limit, callback)
+    if type(limit) == "function" then
+        callback = limit
+        limit = nil
+    end
+    remote.get_workspace_symbols(query, limit, callback)
+end
+
+--- バッファの namespace/class/struct/enum/関数のアウトラインを LSP DocumentSymbol ({ name, kind, range, selectionRange, children }) の木で取得
+function M.get_document_symbols(content, file_path, callback)
+    if type(file_path) == "function" then
+        callback, file_path = file_path, nil
+    end
+    remote.get_document_symbols(content, file_path, callback)
+end
+
+--- 呼び出しの括弧内の LSP SignatureHelp ({ signatures, activeSignature, activeParameter }) を取得
+function M.get_signature_help(opts, callback)
+    remote.get_signature_help(opts.content, opts.line, opts.character, callback)
+end
+
+--- 存在しないメンバーへのアクセスを LSP Diagnostic の配列で取得
+--- file_path を渡すと、そのファイル (と同名のヘッダー/ソース) で定義されたクラスは編集中でインデックスが古い可能性があるので報告しない
+function M.get_diagnostics(content, file_path, callback)
+    if type(file_path) == "function" then
+        callback, file_path = file_path, nil
+    end
+    remote.get_diagnostics(content, file_path, callback)
+end
+
+--- サブクラスでオーバーライド可能な仮想関数の一覧を取得
+function M.get_virtual_members(class_name, callback)
+    remote.get_virtual_members(class_name, callback)
+end
+
+--- クラスと祖先のインデックス状況 ({ class_name, indexed, indexed_ancestors, missing_ancestors }) を取得
+function M.verify_type_complete(class_name, callback)
+    remote.verify_type_complete(class_name, callback)
+end
+
+--- 列挙子の一覧 ({ name, value, expression, documentation }) を宣言順に取得
+function M.get_enum_value_details(enum_name, callback)
+    remote.get_enum_value_details(enum_name, callback)
+end
+
+--- lazy_resolve で省いた detail / documentation を補完候補に埋めて返す (completionItem/resolve)
+function M.resolve_completion_item(item, callback)
+    remote.resolve_completion_item(item, callback)
+end
+
+--- 変更されたファイルで定義された型に依存する補完キャッシュだけを破棄 ({ removed })
+function M.invalidate_completion_cache(paths, callback)
+    remote.invalidate_completion_cache(paths, callback)
+end
+
+--- カーソル位置の型解決の過程 ({ receiverType, steps }) を取得 (不具合報告用)
+function M.explain_resolution(opts, callback)
+    remote.explain_resolution(opts.content, opts.line, opts.character, callback)
 end
 
 return M
@@ -269,12 +269,89 @@ function M.get_all_file_paths(cb)
     M.request("GetAllFilePaths", {}, cb)
 end
 
-function M.get_completions(content, line, character, file_path, cb)
+function M.get_completions(content, line, character, file_path, config, cb)
     M.request("GetCompletions", {
         content = content,
         line = line,
         character = character,
-        file_path = file_path
+        file_path = file_path,
+        config = config
+    }, cb)
+end
+
+function M.get_completions_detailed(content, line, character, cb)
+    M.request("GetCompletionsDetailed", {
+        content = content,
+        line = line,
+        character = character
+    }, cb)
+end
+
+function M.get_hover(content, line, character, cb)
+    M.request("GetHover", {
+        content = content,
+        line = line,
+        character = character
+    }, cb)
+end
+
+function M.get_definition(content, line, character, cb)
+    M.request("GetDefinition", {
+        content = content,
+        line = line,
+        character = character
+    }, cb)
+end
+
+function M.reindex_file(file_path, content, cb)
+    M.request("ReindexFile", { file_path = file_path, content = content }, cb)
+end
+
+function M.get_workspace_symbols(query, limit, cb)
+    M.request("GetWorkspaceSymbols", { query = query, limit = limit }, cb)
+end
+
+function M.get_document_symbols(content, file_path, cb)
+    M.request("GetDocumentSymbols", { content = content, file_path = file_path }, cb)
+end
+
+function M.get_signature_help(content, line, character, cb)
+    M.request("GetSignatureHelp", {
+        content = content,
+        line = line,
+        character = character
+    }, cb)
+end
+
+function M.get_diagnostics(content, file_path, cb)
+    M.request("GetDiagnostics", { content = content, file_path = file_path }, cb)
+end
+
+function M.get_virtual_members(class_name, cb)
+    M.request("GetVirtualMembers", { class_name = class_name }, cb)
+end
+
+function M.verify_type_complete(class_name, cb)
+    M.request("VerifyTypeComplete", { class_name = class_name }, cb)
+end
+
+function M.get_enum_value_details(enum_name, cb)
+    M.request("GetEnumValueDetails", { enum_name = enum_name }, cb)
+end
+
+function M.resolve_completion_item(item, cb)
+    M.request("ResolveCompletionItem", { item = item }, cb)
+end
+
+function M.invalidate_completion_cache(paths, cb)
+    M.request("InvalidateCompletionCache", { paths = paths }, cb)
+end
+
+function M.explain_resolution(content, line, character, cb)
+    M.request("ExplainResolution", {
+        content = content,
+        line = line,
+        character = character
     }, cb)
 end
 
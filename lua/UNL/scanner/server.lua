@@ -106,7 +106,7 @@ function M.start(on_complete)
     if vim.fn.isdirectory(cache_dir) == 0 then vim.fn.mkdir(cache_dir, "p") end
     local registry_path = cache_dir .. "/registered_projects.json"
 
-    local cmd = { server_binary, tostring(conf.port), registry_path }
+    local cmd = { server_binary, tostring(conf.port), registry_path, conf.log_level or "info" }
     log.debug("Starting new UNL Server instance on port %d...", conf.port)
 
     stdout_buf = ""
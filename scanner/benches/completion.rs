@@ -0,0 +1,83 @@
+// 大きめのバッファで process_completion のレイテンシを測る (cargo bench --bench completion)
+use std::fmt::Write;
+use std::sync::Arc;
+use criterion::{criterion_group, criterion_main, Criterion};
+use rusqlite::Connection;
+use tree_sitter::Query;
+use unl_core::types::{ParseResult, ProgressReporter};
+use unl_core::{completion, db, scanner};
+
+struct QuietReporter;
+
+impl ProgressReporter for QuietReporter {
+    fn report(&self, _stage: &str, _current: usize, _total: usize, _message: &str) {}
+}
+
+const CLASS_COUNT: usize = 40;
+const MEMBERS_PER_CLASS: usize = 60;
+
+// UBenchBase0 <- UBenchBase1 <- ... の深い継承と、メンバーを多く持つヘッダー
+fn header_source() -> String {
+    let mut source = String::new();
+    for i in 0..CLASS_COUNT {
+        let base = if i == 0 { "UObject".to_string() } else { format!("UBenchBase{}", i - 1) };
+        writeln!(source, "UCLASS()\nclass UBenchBase{} : public {} {{\n    GENERATED_BODY()\npublic:", i, base).unwrap();
+        for m in 0..MEMBERS_PER_CLASS {
+            writeln!(source, "    UFUNCTION(BlueprintCallable)\n    UBenchBase{}* GetNext{}_{}(int32 Index, const FString& Name);", i, i, m).unwrap();
+            writeln!(source, "    UPROPERTY(EditAnywhere)\n    TArray<UBenchBase{}*> Items{}_{};", i, i, m).unwrap();
+        }
+        writeln!(source, "}};\n").unwrap();
+    }
+    source
+}
+
+// メソッド本体でメンバー/ローカル変数/添字/キャストに対して補完する .cpp
+fn cpp_source() -> (String, Vec<(u32, u32)>) {
+    let last = CLASS_COUNT - 1;
+    let mut lines = vec![format!("void UBenchBase{}::Run()", last), "{".to_string()];
+    for i in 0..200 {
+        let cls = i % CLASS_COUNT;
+        lines.push(format!("    UBenchBase{}* Local{} = NewObject<UBenchBase{}>(this);", cls, i, cls));
+        lines.push(format!("    Local{}->", i));
+        lines.push(format!("    Items{}_0[{}]->", cls, i));
+        lines.push(format!("    Cast<UBenchBase{}>(Local{})->GetNext{}_1(0, TEXT(\"\"))->", cls, i, cls));
+        lines.push("    this->".to_string());
+    }
+    lines.push("}".to_string());
+    let positions = lines.iter().enumerate()
+        .filter(|(_, line)| line.ends_with("->"))
+        .map(|(row, line)| (row as u32, line.len() as u32))
+        .collect();
+    (lines.join("\n"), positions)
+}
+
+fn indexed_connection() -> Connection {
+    let mut conn = Connection::open_in_memory().expect("in-memory db");
+    db::init_db(&conn).expect("schema");
+    let language: tree_sitter::Language = tree_sitter_unreal_cpp::LANGUAGE.into();
+    let query = Query::new(&language, scanner::QUERY_STR).expect("scanner query");
+    let header = header_source();
+    let data = scanner::parse_data(&header, "/Bench/Source/BenchBase.h", scanner::content_hash(&header), &language, &query).expect("parse");
+    let result = ParseResult { path: "/Bench/Source/BenchBase.h".to_string(), status: "parsed".to_string(), mtime: 0, data: Some(data), module_id: None };
+    db::save_to_db(&mut conn, &[result], Arc::new(QuietReporter)).expect("save");
+    conn
+}
+
+fn bench_completion(c: &mut Criterion) {
+    let conn = indexed_connection();
+    let (content, positions) = cpp_source();
+    c.bench_function("process_completion (all cursor positions)", |b| {
+        b.iter(|| {
+            for &(line, character) in &positions {
+                completion::process_completion(&conn, &content, line, character, None).expect("completion");
+            }
+        })
+    });
+    c.bench_function("process_completion (single member access)", |b| {
+        let (line, character) = positions[0];
+        b.iter(|| completion::process_completion(&conn, &content, line, character, None).expect("completion"))
+    });
+}
+
+criterion_group!(benches, bench_completion);
+criterion_main!(benches);
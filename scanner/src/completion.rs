@@ -1,7 +1,32 @@
-use rusqlite::Connection;
+use rusqlite::{params, Connection};
 use serde_json::{json, Value};
 use tree_sitter::{Parser, Point, Node, Query, QueryCursor, StreamingIterator};
-use std::collections::HashMap;
+use std::cell::{Cell, RefCell};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
+use std::time::Instant;
+use crate::types::{CompletionConfig, CompletionItem, CompletionItemData, CompletionItemDefaults, CompletionItemLabelDetails, CompletionList, TypeCompleteness};
+use crate::completion_cache;
+
+// 固定パターンの正規表現/クエリは最初の呼び出しで一度だけコンパイルして使い回す
+macro_rules! cached_regex {
+    ($pattern:expr) => {{
+        static RE: std::sync::OnceLock<regex::Regex> = std::sync::OnceLock::new();
+        RE.get_or_init(|| regex::Regex::new($pattern).expect("invalid regex"))
+    }};
+}
+
+macro_rules! cached_query {
+    ($source:expr) => {{
+        static QUERY: std::sync::OnceLock<Query> = std::sync::OnceLock::new();
+        QUERY.get_or_init(|| {
+            let language: tree_sitter::Language = tree_sitter_unreal_cpp::LANGUAGE.into();
+            Query::new(&language, $source).expect("invalid query")
+        })
+    }};
+}
 
 // 補完ロジックのメインエントリー
 pub fn process_completion(
@@ -9,481 +34,3421 @@ pub fn process_completion(
     content: &str,
     line: u32,
     character: u32,
-    _file_path: Option<String>,
+    file_path: Option<String>,
+) -> anyhow::Result<Value> {
+    process_completion_with_config(conn, content, line, character, file_path, &CompletionConfig::default())
+}
+
+pub fn process_completion_with_config(
+    conn: &Connection,
+    content: &str,
+    line: u32,
+    character: u32,
+    file_path: Option<String>,
+    config: &CompletionConfig,
 ) -> anyhow::Result<Value> {
     tracing::info!("--- Completion Request at {}:{} ---", line, character);
-    let mut parser = Parser::new();
-    let language: tree_sitter::Language = tree_sitter_unreal_cpp::LANGUAGE.into();
-    parser.set_language(&language)?;
+    let list = with_config(config, || complete_with_config(conn, content, line, character, file_path, config))?;
+    Ok(completion_response(list))
+}
 
-    let tree = parser.parse(content, None).ok_or_else(|| anyhow::anyhow!("Failed to parse content"))?;
-    let root = tree.root_node();
-    
-    let row = line as usize;
-    let col = character as usize;
-    
-    // カーソル位置とその直前を含むノードを探す (0.26.5 API)
-    let point = Point::new(row, col);
-    let prev_point = Point::new(row, if col > 0 { col - 1 } else { 0 });
-    
-    let node = match root.descendant_for_point_range(prev_point, point) {
-        Some(n) => n,
-        None => return Ok(json!([])),
+/// 補完結果を MessagePack (CompletionList) で返す
+#[cfg(feature = "msgpack")]
+pub fn process_completion_msgpack(
+    conn: &Connection,
+    content: &str,
+    line: u32,
+    character: u32,
+    file_path: Option<String>,
+    config: &CompletionConfig,
+) -> anyhow::Result<Vec<u8>> {
+    let list = with_config(config, || complete_with_config(conn, content, line, character, file_path, config))?;
+    Ok(rmp_serde::to_vec_named(&list)?)
+}
+
+fn complete_with_config(
+    conn: &Connection,
+    content: &str,
+    line: u32,
+    character: u32,
+    file_path: Option<String>,
+    config: &CompletionConfig,
+) -> anyhow::Result<CompletionList> {
+    let deadline = config.timeout.map(|t| Instant::now() + t);
+    if config.include_scope && config.includes.is_none() {
+        let includes = buffer_includes(content);
+        ACTIVE_CONFIG.with(|c| c.borrow_mut().includes = Some(includes));
+    }
+
+    // 0. 文字列リテラル/マクロ引数の補完 (UE_LOG のカテゴリ, CVar 名)
+    let (mut items, incomplete) = if let Some(literal_ctx) = detect_literal_context(content, line as usize, character as usize) {
+        tracing::debug!("Literal context detected: {:?}", literal_ctx);
+        (fetch_literal_candidates(conn, &literal_ctx)?, false)
+    } else if let Some(handler_class) = detect_delegate_binding(content, line as usize, character as usize) {
+        // AddDynamic(this, &AMyClass::|) はバインド可能な UFUNCTION だけを出す
+        tracing::debug!("Dynamic delegate binding detected for '{}'", handler_class);
+        completion_cache::sync(conn)?;
+        let result = fetch_delegate_handlers(conn, &handler_class);
+        completion_cache::persist(conn);
+        (result?, false)
+    } else {
+        completion_cache::sync(conn)?;
+        let result = complete_members(conn, content, line as usize, character as usize, deadline);
+        completion_cache::persist(conn);
+        result?
     };
 
-    let node_type = node.kind();
-    tracing::info!("Node at cursor: kind='{}', text='{}'", node_type, get_node_text(&node, content));
-    
-    // 1. 演算子（. -> ::）の直後、または演算子そのものの場合
-    if node_type == "." || node_type == "->" || node_type == "::" || node_type == ":" {
-        let op_node = if node_type == ":" {
-            node.parent().filter(|p| p.kind() == "::").unwrap_or(node)
+    apply_item_options(conn, &mut items, config, file_path.as_deref())?;
+    // Actor->Get| のように演算子の後ろが入力途中なら、その部分で絞り込む
+    let partial = partial_token_before(content, line as usize, character as usize);
+    match config.fuzzy_min_prefix {
+        Some(min_len) if partial.len() >= min_len.max(1) => fuzzy_filter(&mut items, &partial, true),
+        None if config.prefix_filter && !partial.is_empty() => fuzzy_filter(&mut items, &partial, false),
+        _ => {}
+    }
+    if let Some(hook) = &config.item_hook {
+        items.iter_mut().for_each(|item| (hook.0)(item));
+    }
+    let item_defaults = if config.item_defaults { extract_item_defaults(&mut items) } else { None };
+    Ok(CompletionList { is_incomplete: incomplete, item_defaults, items })
+}
+
+// カーソル直前まで入力されている識別子 (Obj->Get| の "Get")
+fn partial_token_before(content: &str, row: usize, col: usize) -> String {
+    let line = content.lines().nth(row).unwrap_or("");
+    let before = line.get(..col.min(line.len())).unwrap_or("");
+    // 直前の区切りが「や、のような複数バイトの文字でも文字境界で切る
+    let start = before.char_indices().rev()
+        .find(|&(_, c)| !(c.is_alphanumeric() || c == '_'))
+        .map_or(0, |(i, c)| i + c.len_utf8());
+    before[start..].to_string()
+}
+
+// 大文字小文字を区別しない前方一致 (subsequence なら部分列一致) で絞り込み、前方一致を上位にする
+fn fuzzy_filter(items: &mut Vec<CompletionItem>, partial: &str, subsequence: bool) {
+    let needle: Vec<char> = partial.to_lowercase().chars().collect();
+    items.retain_mut(|item| {
+        let score = if item.label.starts_with(partial) {
+            0
+        } else if item.label.to_lowercase().starts_with(&partial.to_lowercase()) {
+            1
+        } else if !subsequence {
+            return false;
         } else {
-            node
+            let mut rest = needle.iter().peekable();
+            for c in item.label.to_lowercase().chars() {
+                if rest.peek() == Some(&&c) { rest.next(); }
+            }
+            if rest.peek().is_some() { return false; }
+            2
         };
+        let base = item.sort_text.clone().unwrap_or_else(|| item.label.clone());
+        item.sort_text = Some(format!("{}{}", score, base));
+        true
+    });
+}
 
-        if let Some(prev) = get_prev_meaningful_sibling(op_node) {
-            tracing::info!("Operator detected, target node: kind='{}', text='{}'", prev.kind(), get_node_text(&prev, content));
-            return resolve_node_and_fetch_members(conn, prev, &root, content, row);
-        }
+/// completionItem/resolve: lazy_resolve で省いた detail/documentation を data (class_id, name) から埋め直す
+/// data 以外のフィールドはクライアントから受け取ったまま返す
+pub fn process_completion_resolve(conn: &Connection, item: Value) -> anyhow::Result<Value> {
+    let mut resolved = item.clone();
+    let data: CompletionItemData = match item.get("data").cloned().map(serde_json::from_value) {
+        Some(Ok(d)) => d,
+        _ => return Ok(item),
+    };
+    let (Some(class_id), Some(name)) = (data.class_id, data.name) else { return Ok(item) };
+
+    let mut stmt = conn.prepare("SELECT return_type, detail FROM members WHERE class_id = ? AND name = ? ORDER BY line_number LIMIT 1")?;
+    let mut rows = stmt.query(params![class_id, name])?;
+    let (detail, documentation) = if let Some(row) = rows.next()? {
+        (row.get::<_, Option<String>>(0)?.unwrap_or_default(), row.get::<_, Option<String>>(1)?.unwrap_or_default())
+    } else if let Some(value) = load_enum_values(conn, class_id)?.into_iter().find(|v| v.name == name) {
+        let documentation: Vec<String> = [value.display_name.clone(), value.documentation.clone()].into_iter().flatten().collect();
+        (value.detail(), documentation.join("\n\n"))
+    } else {
+        return Ok(item);
+    };
+    resolved["detail"] = json!(detail);
+    if !documentation.is_empty() {
+        resolved["documentation"] = json!(documentation);
     }
+    Ok(resolved)
+}
 
-    // 2. 識別子の入力途中、またはそれ以外
-    let mut curr_opt = Some(node);
-    while let Some(curr) = curr_opt {
-        let p_kind = curr.kind();
-        if p_kind == "field_expression" {
-            if let Some(obj_node) = curr.child_by_field_name("argument") {
-                return resolve_node_and_fetch_members(conn, obj_node, &root, content, row);
-            }
-            break;
-        } else if p_kind == "qualified_identifier" {
-            if let Some(scope_node) = curr.child_by_field_name("scope") {
-                return resolve_static_members(conn, get_node_text(&scope_node, content));
-            }
-            break;
-        } else if p_kind == "ERROR" {
-            // ERRORノード内の子要素を逆順に探して演算子を見つける
-            let count = curr.child_count();
-            for i in (0..count).rev() {
-                if let Some(child) = curr.child(i as u32) {
-                    let ck = child.kind();
-                    if ck == "." || ck == "->" || ck == "::" {
-                        if let Some(prev) = get_prev_meaningful_sibling(child) {
-                             return resolve_node_and_fetch_members(conn, prev, &root, content, row);
-                        }
-                    }
-                }
+// 全候補で同じ値のフィールドを itemDefaults に移し、各候補からは省く
+fn extract_item_defaults(items: &mut [CompletionItem]) -> Option<CompletionItemDefaults> {
+    let format = items.first()?.insert_text_format;
+    if format.is_none() || items.iter().any(|item| item.insert_text_format != format) {
+        return None;
+    }
+    for item in items.iter_mut() {
+        item.insert_text_format = None;
+    }
+    Some(CompletionItemDefaults { insert_text_format: format })
+}
+
+thread_local! {
+    // 型解決の途中で参照する設定。リクエストの間だけ with_config で差し替える
+    static ACTIVE_CONFIG: RefCell<CompletionConfig> = RefCell::new(CompletionConfig::default());
+}
+
+fn with_config<R>(config: &CompletionConfig, f: impl FnOnce() -> R) -> R {
+    // 入れ子の呼び出しやパニックでも、抜けるときに直前の設定へ戻す
+    struct Restore(Option<CompletionConfig>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            if let Some(previous) = self.0.take() {
+                ACTIVE_CONFIG.with(|c| *c.borrow_mut() = previous);
             }
         }
-        curr_opt = curr.parent();
     }
+    let _restore = Restore(Some(ACTIVE_CONFIG.with(|c| c.replace(config.clone()))));
+    f()
+}
 
-    // 3. 暗黙の this 補完 (スタンドアロンの識別子入力時)
-    if node_type == "identifier" || node_type == "type_identifier" || node_type == "field_identifier" || node_type == "this" {
-        if let Some(current_class) = get_enclosing_class_name(&node, content) {
-            tracing::info!("Implicit 'this' context detected: '{}'", current_class);
-            let members = fetch_members_recursive(conn, &current_class)?;
-            if !members.is_empty() {
-                return Ok(json!(members));
-            }
+fn active_config<R>(f: impl FnOnce(&CompletionConfig) -> R) -> R {
+    ACTIVE_CONFIG.with(|c| f(&c.borrow()))
+}
+
+// 戻り値の bool はタイムアウトで打ち切ったかどうか
+fn complete_members(conn: &Connection, content: &str, row: usize, col: usize, deadline: Option<Instant>) -> anyhow::Result<(Vec<CompletionItem>, bool)> {
+    let tree = parse_content(content)?;
+    let root = tree.root_node();
+
+    let target = match locate_completion_target(root, content, row, col) {
+        Some(t) => t,
+        None if active_config(|c| c.strict) => {
+            let point = Point::new(row, col);
+            let kind = root.descendant_for_point_range(point, point).map_or("none", |n| n.kind());
+            return Err(anyhow::anyhow!("No completion target at {}:{} (node kind '{}')", row, col, kind));
+        }
+        // クラスの外 (.cpp のフリー関数内など) ではグローバル関数とマクロを出す
+        None => return Ok((fetch_globals(conn, &partial_token_before(content, row, col))?, false)),
+    };
+    let member_access = matches!(target, CompletionTarget::Expression(_));
+    let receiver = match &target {
+        CompletionTarget::Expression(n) => Some(*n),
+        _ => None,
+    };
+    let implicit_this = matches!(target, CompletionTarget::ImplicitThis(_));
+    let enclosing_class = match &target {
+        CompletionTarget::Expression(n) | CompletionTarget::Scope(n) => get_enclosing_class_name(n, content),
+        CompletionTarget::ImplicitThis(_) => None,
+    };
+    let scope_path = typed_scope_before(content, row, col);
+    let class_name = match resolve_target_type(conn, target, &root, content, row)? {
+        Some(c) => c,
+        // classes に行が無い名前空間のスコープは中の型だけを出す
+        None => return match scope_path {
+            Some(scope) => Ok((fetch_scope_types(conn, &scope)?, false)),
+            None => Ok((Vec::new(), false)),
+        },
+    };
+    tracing::debug!("Final type for member lookup: '{}'", class_name);
+
+    if is_past(deadline) {
+        tracing::warn!("Completion timed out while resolving '{}'", class_name);
+        return Ok((Vec::new(), true));
+    }
+    // Callback. / OnDone-> が TFunction<void(int32)> ならクラスのメンバーではなく呼び出しのシグネチャを出す
+    if member_access && CALLABLE_TEMPLATES.contains(&class_name.as_str()) {
+        let raw = match receiver {
+            Some(n) => resolve_raw_expression_type(conn, n, &root, content, row)?,
+            None => None,
+        };
+        return Ok((callable_items(&class_name, raw.as_deref()), false));
+    }
+    let access_filter = match enclosing_class {
+        _ if implicit_this => AccessFilter::AllAccess,
+        Some(enclosing) => access_filter_for(conn, &enclosing, &class_name)?,
+        None => AccessFilter::PublicOnly,
+    };
+    let (mut items, incomplete) = fetch_members_recursive(conn, &class_name, access_filter, deadline)?;
+    // enum class の値は EnumName:: の後でだけ出す
+    let scope_typed = scope_typed_before(content, row, col);
+    if !scope_typed {
+        items.retain(|item| item.kind != 20 || item.data.as_ref().map_or(true, |d| !d.flags.split_whitespace().any(|f| f == "scoped")));
+    }
+    if active_config(|c| c.qualified_insert_text) && !member_access && !scope_typed {
+        qualify_insert_text(&mut items, &extract_clean_type(&class_name));
+    }
+    // Outer:: ではネストした型も候補にする
+    if let Some(scope) = scope_path {
+        items.extend(fetch_scope_types(conn, &scope)?);
+    }
+    // 暗黙の this ではメンバーを優先し、同名でないグローバル関数/マクロを後ろに足す
+    if implicit_this {
+        for global in fetch_globals(conn, &partial_token_before(content, row, col))? {
+            if !items.iter().any(|i| i.label == global.label) { items.push(global); }
         }
     }
+    Ok((items, incomplete))
+}
 
-    Ok(json!([]))
+// カーソル直前の `Foo::Bar::` のスコープ部分 ("Foo::Bar")
+fn typed_scope_before(content: &str, row: usize, col: usize) -> Option<String> {
+    let line = content.lines().nth(row)?;
+    let before = line.get(..col.min(line.len()))?;
+    let re = cached_regex!(r"([A-Za-z_][A-Za-z0-9_]*(?:\s*::\s*[A-Za-z_][A-Za-z0-9_]*)*)\s*::\s*[A-Za-z0-9_]*$");
+    let cap = re.captures(before)?;
+    Some(cap.get(1)?.as_str().split("::").map(|s| s.trim()).collect::<Vec<_>>().join("::"))
 }
 
-fn get_node_text<'a>(node: &Node, content: &'a str) -> &'a str {
-    let range = node.byte_range();
-    if range.end <= content.len() {
-        &content[range.start..range.end]
-    } else {
-        ""
+/// 名前空間/クラス scope の直下で宣言された型
+fn fetch_scope_types(conn: &Connection, scope: &str) -> anyhow::Result<Vec<CompletionItem>> {
+    let mut stmt = conn.prepare("SELECT DISTINCT c.name, c.namespace, c.symbol_type FROM classes c WHERE c.namespace = ?1 OR c.namespace LIKE '%::' || ?1")?;
+    let with_namespace = active_config(|c| c.namespace_label_details);
+    let rows = stmt.query_map([scope], |row| {
+        let name: String = row.get(0)?;
+        let namespace: Option<String> = row.get(1)?;
+        let symbol_type: String = row.get::<_, Option<String>>(2)?.unwrap_or_default();
+        let kind = match symbol_type.as_str() {
+            "struct" | "USTRUCT" => 22,
+            "enum" | "UENUM" => 13,
+            _ => 7,
+        };
+        let mut item = CompletionItem::new(&name, kind, &symbol_type);
+        if with_namespace {
+            item.label_details = Some(CompletionItemLabelDetails { detail: None, description: namespace });
+        }
+        Ok(item)
+    })?;
+    let mut items: Vec<CompletionItem> = Vec::new();
+    for item in rows {
+        let item = item?;
+        // 同じ型が複数ファイルで登録されている場合は 1 つにまとめる
+        if !items.iter().any(|i| i.label == item.label && i.label_details == item.label_details) {
+            items.push(item);
+        }
     }
+    Ok(items)
 }
 
-fn get_prev_meaningful_sibling(node: Node) -> Option<Node> {
-    let mut curr = node.prev_sibling();
-    while let Some(n) = curr {
-        let kind = n.kind();
-        if kind != "comment" && kind != " " && kind != "\n" && kind != "\r" {
-            return Some(n);
+// 入力途中の名前で前方一致するグローバル関数と #define マクロ (未入力なら出さない)
+fn fetch_globals(conn: &Connection, prefix: &str) -> anyhow::Result<Vec<CompletionItem>> {
+    if prefix.is_empty() { return Ok(Vec::new()); }
+    let mut stmt = conn.prepare(
+        "SELECT g.name, g.kind, g.return_type, g.detail, f.path FROM globals g LEFT JOIN files f ON g.file_id = f.id
+         WHERE g.name LIKE ? GROUP BY g.name, g.kind ORDER BY g.name LIMIT 200",
+    )?;
+    let rows = stmt.query_map([format!("{}%", prefix)], |row| {
+        let name: String = row.get(0)?;
+        let kind: String = row.get(1)?;
+        let return_type: Option<String> = row.get(2)?;
+        let params: Option<String> = row.get(3)?;
+        let (lsp_kind, detail) = match kind.as_str() {
+            "function" => (3, return_type.unwrap_or_default()),
+            "macro_function" => (3, "macro".to_string()),
+            _ => (21, "macro".to_string()),
+        };
+        let mut item = CompletionItem::new(&name, lsp_kind, &detail);
+        item.documentation = params;
+        // メンバーより後ろに並べる
+        item.sort_text = Some(format!("~{}", name));
+        item.data = Some(CompletionItemData { file: row.get(4)?, ..Default::default() });
+        Ok(item)
+    })?;
+    Ok(rows.collect::<Result<Vec<_>, _>>()?)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum AccessFilter {
+    AllAccess,
+    PublicOnly,
+    PublicProtected,
+}
+
+impl AccessFilter {
+    fn allows(self, access: &str) -> bool {
+        match self {
+            AccessFilter::AllAccess => true,
+            AccessFilter::PublicOnly => access == "public",
+            AccessFilter::PublicProtected => access != "private",
         }
-        curr = n.prev_sibling();
     }
-    None
 }
 
-fn resolve_node_and_fetch_members(
-    conn: &Connection,
-    node: Node,
-    root: &Node,
-    content: &str,
-    cursor_row: usize,
-) -> anyhow::Result<Value> {
-    if let Some(t_name) = resolve_expression_type(conn, node, root, content, cursor_row)? {
-        let resolved = resolve_typedef(conn, &t_name)?;
-        tracing::info!("Final type for member lookup: '{}'", resolved);
-        
-        let members = fetch_members_recursive(conn, &resolved)?;
-        return Ok(json!(members));
+// 同じクラスなら全て、派生クラスからなら protected まで、それ以外は public のみ
+fn access_filter_for(conn: &Connection, enclosing_class: &str, target_class: &str) -> anyhow::Result<AccessFilter> {
+    let enclosing = resolve_typedef(conn, enclosing_class)?;
+    let target_class = extract_clean_type(target_class);
+    let target_class = target_class.as_str();
+    if enclosing.eq_ignore_ascii_case(target_class) {
+        return Ok(AccessFilter::AllAccess);
+    }
+    if class_ancestors(conn, &enclosing)?.iter().any(|cls| cls.eq_ignore_ascii_case(target_class)) {
+        return Ok(AccessFilter::PublicProtected);
     }
-    Ok(json!([]))
+    Ok(AccessFilter::PublicOnly)
 }
 
-fn resolve_expression_type(
-    conn: &Connection,
-    node: Node,
-    root: &Node,
-    content: &str,
-    cursor_row: usize,
-) -> anyhow::Result<Option<String>> {
-    let kind = node.kind();
-    tracing::info!("resolve_expression_type(kind='{}', text='{}')", kind, get_node_text(&node, content));
+// カーソル位置の単語の直前が `::` か (EMyEnum::Rif| など)
+fn scope_typed_before(content: &str, row: usize, col: usize) -> bool {
+    let Some(line) = content.lines().nth(row) else { return false };
+    let before = line.get(..col.min(line.len())).unwrap_or("");
+    before.trim_end_matches(|c: char| c.is_alphanumeric() || c == '_').trim_end().ends_with("::")
+}
 
-    match kind {
-        "this" => {
-            let cls = get_enclosing_class_name(&node, content);
-            tracing::info!("Resolved 'this' to class: {:?}", cls);
-            Ok(cls)
+// スコープが入力されていない文脈では enum 値と (自クラス以外の) 静的メンバーをスコープ付きで挿入する
+fn qualify_insert_text(items: &mut [CompletionItem], class_name: &str) {
+    for item in items.iter_mut() {
+        let Some(data) = item.data.as_ref() else { continue };
+        if item.kind == 20 || (data.is_static && data.class != class_name) {
+            item.insert_text = format!("{}::{}", data.class, item.insert_text);
         }
-        "identifier" | "type_identifier" | "field_identifier" | "namespace_identifier" | "scoped_type_identifier" => {
-            let name = get_node_text(&node, content).trim();
-            if name.is_empty() { return Ok(None); }
-            if name == "this" {
-                return Ok(get_enclosing_class_name(&node, content));
-            }
-            if let Some(t) = infer_variable_type(name, root, content, cursor_row)? {
-                return Ok(Some(t));
-            }
-            if let Some(current_class) = get_enclosing_class_name(&node, content) {
-                tracing::info!("Checking if '{}' is a member variable of '{}'", name, current_class);
-                if let Some(rt) = find_member_return_type(conn, &current_class, name)? {
-                    return Ok(Some(rt));
-                }
-            }
-            
-            // Fallback: Check if it's a known type (Class or Enum)
-            if is_known_type(conn, name)? {
-                return Ok(Some(name.to_string()));
-            }
+    }
+}
 
-            Ok(None)
-        }
-        "qualified_identifier" => {
-            let text = get_node_text(&node, content);
-            if is_known_type(conn, text)? {
-                return Ok(Some(text.to_string()));
-            }
-            Ok(None)
-        }
-        "call_expression" => {
-            if let Some(func_node) = node.child_by_field_name("function") {
-                if func_node.kind() == "field_expression" {
-                    if let Some(obj_node) = func_node.child_by_field_name("argument") {
-                        if let Some(obj_type) = resolve_expression_type(conn, obj_node, root, content, cursor_row)? {
-                            if let Some(field_node) = func_node.child_by_field_name("field") {
-                                return find_member_return_type(conn, &obj_type, get_node_text(&field_node, content).trim());
-                            }
-                        }
-                    }
-                } else {
-                    let func_name = get_node_text(&func_node, content).trim();
-                    if let Some(current_class) = get_enclosing_class_name(&node, content) {
-                        return find_member_return_type(conn, &current_class, func_name);
-                    }
-                }
-            }
-            Ok(None)
-        }
-        "field_expression" => {
-            if let Some(obj_node) = node.child_by_field_name("argument") {
-                if let Some(obj_type) = resolve_expression_type(conn, obj_node, root, content, cursor_row)? {
-                    if let Some(field_node) = node.child_by_field_name("field") {
-                        return find_member_return_type(conn, &obj_type, get_node_text(&field_node, content).trim());
+// 設定に応じて各候補を加工する
+fn apply_item_options(conn: &Connection, items: &mut [CompletionItem], config: &CompletionConfig, current_file: Option<&str>) -> anyhow::Result<()> {
+    if config.recency_boost {
+        // 0: 編集中のファイル, 1: 最近更新されたファイル, 2: それ以外 (エンジン等)
+        let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0);
+        let current = current_file.map(normalize_path);
+        let mut recency: HashMap<String, bool> = HashMap::new();
+        let mut mtime_stmt = conn.prepare("SELECT mtime FROM files WHERE path = ?")?;
+        for item in items.iter_mut() {
+            let tier = match item.data.as_ref().and_then(|d| d.file.clone()) {
+                Some(f) if current.as_deref() == Some(normalize_path(&f).as_str()) => 0,
+                Some(f) => {
+                    if !recency.contains_key(&f) {
+                        let mtime: Option<i64> = mtime_stmt.query_row([&f], |r| r.get(0)).ok();
+                        recency.insert(f.clone(), mtime.map_or(false, |m| now - m <= RECENT_FILE_WINDOW_SECS));
                     }
+                    if recency[&f] { 1 } else { 2 }
                 }
-            }
-            Ok(None)
+                None => 2,
+            };
+            let base = item.sort_text.clone().unwrap_or_else(|| item.label.clone());
+            item.sort_text = Some(format!("{}{}", tier, base));
         }
-        _ => Ok(None)
     }
-}
 
-fn find_member_return_type(conn: &Connection, class_name: &str, member_name: &str) -> anyhow::Result<Option<String>> {
-    let clean_class = extract_clean_type(class_name);
-    let resolved_class = resolve_typedef(conn, &clean_class)?;
-    tracing::info!("Searching member '{}' in class '{}' (and parents)", member_name, resolved_class);
-    
-    let mut queue = vec![resolved_class];
-    let mut visited = HashMap::new();
-    while let Some(cls) = queue.pop() {
-        if visited.contains_key(&cls) { continue; }
-        visited.insert(cls.clone(), true);
-        
-        let mut stmt = conn.prepare("
-            SELECT m.return_type FROM members m JOIN classes c ON m.class_id = c.id 
-            WHERE c.name = ? AND m.name = ? 
-            ORDER BY (CASE WHEN m.return_type = 'T' OR m.return_type = 'T*' OR m.return_type = 'void' THEN 1 ELSE 0 END) ASC, length(m.return_type) DESC 
-            LIMIT 1
-        ")?;
-        let mut rows = stmt.query([&cls, member_name])?;
-        if let Some(row) = rows.next()? {
-            if let Some(rt) = row.get::<_, Option<String>>(0)? {
-                let cleaned = extract_clean_type(&rt);
-                tracing::info!("Found member '{}' -> '{}' in '{}'", member_name, cleaned, cls);
-                return Ok(Some(cleaned));
-            }
+    if config.deprecated_penalty > 0 || config.editor_only_penalty > 0 {
+        // 重みの合計を先頭に付けて、非推奨/エディタ専用のメンバーを下に沈める
+        for item in items.iter_mut() {
+            let flags = item.data.as_ref().map(|d| d.flags.as_str()).unwrap_or("");
+            let mut penalty = 0;
+            if flags.split_whitespace().any(|f| f == "deprecated") { penalty += config.deprecated_penalty; }
+            if flags.split_whitespace().any(|f| f == "editor_only") { penalty += config.editor_only_penalty; }
+            let base = item.sort_text.clone().unwrap_or_else(|| item.label.clone());
+            item.sort_text = Some(format!("{:04}{}", penalty, base));
         }
-        
-        let mut p_stmt = conn.prepare("SELECT parent_name FROM inheritance i JOIN classes c ON i.child_id = c.id WHERE c.name = ?")?;
-        let p_rows = p_stmt.query_map([&cls], |r| Ok(r.get::<_, String>(0)?))?;
-        for p in p_rows { queue.push(p?); }
     }
-    Ok(None)
-}
 
-fn get_enclosing_class_name(start_node: &Node, content: &str) -> Option<String> {
-    let mut curr_opt = Some(*start_node);
-    while let Some(curr) = curr_opt {
-        let kind = curr.kind();
-        if kind == "class_specifier" || kind == "struct_specifier" || 
-           kind == "unreal_class_declaration" || kind == "unreal_struct_declaration" {
-            if let Some(name_node) = curr.child_by_field_name("name") {
-                let name = get_node_text(&name_node, content).trim().to_string();
-                tracing::info!("Enclosing class found via specifier: '{}'", name);
-                return Some(name);
-            }
-        } else if kind == "function_definition" {
-            if let Some(decl) = curr.child_by_field_name("declarator") {
-                if let Some(qualified) = find_qualified_identifier(decl) {
-                    if let Some(scope) = qualified.child_by_field_name("scope") {
-                        let text = get_node_text(&scope, content).trim().trim_end_matches("::");
-                        let clean = extract_clean_type(text);
-                        tracing::info!("Enclosing class found via qualified method: '{}'", clean);
-                        return Some(clean);
-                    }
-                }
+    if config.unprefixed_filter_text {
+        // UFoo -> "Foo UFoo": 接頭辞の有無どちらで入力しても絞り込めるようにする
+        for item in items.iter_mut() {
+            if let Some(stripped) = strip_type_prefix(&item.label) {
+                item.filter_text = Some(format!("{} {}", stripped, item.label));
             }
         }
-        curr_opt = curr.parent();
     }
-    None
+    Ok(())
 }
 
-fn find_qualified_identifier(node: Node) -> Option<Node> {
-    if node.kind() == "qualified_identifier" { return Some(node); }
-    for i in 0..node.child_count() {
-        if let Some(child) = node.child(i as u32) {
-            if let Some(res) = find_qualified_identifier(child) { return Some(res); }
-        }
-    }
-    None
+// recency_boost で「最近更新された」とみなす期間
+const RECENT_FILE_WINDOW_SECS: i64 = 60 * 60;
+
+fn normalize_path(path: &str) -> String {
+    path.replace('\\', "/")
 }
 
-fn resolve_typedef(conn: &Connection, type_name: &str) -> anyhow::Result<String> {
-    let mut current = extract_clean_type(type_name);
-    if current.is_empty() || current == "T" || current == "void" { return Ok(current); }
-    for _ in 0..3 {
-        let mut stmt = conn.prepare("SELECT base_class FROM classes WHERE name = ? AND symbol_type = 'typedef' ORDER BY (CASE WHEN base_class IS NOT NULL AND base_class != '' THEN 0 ELSE 1 END) ASC LIMIT 1")?;
-        let mut rows = stmt.query([&current])?;
-        if let Some(row) = rows.next()? {
-            if let Some(base) = row.get::<_, Option<String>>(0)? {
-                let clean = extract_clean_type(&base);
-                if clean == current || clean.is_empty() { break; }
-                current = clean;
-            } else { break; }
-        } else { break; }
+/// UE の型接頭辞 (U/A/F/I/E/T/S + 大文字) を除いた名前
+fn strip_type_prefix(name: &str) -> Option<&str> {
+    let mut chars = name.chars();
+    let prefix = chars.next()?;
+    let next = chars.next()?;
+    if "UAFIETS".contains(prefix) && next.is_ascii_uppercase() {
+        Some(&name[1..])
+    } else {
+        None
     }
-    Ok(current)
 }
 
-fn resolve_static_members(conn: &Connection, scope_name: &str) -> anyhow::Result<Value> {
-    let clean_scope = extract_clean_type(scope_name);
-    let t_name = resolve_typedef(conn, &clean_scope)?;
-    let members = fetch_members_recursive(conn, &t_name)?;
-    Ok(json!(members))
+/// 補完候補と、そのレシーバーの解決済み型をまとめて返す
+pub fn process_completion_detailed(
+    conn: &Connection,
+    content: &str,
+    line: u32,
+    character: u32,
+) -> anyhow::Result<Value> {
+    let receiver_type = resolve_type_at(conn, content, line, character)?;
+    let items = process_completion(conn, content, line, character, None)?;
+    Ok(json!({ "receiverType": receiver_type, "items": items }))
 }
 
-fn fetch_members_recursive(conn: &Connection, class_name: &str) -> anyhow::Result<Vec<Value>> {
-    let mut result = Vec::new();
-    let mut queue = vec![class_name.to_string()];
-    let mut visited = HashMap::new();
-    while let Some(current) = queue.pop() {
-        if visited.contains_key(&current) { continue; }
-        visited.insert(current.clone(), true);
-        
-        let mut stmt = conn.prepare("SELECT c.id FROM classes c LEFT JOIN members m ON c.id = m.class_id WHERE LOWER(c.name) = LOWER(?) GROUP BY c.id ORDER BY COUNT(m.id) DESC LIMIT 1")?;
-        let mut rows = stmt.query([&current])?;
+/// カーソル位置の補完レシーバー (`Obj.` の Obj, `Scope::` の Scope, 暗黙の this) の型名を解決する
+pub fn resolve_type_at(conn: &Connection, content: &str, line: u32, character: u32) -> anyhow::Result<Option<String>> {
+    completion_cache::sync(conn)?;
+    let tree = parse_content(content)?;
+    let root = tree.root_node();
+    let row = line as usize;
+
+    match locate_completion_target(root, content, row, character as usize) {
+        Some(target) => resolve_target_type(conn, target, &root, content, row),
+        None => Ok(None),
+    }
+}
+
+/// カーソル位置の識別子の型/シグネチャを LSP Hover (markdown) で返す。解決できなければ null
+pub fn process_hover(conn: &Connection, content: &str, line: u32, character: u32) -> anyhow::Result<Value> {
+    completion_cache::sync(conn)?;
+    let tree = parse_content(content)?;
+    let root = tree.root_node();
+    let row = line as usize;
+    let point = Point::new(row, character as usize);
+    let node = match root.descendant_for_point_range(point, point) {
+        Some(n) => n,
+        None => return Ok(Value::Null),
+    };
+    let text = match hover_text(conn, node, &root, content, row)? {
+        Some(t) => t,
+        None => return Ok(Value::Null),
+    };
+    Ok(json!({ "contents": { "kind": "markdown", "value": format!("```cpp\n{}\n```", text) } }))
+}
+
+fn hover_text(conn: &Connection, node: Node, root: &Node, content: &str, row: usize) -> anyhow::Result<Option<String>> {
+    match symbol_at(conn, node, root, content, row)? {
+        Some(SymbolRef::Class(cls)) => {
+            // 別名なら経路も出す (FMyAlias -> FOtherAlias -> FFoo)
+            let chain = resolve_typedef_chain(conn, &cls)?;
+            match chain.last() {
+                Some(last) if chain.len() > 1 => Ok(Some(format!("class {}\n\n{}", last, chain.join(" -> ")))),
+                _ => Ok(Some(format!("class {}", cls))),
+            }
+        }
+        Some(SymbolRef::Member { class, name }) => member_hover(conn, &class, &name),
+        Some(SymbolRef::Local { name, type_name }) => Ok(Some(format!("{} {}", type_name, name))),
+        None => Ok(None),
+    }
+}
+
+/// ホバー/定義ジャンプ対象のシンボル
+enum SymbolRef {
+    Class(String),
+    Member { class: String, name: String },
+    Local { name: String, type_name: String },
+}
+
+fn symbol_at(conn: &Connection, node: Node, root: &Node, content: &str, row: usize) -> anyhow::Result<Option<SymbolRef>> {
+    let name = get_node_text(&node, content).trim().to_string();
+    if node.kind() == "this" {
+        return Ok(get_enclosing_class_name(&node, content).map(SymbolRef::Class));
+    }
+    if !["identifier", "field_identifier", "type_identifier", "namespace_identifier"].contains(&node.kind()) {
+        return Ok(None);
+    }
+    let parent = node.parent();
+    // Obj.Member / Obj->Member
+    if let Some(p) = parent.filter(|p| p.kind() == "field_expression" && p.child_by_field_name("field") == Some(node)) {
+        let Some(obj) = p.child_by_field_name("argument") else { return Ok(None) };
+        return Ok(resolve_expression_type(conn, obj, root, content, row)?.map(|class| SymbolRef::Member { class, name }));
+    }
+    // Scope::Member
+    if let Some(p) = parent.filter(|p| p.kind() == "qualified_identifier" && p.child_by_field_name("name") == Some(node)) {
+        let Some(scope) = p.child_by_field_name("scope") else { return Ok(None) };
+        let scope_text = get_node_text(&scope, content);
+        let owner = match super_depth(scope_text) {
+            Some(depth) => resolve_super_class(conn, &scope, content, depth)?,
+            None => Some(extract_clean_type(scope_text)),
+        };
+        return Ok(owner.map(|class| SymbolRef::Member { class, name }));
+    }
+    if node.kind() == "identifier" {
+        if let Some(type_name) = infer_variable_type(conn, &name, root, content, row)? {
+            return Ok(Some(SymbolRef::Local { name, type_name }));
+        }
+    }
+    if let Some(current_class) = get_enclosing_class_name(&node, content) {
+        if member_hover(conn, &current_class, &name)?.is_some() {
+            return Ok(Some(SymbolRef::Member { class: current_class, name }));
+        }
+    }
+    if is_known_type(conn, &name)? {
+        return Ok(Some(SymbolRef::Class(name)));
+    }
+    Ok(None)
+}
+
+/// カーソル位置のクラス/メンバーの宣言位置を LSP Location で返す。分からなければ null
+pub fn process_definition(conn: &Connection, content: &str, line: u32, character: u32) -> anyhow::Result<Value> {
+    completion_cache::sync(conn)?;
+    let tree = parse_content(content)?;
+    let root = tree.root_node();
+    let row = line as usize;
+    let point = Point::new(row, character as usize);
+    let node = match root.descendant_for_point_range(point, point) {
+        Some(n) => n,
+        None => return Ok(Value::Null),
+    };
+    let location = match symbol_at(conn, node, &root, content, row)? {
+        Some(SymbolRef::Class(cls)) => {
+            let resolved = resolve_typedef(conn, &cls)?;
+            let mut stmt = conn.prepare("SELECT f.path, c.line_number FROM classes c JOIN files f ON c.file_id = f.id WHERE c.name = ? ORDER BY (CASE WHEN f.path LIKE '%.h' OR f.path LIKE '%.hpp' THEN 0 ELSE 1 END), c.line_number LIMIT 1")?;
+            let mut rows = stmt.query([&resolved])?;
+            match rows.next()? {
+                Some(r) => Some((r.get::<_, String>(0)?, r.get::<_, Option<i64>>(1)?.unwrap_or(1))),
+                None => None,
+            }
+        }
+        Some(SymbolRef::Member { class, name }) => {
+            let resolved = resolve_typedef(conn, &class)?;
+            // 宣言 (ヘッダー) を実装 (.cpp) より優先する
+            let mut stmt = conn.prepare("SELECT f.path, m.line_number FROM members m JOIN classes c ON m.class_id = c.id JOIN files f ON c.file_id = f.id WHERE c.name = ? AND m.name = ? ORDER BY (CASE WHEN f.path LIKE '%.h' OR f.path LIKE '%.hpp' THEN 0 ELSE 1 END), m.line_number LIMIT 1")?;
+            let mut found = None;
+            for cls in class_ancestors(conn, &resolved)? {
+                let mut rows = stmt.query([&cls, &name])?;
+                if let Some(r) = rows.next()? {
+                    found = Some((r.get::<_, String>(0)?, r.get::<_, Option<i64>>(1)?.unwrap_or(1)));
+                    break;
+                }
+            }
+            found
+        }
+        // ローカル変数は編集中のバッファ内なのでクライアント側で扱う
+        Some(SymbolRef::Local { .. }) | None => None,
+    };
+    Ok(match location {
+        Some((path, line_number)) => location_json(&path, line_number),
+        None => Value::Null,
+    })
+}
+
+fn location_json(path: &str, line_number: i64) -> Value {
+    let path = percent_encode_path(&normalize_path(path));
+    let uri = if path.starts_with('/') { format!("file://{}", path) } else { format!("file:///{}", path) };
+    let pos = json!({ "line": (line_number - 1).max(0), "character": 0 });
+    json!({ "uri": uri, "range": { "start": pos, "end": pos } })
+}
+
+// "C:/My Game/#1.h" -> "C:/My%20Game/%231.h"。区切りの / とドライブレターの : は残す
+fn percent_encode_path(path: &str) -> String {
+    let mut encoded = String::with_capacity(path.len());
+    for b in path.bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~/:".contains(&b) {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", b));
+        }
+    }
+    encoded
+}
+
+const DEFAULT_WORKSPACE_SYMBOL_LIMIT: usize = 100;
+
+/// クラス・メンバー名のあいまい検索 (workspace/symbol)。LSP SymbolInformation の配列を返す。空のクエリは何も返さない
+pub fn process_workspace_symbols(conn: &Connection, query: &str, limit: Option<usize>) -> anyhow::Result<Value> {
+    let query = query.trim();
+    if query.is_empty() { return Ok(json!([])); }
+    let limit = limit.unwrap_or(DEFAULT_WORKSPACE_SYMBOL_LIMIT);
+    // "GetCtrl" -> "%G%e%t%C%t%r%l%" (大文字小文字は LIKE が無視する)。_ と % はエスケープ
+    let escape = |c: char| if c == '_' || c == '%' || c == '\\' { format!("\\{}", c) } else { c.to_string() };
+    let escaped: String = query.chars().map(escape).collect();
+    let fuzzy = format!("%{}%", query.chars().map(escape).collect::<Vec<_>>().join("%"));
+    let prefix = format!("{}%", escaped);
+    let contains = format!("%{}%", escaped);
+    // 前方一致 > 部分一致 > あいまい一致、同順位なら短い名前を先に
+    let order = "ORDER BY (CASE WHEN name LIKE ?2 ESCAPE '\\' THEN 0 WHEN name LIKE ?3 ESCAPE '\\' THEN 1 ELSE 2 END), length(name), name LIMIT ?4";
+
+    let mut symbols = Vec::new();
+    let mut class_stmt = conn.prepare(&format!(
+        "SELECT name, symbol_type, namespace, path, line_number FROM (
+            SELECT c.name AS name, c.symbol_type AS symbol_type, c.namespace AS namespace, f.path AS path, c.line_number AS line_number
+            FROM classes c JOIN files f ON c.file_id = f.id
+            WHERE c.name LIKE ?1 ESCAPE '\\' AND c.name NOT LIKE '(%'
+        ) {}", order))?;
+    let rows = class_stmt.query_map(params![fuzzy, prefix, contains, limit as i64], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?, row.get::<_, Option<String>>(2)?, row.get::<_, String>(3)?, row.get::<_, Option<i64>>(4)?))
+    })?;
+    for r in rows {
+        let (name, symbol_type, namespace, path, line_number) = r?;
+        let kind = match symbol_type.as_deref().unwrap_or("class") {
+            "struct" | "USTRUCT" => 23,
+            "enum" | "UENUM" => 10,
+            _ => 5,
+        };
+        let mut symbol = json!({ "name": name, "kind": kind, "location": location_json(&path, line_number.unwrap_or(1)) });
+        if let Some(ns) = namespace.filter(|ns| !ns.is_empty()) { symbol["containerName"] = json!(ns); }
+        symbols.push(symbol);
+    }
+
+    let remaining = limit.saturating_sub(symbols.len());
+    if remaining > 0 {
+        let mut member_stmt = conn.prepare(&format!(
+            "SELECT name, type, class_name, path, line_number FROM (
+                SELECT m.name AS name, m.type AS type, c.name AS class_name, f.path AS path, COALESCE(m.line_number, c.line_number) AS line_number
+                FROM members m JOIN classes c ON m.class_id = c.id JOIN files f ON c.file_id = f.id
+                WHERE m.name LIKE ?1 ESCAPE '\\'
+            ) {}", order))?;
+        let rows = member_stmt.query_map(params![fuzzy, prefix, contains, remaining as i64], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?, row.get::<_, String>(3)?, row.get::<_, Option<i64>>(4)?))
+        })?;
+        for r in rows {
+            let (name, mem_type, class_name, path, line_number) = r?;
+            let kind = match mem_type.as_str() { "function" => 6, "enum_item" => 22, _ => 8 };
+            symbols.push(json!({ "name": name, "kind": kind, "containerName": class_name, "location": location_json(&path, line_number.unwrap_or(1)) }));
+        }
+    }
+    Ok(json!(symbols))
+}
+
+// public: AController* APawn::GetController() const
+fn member_hover(conn: &Connection, class_name: &str, member_name: &str) -> anyhow::Result<Option<String>> {
+    let resolved = resolve_typedef(conn, class_name)?;
+    let mut stmt = conn.prepare("SELECT m.return_type, m.detail, m.access FROM members m JOIN classes c ON m.class_id = c.id WHERE c.name = ? AND m.name = ? LIMIT 1")?;
+    for cls in class_ancestors(conn, &resolved)? {
+        let mut rows = stmt.query([&cls, member_name])?;
         if let Some(row) = rows.next()? {
-            let class_id: i64 = row.get(0)?;
-            let mut mem_stmt = conn.prepare("SELECT name, type, return_type, access, is_static, detail FROM members WHERE class_id = ?")?;
-            let mem_rows = mem_stmt.query_map([class_id], |row| {
-                let m_name: String = row.get(0)?;
-                let m_type: String = row.get(1)?;
-                let r_type: Option<String> = row.get(2)?;
-                let detail: Option<String> = row.get(5)?;
-                Ok(json!({ "label": m_name, "kind": map_kind(&m_type), "detail": r_type.unwrap_or_default(), "documentation": detail.unwrap_or_default(), "insertText": m_name }))
-            })?;
-            for m in mem_rows { result.push(m?); }
-            let mut enum_stmt = conn.prepare("SELECT name FROM enum_values WHERE enum_id = ?")?;
-            let enum_rows = enum_stmt.query_map([class_id], |row| {
-                let e_name: String = row.get(0)?;
-                Ok(json!({ "label": e_name, "kind": 20, "detail": "enum item", "insertText": e_name }))
-            })?;
-            for e in enum_rows { result.push(e?); }
-            let mut parent_stmt = conn.prepare("SELECT parent_name FROM inheritance WHERE child_id = ?")?;
-            let p_rows = parent_stmt.query_map([class_id], |row| Ok(row.get::<_, String>(0)?))?;
-            for p in p_rows { queue.push(p?); }
+            let return_type: Option<String> = row.get(0)?;
+            let detail: Option<String> = row.get(1)?;
+            let access: Option<String> = row.get(2)?;
+            let mut text = String::new();
+            if let Some(a) = access.filter(|a| !a.is_empty()) { text.push_str(&format!("{}: ", a)); }
+            if let Some(rt) = return_type.filter(|rt| !rt.is_empty()) { text.push_str(&format!("{} ", rt)); }
+            text.push_str(&format!("{}::{}{}", cls, member_name, detail.unwrap_or_default()));
+            return Ok(Some(text));
         }
     }
-    Ok(result)
+    Ok(None)
 }
 
-fn map_kind(k: &str) -> i64 {
-    match k { "function" => 2, "variable" | "property" => 5, "enum_item" => 20, _ => 1 }
+/// バッファの namespace / class / struct / enum / 関数を LSP DocumentSymbol の木で返す (textDocument/documentSymbol)
+/// 構文木だけから作るので DB は引かない。ネストしたクラスやメソッドは children に入る
+pub fn process_document_symbols(_conn: &Connection, content: &str, _file_path: Option<&str>) -> anyhow::Result<Value> {
+    let tree = parse_content(content)?;
+    Ok(json!(document_symbols(tree.root_node(), content, None)))
 }
 
-fn is_known_type(conn: &Connection, name: &str) -> anyhow::Result<bool> {
-    let clean = extract_clean_type(name);
-    if clean.is_empty() { return Ok(false); }
-    let mut stmt = conn.prepare("SELECT 1 FROM classes WHERE LOWER(name) = LOWER(?) LIMIT 1")?;
-    Ok(stmt.exists([&clean])?)
+fn document_symbols(node: Node, content: &str, class_name: Option<&str>) -> Vec<Value> {
+    let mut symbols = Vec::new();
+    let mut walker = node.walk();
+    for child in node.named_children(&mut walker) {
+        match document_symbol(child, content, class_name) {
+            Some(symbol) => symbols.push(symbol),
+            // template<...> や #if WITH_EDITOR などの中も探す。関数の本体には入らない
+            None if child.kind() != "compound_statement" => symbols.extend(document_symbols(child, content, class_name)),
+            None => {}
+        }
+    }
+    symbols
 }
 
-fn infer_variable_type(target_name: &str, root: &Node, content: &str, cursor_row: usize) -> anyhow::Result<Option<String>> {
-    let language: tree_sitter::Language = tree_sitter_unreal_cpp::LANGUAGE.into();
-    let query_str = "
-      (declaration type: (_) @type declarator: (_) @decl)
-      (parameter_declaration type: (_) @type declarator: (_) @decl)
-      (for_range_loop type: (_) @type declarator: (_) @decl)
-      (condition_clause (declaration type: (_) @type declarator: (_) @decl))
-    ";
-    let query = Query::new(&language, query_str)?;
-    let mut cursor = QueryCursor::new();
-    let mut matches = cursor.matches(&query, *root, content.as_bytes());
-    let mut best_type = None;
-    let mut best_row = 0;
-    while let Some(m) = matches.next() {
-        let mut type_node = None;
-        let mut decl_nodes = Vec::new();
-        for cap in m.captures {
-            let c_name = query.capture_names()[cap.index as usize];
-            if c_name == "type" { type_node = Some(cap.node); }
-            else if c_name == "decl" { decl_nodes.push(cap.node); }
+// class_name はクラス本体の中なら囲んでいるクラスの名前
+fn document_symbol(node: Node, content: &str, class_name: Option<&str>) -> Option<Value> {
+    match node.kind() {
+        "class_specifier" | "unreal_class_declaration" => type_symbol(node, content, 5),
+        "struct_specifier" | "unreal_struct_declaration" => type_symbol(node, content, 23),
+        "enum_specifier" | "unreal_enum_declaration" => {
+            let name = node.child_by_field_name("name").map(|n| get_node_text(&n, content).trim().to_string())?;
+            let body = node.child_by_field_name("body").or_else(|| find_descendant_by_kind(node, "enumerator_list"))?;
+            let mut walker = body.walk();
+            let values: Vec<Value> = body.named_children(&mut walker)
+                .filter(|c| c.kind() == "enumerator")
+                .filter_map(|c| {
+                    let value_name = c.child_by_field_name("name")?;
+                    Some(symbol_json(get_node_text(&value_name, content).trim(), 22, None, &c, &value_name, Vec::new()))
+                })
+                .collect();
+            let selection = node.child_by_field_name("name").unwrap_or(node);
+            Some(symbol_json(&name, 10, None, &node, &selection, values))
         }
-        if let Some(t_node) = type_node {
-            for d_node in decl_nodes {
-                if find_identifier_in_decl(&d_node, target_name, content)? {
-                    let row = d_node.start_position().row;
-                    if row <= cursor_row && (best_type.is_none() || row >= best_row) {
-                        let type_text = get_node_text(&t_node, content).trim();
-                        if type_text == "auto" {
-                            if let Some(inferred) = infer_from_assignment(target_name, root, content, cursor_row)? {
-                                best_type = Some(inferred);
+        "namespace_definition" => {
+            let name_node = node.child_by_field_name("name");
+            let name = name_node.map_or_else(|| "(anonymous namespace)".to_string(), |n| get_node_text(&n, content).trim().to_string());
+            let children = node.child_by_field_name("body").map(|b| document_symbols(b, content, None)).unwrap_or_default();
+            Some(symbol_json(&name, 3, None, &node, &name_node.unwrap_or(node), children))
+        }
+        "function_definition" => function_symbol(node, content, class_name),
+        // クラス本体の struct FInner { ... }; は型が struct_specifier の field_declaration になる
+        "field_declaration" | "declaration" | "unreal_function_declaration" if class_name.is_some() => {
+            if let Some(nested) = node.child_by_field_name("type").and_then(|t| document_symbol(t, content, class_name)) {
+                return Some(nested);
+            }
+            if let Some(method) = function_symbol(node, content, class_name) {
+                return Some(method);
+            }
+            let declarator = node.child_by_field_name("declarator")?;
+            let name_node = find_descendant_by_kind(declarator, "field_identifier").or_else(|| find_descendant_by_kind(declarator, "identifier"))?;
+            let detail = node.child_by_field_name("type").map(|t| get_node_text(&t, content).trim().to_string());
+            Some(symbol_json(get_node_text(&name_node, content).trim(), 8, detail, &node, &name_node, Vec::new()))
+        }
+        _ => None,
+    }
+}
+
+// 前方宣言 (class UFoo;) は本体が無いので出さない
+fn type_symbol(node: Node, content: &str, kind: i64) -> Option<Value> {
+    let body = node.child_by_field_name("body").or_else(|| find_descendant_by_kind(node, "field_declaration_list"))?;
+    let name = class_declaration_name(&node, content)?;
+    let selection = node.child_by_field_name("name").unwrap_or(node);
+    let children = document_symbols(body, content, Some(&name));
+    Some(symbol_json(&name, kind, None, &node, &selection, children))
+}
+
+// AFoo::BeginPlay() のような外側の定義もメソッドとして扱う。引数リストを detail にする
+fn function_symbol(node: Node, content: &str, class_name: Option<&str>) -> Option<Value> {
+    let mut declarator = node.child_by_field_name("declarator")?;
+    while declarator.kind() != "function_declarator" {
+        declarator = declarator.child_by_field_name("declarator").or_else(|| declarator.named_child(0))?;
+    }
+    let name_node = declarator.child_by_field_name("declarator")?;
+    let name: String = get_node_text(&name_node, content).split_whitespace().collect::<Vec<_>>().join(" ");
+    let leaf = name.rsplit("::").next().unwrap_or(&name);
+    let owner = name.rsplit_once("::").map(|(scope, _)| scope.rsplit("::").next().unwrap_or(scope)).or(class_name);
+    let kind = if Some(leaf) == owner || leaf.starts_with('~') {
+        9
+    } else if is_operator_name(leaf) {
+        25
+    } else if owner.is_some() {
+        6
+    } else {
+        12
+    };
+    let detail = declarator.child_by_field_name("parameters").map(|p| get_node_text(&p, content).split_whitespace().collect::<Vec<_>>().join(" "));
+    Some(symbol_json(&name, kind, detail, &node, &name_node, Vec::new()))
+}
+
+fn symbol_json(name: &str, kind: i64, detail: Option<String>, node: &Node, selection: &Node, children: Vec<Value>) -> Value {
+    let mut symbol = json!({
+        "name": name,
+        "kind": kind,
+        "range": node_range_json(node),
+        "selectionRange": node_range_json(selection),
+    });
+    if let Some(detail) = detail.filter(|d| !d.is_empty()) { symbol["detail"] = json!(detail); }
+    if !children.is_empty() { symbol["children"] = json!(children); }
+    symbol
+}
+
+fn node_range_json(node: &Node) -> Value {
+    let start = node.start_position();
+    let end = node.end_position();
+    json!({
+        "start": { "line": start.row, "character": start.column },
+        "end": { "line": end.row, "character": end.column }
+    })
+}
+
+/// 呼び出しの括弧内で LSP SignatureHelp (オーバーロードごとの引数と activeParameter) を返す。呼び出し外なら null
+pub fn process_signature_help(conn: &Connection, content: &str, line: u32, character: u32) -> anyhow::Result<Value> {
+    completion_cache::sync(conn)?;
+    let tree = parse_content(content)?;
+    let root = tree.root_node();
+    let row = line as usize;
+    let point = Point::new(row, character as usize);
+    let prev_point = Point::new(row, (character as usize).saturating_sub(1));
+    let mut curr_opt = root.descendant_for_point_range(prev_point, point);
+    let args_node = loop {
+        match curr_opt {
+            Some(n) if n.kind() == "argument_list" && n.parent().map_or(false, |p| p.kind() == "call_expression") => break n,
+            Some(n) => curr_opt = n.parent(),
+            None => return Ok(Value::Null),
+        }
+    };
+    let cursor_byte = byte_offset(content, row, character as usize);
+    // 閉じ括弧より後ろは呼び出しの外
+    if cursor_byte <= args_node.start_byte() || (cursor_byte >= args_node.end_byte() && get_node_text(&args_node, content).ends_with(')')) {
+        return Ok(Value::Null);
+    }
+    let Some(call_node) = args_node.parent() else { return Ok(Value::Null) };
+    let Some(func_node) = call_node.child_by_field_name("function") else { return Ok(Value::Null) };
+    let Some((owner, name)) = resolve_callee(conn, func_node, &root, content, row)? else { return Ok(Value::Null) };
+
+    let resolved = resolve_typedef(conn, &owner)?;
+    let mut stmt = conn.prepare("SELECT m.detail FROM members m JOIN classes c ON m.class_id = c.id WHERE c.name = ? AND m.name = ? AND m.type = 'function'")?;
+    let mut signatures = Vec::new();
+    for cls in class_ancestors(conn, &resolved)? {
+        let rows = stmt.query_map([&cls, &name], |r| r.get::<_, Option<String>>(0))?;
+        for detail in rows {
+            let detail = detail?.unwrap_or_else(|| "()".to_string());
+            let params: Vec<Value> = split_parameters(&detail).into_iter().map(|p| json!({ "label": p })).collect();
+            signatures.push(json!({ "label": format!("{}{}", name, detail), "parameters": params }));
+        }
+        // オーバーライドは最も派生したクラスの宣言だけを使う
+        if !signatures.is_empty() { break; }
+    }
+    if signatures.is_empty() { return Ok(Value::Null); }
+
+    // カーソルより前のトップレベルのカンマ数
+    let typed = content.get(args_node.start_byte() + 1..cursor_byte).unwrap_or("");
+    let active_parameter = count_top_level_commas(typed);
+    Ok(json!({ "signatures": signatures, "activeSignature": 0, "activeParameter": active_parameter }))
+}
+
+// 呼び出し先の (クラス, 関数名)
+fn resolve_callee(conn: &Connection, func_node: Node, root: &Node, content: &str, row: usize) -> anyhow::Result<Option<(String, String)>> {
+    match func_node.kind() {
+        "field_expression" => {
+            let (Some(obj), Some(field)) = (func_node.child_by_field_name("argument"), func_node.child_by_field_name("field")) else { return Ok(None) };
+            let name = match split_template_call(field, content) {
+                Some((n, _)) => n,
+                None => get_node_text(&field, content).trim().to_string(),
+            };
+            Ok(resolve_expression_type(conn, obj, root, content, row)?.map(|t| (t, name)))
+        }
+        "qualified_identifier" => {
+            let Some((scope, name)) = split_qualified_identifier(func_node, content) else { return Ok(None) };
+            let full_scope = get_node_text(&func_node, content).trim().rsplit_once("::").map_or("", |(s, _)| s);
+            match super_depth(full_scope) {
+                Some(depth) => Ok(resolve_super_class(conn, &func_node, content, depth)?.map(|cls| (cls, name))),
+                None => Ok(Some((scope, name))),
+            }
+        }
+        "identifier" | "template_function" => {
+            let name = match split_template_call(func_node, content) {
+                Some((n, _)) => n,
+                None => get_node_text(&func_node, content).trim().to_string(),
+            };
+            Ok(get_enclosing_class_name(&func_node, content).map(|cls| (cls, name)))
+        }
+        _ => Ok(None),
+    }
+}
+
+fn byte_offset(content: &str, row: usize, col: usize) -> usize {
+    let line_start: usize = content.split('\n').take(row).map(|l| l.len() + 1).sum();
+    let line_len = content.split('\n').nth(row).map_or(0, |l| l.len());
+    (line_start + col.min(line_len)).min(content.len())
+}
+
+// < > は比較演算子や -> と区別できないので数えず、括弧と文字列/文字リテラルだけをたどる
+fn count_top_level_commas(text: &str) -> usize {
+    let mut depth = 0i32;
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    let mut count = 0;
+    for ch in text.chars() {
+        if let Some(q) = quote {
+            if escaped { escaped = false; } else if ch == '\\' { escaped = true; } else if ch == q { quote = None; }
+            continue;
+        }
+        match ch {
+            '"' | '\'' => quote = Some(ch),
+            '(' | '{' | '[' => depth += 1,
+            ')' | '}' | ']' => depth -= 1,
+            ',' if depth == 0 => count += 1,
+            _ => {}
+        }
+    }
+    count
+}
+
+// "(int32 A, const FVector& B = FVector::ZeroVector)" -> ["int32 A", "const FVector& B = FVector::ZeroVector"]
+// "(int X, float Y = 1.f)" -> "Foo(${1:int X}, ${2:float Y})" / 引数なしは "Foo()"
+fn function_snippet(name: &str, detail: &str) -> String {
+    let placeholders: Vec<String> = split_parameters(detail).iter().enumerate().map(|(i, param)| {
+        let param = param.split_once('=').map_or(param.as_str(), |(p, _)| p).trim();
+        let escaped = param.replace('\\', "\\\\").replace('$', "\\$").replace('}', "\\}");
+        format!("${{{}:{}}}", i + 1, escaped)
+    }).collect();
+    format!("{}({})", name, placeholders.join(", "))
+}
+
+/// デフォルト引数と空白の差を無視した引数リストの比較キー (基底と派生のオーバーライド判定用)
+fn parameter_signature(detail: &str) -> String {
+    split_parameters(detail).iter()
+        .map(|param| param.split_once('=').map_or(param.as_str(), |(p, _)| p).split_whitespace().collect::<Vec<_>>().join(" "))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn split_parameters(detail: &str) -> Vec<String> {
+    let inner = detail.trim().trim_start_matches('(').trim_end_matches(')').trim();
+    if inner.is_empty() || inner == "void" { return Vec::new(); }
+    let mut params = Vec::new();
+    let mut depth = 0i32;
+    let mut current = String::new();
+    for ch in inner.chars() {
+        match ch {
+            '(' | '<' | '{' | '[' => { depth += 1; current.push(ch); }
+            ')' | '>' | '}' | ']' => { depth -= 1; current.push(ch); }
+            ',' if depth == 0 => { params.push(current.trim().to_string()); current.clear(); }
+            _ => current.push(ch),
+        }
+    }
+    params.push(current.trim().to_string());
+    params
+}
+
+/// カーソル位置のレシーバー型の解決過程 (ノード種別, 途中の型, typedef 解決, DB の検索結果) を返す
+pub fn explain_resolution(conn: &Connection, content: &str, line: u32, character: u32) -> anyhow::Result<Value> {
+    RESOLUTION_TRACE.with(|t| *t.borrow_mut() = Some(Vec::new()));
+    let result = resolve_type_at(conn, content, line, character);
+    let steps = RESOLUTION_TRACE.with(|t| t.borrow_mut().take()).unwrap_or_default();
+    Ok(json!({ "receiverType": result?, "steps": steps }))
+}
+
+thread_local! {
+    // explain_resolution の間だけ解決ステップを記録する
+    static RESOLUTION_TRACE: RefCell<Option<Vec<Value>>> = RefCell::new(None);
+}
+
+fn trace_step(step: impl FnOnce() -> Value) {
+    RESOLUTION_TRACE.with(|t| {
+        if let Some(steps) = t.borrow_mut().as_mut() {
+            steps.push(step());
+        }
+    });
+}
+
+fn resolve_target_type(
+    conn: &Connection,
+    target: CompletionTarget,
+    root: &Node,
+    content: &str,
+    cursor_row: usize,
+) -> anyhow::Result<Option<String>> {
+    trace_step(|| match &target {
+        CompletionTarget::Expression(n) => json!({ "step": "target", "target": "expression", "nodeKind": n.kind(), "text": get_node_text(n, content) }),
+        CompletionTarget::Scope(n) => json!({ "step": "target", "target": "scope", "nodeKind": n.kind(), "text": get_node_text(n, content) }),
+        CompletionTarget::ImplicitThis(cls) => json!({ "step": "target", "target": "implicitThis", "type": cls }),
+    });
+    match target {
+        CompletionTarget::Expression(obj_node) => {
+            match resolve_expression_type(conn, obj_node, root, content, cursor_row)? {
+                // テンプレート引数 T は既定型/制約型が分かる場合のみ補完する
+                Some(t_name) => match substitute_template_param(&t_name, &obj_node, content) {
+                    Some(concrete) => Ok(Some(resolve_typedef(conn, &concrete)?)),
+                    None => {
+                        tracing::debug!("Receiver type '{}' is an unconstrained template parameter", t_name);
+                        Ok(None)
+                    }
+                },
+                None => Ok(None),
+            }
+        }
+        CompletionTarget::Scope(scope_node) => {
+            let scope = full_scope_text(scope_node, content);
+            if let Some(depth) = super_depth(&scope) {
+                return resolve_super_class(conn, &scope_node, content, depth);
+            }
+            // Foo::Bar:: / A::B::C:: は名前空間まで一致する型を優先する
+            if let Some((prefix, leaf)) = scope.rsplit_once("::") {
+                if let Some(qualified) = resolve_scope_chain(conn, prefix, leaf)? {
+                    let resolved = resolve_typedef(conn, leaf)?;
+                    return Ok(Some(if resolved == leaf.trim() { qualified } else { resolved }));
+                }
+            }
+            Ok(Some(resolve_typedef(conn, &extract_clean_type(&scope))?))
+        }
+        CompletionTarget::ImplicitThis(current_class) => Ok(Some(current_class)),
+    }
+}
+
+/// 囲んでいる template<...> の型引数名なら、既定型 (typename T = X) か
+/// 制約 (TIsDerivedFrom<T, X> など) の型を返す。どちらも無ければ None。型引数でなければそのまま返す
+fn substitute_template_param(type_name: &str, node: &Node, content: &str) -> Option<String> {
+    let mut curr_opt = node.parent();
+    while let Some(curr) = curr_opt {
+        if curr.kind() == "template_declaration" {
+            if let Some(params) = curr.child_by_field_name("parameters") {
+                let mut walker = params.walk();
+                for param in params.named_children(&mut walker) {
+                    match param.kind() {
+                        "optional_type_parameter_declaration" => {
+                            let name = param.child_by_field_name("name").map(|n| get_node_text(&n, content).trim());
+                            if name == Some(type_name) {
+                                return param.child_by_field_name("default_type").map(|d| extract_clean_type(get_node_text(&d, content)));
                             }
-                        } else {
-                            best_type = Some(extract_clean_type(type_text));
                         }
-                        best_row = row;
+                        "type_parameter_declaration" | "variadic_type_parameter_declaration" => {
+                            let name = find_descendant_by_kind(param, "type_identifier").map(|n| get_node_text(&n, content).trim());
+                            if name == Some(type_name) {
+                                let mut constraint_text = get_node_text(&params, content).to_string();
+                                let mut c_walker = curr.walk();
+                                for child in curr.named_children(&mut c_walker) {
+                                    if child.kind() == "requires_clause" {
+                                        constraint_text.push_str(get_node_text(&child, content));
+                                    }
+                                }
+                                return template_constraint_type(&constraint_text, type_name);
+                            }
+                        }
+                        _ => {}
                     }
                 }
             }
         }
+        curr_opt = curr.parent();
     }
-    if best_type.is_none() {
-        best_type = infer_from_assignment(target_name, root, content, cursor_row)?;
-    }
-    Ok(best_type)
+    Some(type_name.to_string())
 }
 
-fn find_identifier_in_decl(node: &Node, target_name: &str, content: &str) -> anyhow::Result<bool> {
-    let kind = node.kind();
-    if kind == "identifier" || kind == "field_identifier" {
-        return Ok(get_node_text(node, content).trim() == target_name);
+fn template_constraint_type(text: &str, param: &str) -> Option<String> {
+    let derived_re = cached_regex!(r"\b(?:TIsDerivedFrom|derived_from|TPointerIsConvertibleFromTo)\s*<\s*([A-Za-z0-9_]+)\s*\*?\s*,\s*([A-Za-z0-9_:]+)");
+    if let Some(cap) = derived_re.captures_iter(text).find(|cap| &cap[1] == param) {
+        return Some(extract_clean_type(&cap[2]));
     }
-    for i in 0..node.child_count() {
-        if let Some(child) = node.child(i as u32) {
-            if find_identifier_in_decl(&child, target_name, content)? { return Ok(true); }
-        }
+    let base_re = cached_regex!(r"\bis_base_of(?:_v)?\s*<\s*([A-Za-z0-9_:]+)\s*,\s*([A-Za-z0-9_]+)\s*>");
+    let cap = base_re.captures_iter(text).find(|cap| &cap[2] == param)?;
+    Some(extract_clean_type(&cap[1]))
+}
+
+// タイムアウト時や itemDefaults がある場合は LSP の CompletionList 形式で返す
+fn completion_response(list: CompletionList) -> Value {
+    if list.is_incomplete || list.item_defaults.is_some() {
+        json!(list)
+    } else {
+        json!(list.items)
     }
-    Ok(false)
 }
 
-fn infer_from_assignment(target_name: &str, root: &Node, content: &str, cursor_row: usize) -> anyhow::Result<Option<String>> {
-    let language: tree_sitter::Language = tree_sitter_unreal_cpp::LANGUAGE.into();
-    let query_str = "
-      (declaration declarator: (init_declarator declarator: (_) @decl value: (_) @value))
-      (assignment_expression left: (_) @decl right: (_) @value)
-    ";
-    let query = Query::new(&language, query_str)?;
+fn is_past(deadline: Option<Instant>) -> bool {
+    deadline.map_or(false, |d| Instant::now() >= d)
+}
+
+/// メンバーアクセス (Obj.Member / Obj->Member) のうち、レシーバー型とその全祖先が
+/// インデックス済みなのにメンバーが見つからないものを LSP Diagnostic (warning) として返す
+pub fn process_diagnostics(conn: &Connection, content: &str, file_path: Option<&str>) -> anyhow::Result<Value> {
+    completion_cache::sync(conn)?;
+    let tree = parse_content(content)?;
+    let root = tree.root_node();
+    let query = cached_query!("(field_expression argument: (_) @obj field: (_) @field)");
     let mut cursor = QueryCursor::new();
-    let mut matches = cursor.matches(&query, *root, content.as_bytes());
+    let mut matches = cursor.matches(&query, root, content.as_bytes());
+    let mut diagnostics = Vec::new();
     while let Some(m) = matches.next() {
-        let mut decl_node = None;
-        let mut value_node = None;
+        let mut obj_node = None;
+        let mut field_node = None;
         for cap in m.captures {
             let c_name = query.capture_names()[cap.index as usize];
-            if c_name == "decl" { decl_node = Some(cap.node); }
-            else if c_name == "value" { value_node = Some(cap.node); }
+            if c_name == "obj" { obj_node = Some(cap.node); }
+            else if c_name == "field" { field_node = Some(cap.node); }
         }
-        if let (Some(d_node), Some(v_node)) = (decl_node, value_node) {
-            if find_identifier_in_decl(&d_node, target_name, content)? {
-                let row = d_node.start_position().row;
-                if row <= cursor_row { 
-                    let v_text = get_node_text(&v_node, content);
-                    return infer_from_value_text(v_text);
-                }
+        let (obj_node, field_node) = match (obj_node, field_node) {
+            (Some(o), Some(f)) => (o, f),
+            _ => continue,
+        };
+        let name_node = if field_node.kind() == "template_method" {
+            match field_node.child_by_field_name("name") { Some(n) => n, None => continue }
+        } else {
+            field_node
+        };
+        if name_node.kind() != "field_identifier" { continue; }
+        let member = get_node_text(&name_node, content).trim();
+        if member.is_empty() || IMPLICIT_MEMBERS.contains(&member) { continue; }
+
+        let row = obj_node.start_position().row;
+        // WeakPtr.Get() などラッパー自身のメンバーは中身の型に無いので対象外
+        let op = field_node.prev_sibling().map(|o| get_node_text(&o, content).trim()).unwrap_or("");
+        if op == "." {
+            if let Some(raw) = resolve_raw_expression_type(conn, obj_node, &root, content, row)? {
+                if split_template_args(&raw).map_or(false, |(w, _)| UNWRAPPED_TEMPLATES.contains(&w.as_str())) { continue; }
             }
         }
+        let receiver = match resolve_expression_type(conn, obj_node, &root, content, row)? {
+            Some(t) => resolve_typedef(conn, &t)?,
+            None => continue,
+        };
+        if member_exists(conn, &receiver, member)? != Some(false) { continue; }
+        if let Some(path) = file_path {
+            if defined_in_edited_file(conn, &receiver, path)? { continue; }
+        }
+
+        let start = name_node.start_position();
+        let end = name_node.end_position();
+        diagnostics.push(json!({
+            "range": {
+                "start": { "line": start.row, "character": start.column },
+                "end": { "line": end.row, "character": end.column }
+            },
+            "severity": 2,
+            "source": "UNL",
+            "message": format!("'{}' is not a member of '{}'", member, receiver)
+        }));
     }
-    Ok(None)
+    Ok(json!(diagnostics))
 }
 
-fn infer_from_value_text(text: &str) -> anyhow::Result<Option<String>> {
-    let text = text.trim();
-    if let Ok(re) = regex::Regex::new(r"CreateDefaultSubobject\s*<\s*([a-zA-Z0-9_:]+)") {
-        if let Some(cap) = re.captures(text) { 
-            return Ok(Some(extract_clean_type(cap.get(1).unwrap().as_str())));
-        }
+// 編集中のファイルか同名のヘッダー/ソースで定義されたクラスか (追加したばかりのメンバーはまだインデックスに無い)
+fn defined_in_edited_file(conn: &Connection, class_name: &str, file_path: &str) -> anyhow::Result<bool> {
+    let stem = |p: &str| {
+        let name = normalize_path(p).rsplit('/').next().unwrap_or("").to_string();
+        name.rsplit_once('.').map_or(name.clone(), |(s, _)| s.to_string())
+    };
+    let current = stem(file_path);
+    let mut stmt = conn.prepare("SELECT f.path FROM classes c JOIN files f ON c.file_id = f.id WHERE c.name = ?")?;
+    let paths = stmt.query_map([class_name], |row| row.get::<_, String>(0))?;
+    for path in paths {
+        if stem(&path?).eq_ignore_ascii_case(&current) { return Ok(true); }
     }
-    if let Ok(re) = regex::Regex::new(r"([a-zA-Z0-9_]+)\s*<\s*([a-zA-Z0-9_:]+)") {
-        if let Some(cap) = re.captures(text) { 
-            let func = cap.get(1).unwrap().as_str();
-            let inner = cap.get(2).unwrap().as_str();
-            if ["NewObject", "TObjectPtr", "TSharedPtr"].contains(&func) {
-                return Ok(Some(extract_clean_type(inner)));
-            }
-            return Ok(Some(extract_clean_type(func)));
+    Ok(false)
+}
+
+/// サブクラスでオーバーライド可能な仮想関数 (自身と祖先の virtual/override のうち final でないもの) を返す。
+/// 同名の関数は最も派生側の宣言を採用する
+pub fn virtual_members(conn: &Connection, class_name: &str) -> anyhow::Result<Value> {
+    completion_cache::sync(conn)?;
+    let resolved = resolve_typedef(conn, class_name)?;
+    let mut result = Vec::new();
+    // 名前 + 引数で判定するので、オーバーロードは別々にオーバーライド/final される
+    let mut seen: HashMap<(String, String), bool> = HashMap::new();
+    let mut stmt = conn.prepare("
+        SELECT m.name, m.return_type, m.detail, m.flags, m.access FROM members m JOIN classes c ON m.class_id = c.id
+        WHERE c.name = ? AND m.type = 'function' ORDER BY m.line_number
+    ")?;
+    for cls in class_ancestors(conn, &resolved)? {
+        let rows = stmt.query_map([&cls], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, Option<String>>(1)?,
+                row.get::<_, Option<String>>(2)?,
+                row.get::<_, Option<String>>(3)?,
+                row.get::<_, Option<String>>(4)?,
+            ))
+        })?;
+        for r in rows {
+            let (name, return_type, params, flags, access) = r?;
+            let params = params.unwrap_or_else(|| "()".to_string());
+            let key = (name.clone(), parameter_signature(&params));
+            if name.starts_with('~') || seen.contains_key(&key) { continue; }
+            let flags = flags.unwrap_or_default();
+            let has_flag = |f: &str| flags.split_whitespace().any(|w| w == f);
+            if !has_flag("virtual") && !has_flag("override") { continue; }
+            seen.insert(key, true);
+            // final で封じられたものは、より基底側の宣言があっても候補にしない
+            if has_flag("final") { continue; }
+            let return_type = return_type.unwrap_or_default();
+            result.push(json!({
+                "name": name,
+                "class": cls,
+                "return_type": return_type,
+                "params": params,
+                "access": access.unwrap_or_default(),
+                "signature": format!("{} {}{}", return_type, name, params).trim().to_string()
+            }));
         }
     }
-    if let Ok(re) = regex::Regex::new(r"^([a-zA-Z0-9_:]+)\s*\(") {
-        if let Some(cap) = re.captures(text) { return Ok(Some(extract_clean_type(cap.get(1).unwrap().as_str()))); }
+    Ok(json!(result))
+}
+
+// GENERATED_BODY 等のマクロが生成するため、インデックスに現れないメンバー
+const IMPLICIT_MEMBERS: &[&str] = &["StaticClass", "StaticStruct", "GetPrivateStaticClass", "Super", "ThisClass"];
+
+/// Some(true): 見つかった / Some(false): 型と全祖先がインデックス済みで見つからない / None: 判定不能
+fn member_exists(conn: &Connection, class_name: &str, member_name: &str) -> anyhow::Result<Option<bool>> {
+    for cls in class_ancestors(conn, class_name)? {
+        let mut class_stmt = conn.prepare_cached("SELECT 1 FROM classes WHERE name = ? LIMIT 1")?;
+        if !class_stmt.exists([&cls])? { return Ok(None); }
+
+        let mut stmt = conn.prepare_cached("
+            SELECT 1 FROM members m JOIN classes c ON m.class_id = c.id WHERE c.name = ? AND m.name = ?
+            UNION ALL
+            SELECT 1 FROM enum_values e JOIN classes c ON e.enum_id = c.id WHERE c.name = ? AND e.name = ?
+            LIMIT 1
+        ")?;
+        if stmt.exists([&cls, member_name, &cls, member_name])? { return Ok(Some(true)); }
     }
-    Ok(None)
+    Ok(Some(false))
 }
 
-fn extract_clean_type(raw: &str) -> String {
-    let mut clean = raw.trim().to_string();
-    if let Some(start) = clean.find('<') {
-        if let Some(end) = clean.rfind('>') {
-            let wrapper = clean[..start].trim();
-            let inner = &clean[start+1..end];
-            if ["TObjectPtr", "TSharedPtr", "TUniquePtr", "TWeakObjectPtr", "TSubclassOf", "TSoftObjectPtr", "TSoftClassPtr", "TEnumAsByte"].contains(&wrapper) {
-                return extract_clean_type(inner);
-            }
-            clean = wrapper.to_string();
-        }
+// 直近にパースしたバッファの木 (content のハッシュ, content, 木)。先頭ほど新しい
+// ハッシュは比較を速くするためだけに使い、一致したら内容そのものを比べる
+struct TreeCache {
+    entries: Vec<(u64, String, tree_sitter::Tree)>,
+}
+
+impl TreeCache {
+    const fn new() -> Self {
+        TreeCache { entries: Vec::new() }
     }
-    let keywords = ["const", "typename", "struct", "class", "enum", "virtual", "static", "inline", "FORCEINLINE"];
-    for kw in keywords {
-        if let Ok(re) = regex::Regex::new(&format!(r"\b{}\b", kw)) {
-            clean = re.replace_all(&clean, "").to_string();
-        }
+
+    fn key(content: &str) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        content.hash(&mut hasher);
+        hasher.finish()
     }
-    if let Ok(re) = regex::Regex::new(r"\b[A-Z0-9_]+_API\b") {
-        clean = re.replace_all(&clean, "").to_string();
+
+    fn get(&mut self, content: &str) -> Option<tree_sitter::Tree> {
+        let key = Self::key(content);
+        let pos = self.entries.iter().position(|(h, text, _)| *h == key && text == content)?;
+        let entry = self.entries.remove(pos);
+        let tree = entry.2.clone();
+        self.entries.insert(0, entry);
+        Some(tree)
     }
+
+    fn insert(&mut self, content: &str, tree: tree_sitter::Tree, capacity: usize) {
+        if capacity == 0 { return; }
+        self.entries.insert(0, (Self::key(content), content.to_string(), tree));
+        self.entries.truncate(capacity);
+    }
+}
+
+static TREE_CACHE: Mutex<TreeCache> = Mutex::new(TreeCache::new());
+const DEFAULT_TREE_CACHE_SIZE: usize = 4;
+
+fn parse_content(content: &str) -> anyhow::Result<tree_sitter::Tree> {
+    let capacity = active_config(|c| c.tree_cache_size).unwrap_or(DEFAULT_TREE_CACHE_SIZE);
+    if capacity == 0 {
+        return parse_uncached(content);
+    }
+    if let Some(tree) = TREE_CACHE.lock().ok().and_then(|mut cache| cache.get(content)) {
+        return Ok(tree);
+    }
+    let tree = parse_uncached(content)?;
+    if let Ok(mut cache) = TREE_CACHE.lock() {
+        cache.insert(content, tree.clone(), capacity);
+    }
+    Ok(tree)
+}
+
+thread_local! {
+    // 言語の設定済みのパーサーをスレッドごとに使い回す
+    static PARSER: RefCell<Option<Parser>> = const { RefCell::new(None) };
+}
+
+fn parse_uncached(content: &str) -> anyhow::Result<tree_sitter::Tree> {
+    PARSER.with(|cell| {
+        let mut slot = cell.borrow_mut();
+        if slot.is_none() {
+            let mut parser = Parser::new();
+            let language: tree_sitter::Language = tree_sitter_unreal_cpp::LANGUAGE.into();
+            parser.set_language(&language)?;
+            *slot = Some(parser);
+        }
+        let parser = slot.as_mut().expect("parser initialized above");
+        parser.parse(content, None).ok_or_else(|| anyhow::anyhow!("Failed to parse content"))
+    })
+}
+
+enum CompletionTarget<'t> {
+    /// `Obj.` / `Obj->` のレシーバー式
+    Expression(Node<'t>),
+    /// `Scope::` のスコープ
+    Scope(Node<'t>),
+    /// スタンドアロンの識別子入力時の囲みクラス
+    ImplicitThis(String),
+}
+
+fn locate_completion_target<'t>(root: Node<'t>, content: &str, row: usize, col: usize) -> Option<CompletionTarget<'t>> {
+    // カーソル位置とその直前を含むノードを探す (0.26.5 API)
+    let point = Point::new(row, col);
+    let prev_point = Point::new(row, if col > 0 { col - 1 } else { 0 });
+
+    let node = root.descendant_for_point_range(prev_point, point)?;
+
+    let node_type = node.kind();
+    tracing::trace!("Node at cursor: kind='{}', text='{}'", node_type, get_node_text(&node, content));
+    
+    // 1. 演算子（. -> ::）の直後、または演算子そのものの場合
+    if node_type == "." || node_type == "->" || node_type == "::" || node_type == ":" {
+        let op_node = if node_type == ":" {
+            node.parent().filter(|p| p.kind() == "::").unwrap_or(node)
+        } else {
+            node
+        };
+
+        if let Some(prev) = get_prev_meaningful_sibling(op_node) {
+            tracing::debug!("Operator detected, target node: kind='{}', text='{}'", prev.kind(), get_node_text(&prev, content));
+            return Some(CompletionTarget::Expression(prev));
+        }
+    }
+
+    // 2. 識別子の入力途中、またはそれ以外
+    let mut curr_opt = Some(node);
+    while let Some(curr) = curr_opt {
+        let p_kind = curr.kind();
+        if p_kind == "field_expression" {
+            if let Some(obj_node) = curr.child_by_field_name("argument") {
+                return Some(CompletionTarget::Expression(obj_node));
+            }
+            break;
+        } else if p_kind == "qualified_identifier" {
+            if let Some(scope_node) = curr.child_by_field_name("scope") {
+                return Some(CompletionTarget::Scope(scope_node));
+            }
+            break;
+        } else if p_kind == "ERROR" {
+            // ERRORノード内の子要素を逆順に探して演算子を見つける
+            let count = curr.child_count();
+            for i in (0..count).rev() {
+                if let Some(child) = curr.child(i as u32) {
+                    let ck = child.kind();
+                    if ck == "." || ck == "->" || ck == "::" {
+                        if let Some(prev) = get_prev_meaningful_sibling(child) {
+                             return Some(CompletionTarget::Expression(prev));
+                        }
+                    }
+                }
+            }
+        }
+        curr_opt = curr.parent();
+    }
+
+    // 3. 暗黙の this 補完 (スタンドアロンの識別子入力時)
+    if node_type == "identifier" || node_type == "type_identifier" || node_type == "field_identifier" || node_type == "this" {
+        if let Some(current_class) = get_enclosing_class_name(&node, content) {
+            tracing::debug!("Implicit 'this' context detected: '{}'", current_class);
+            return Some(CompletionTarget::ImplicitThis(current_class));
+        }
+    }
+
+    None
+}
+
+// AddDynamic / AddUniqueDynamic / RemoveDynamic / RemoveAll の `&Class::` の位置ならそのクラス名
+fn detect_delegate_binding(content: &str, row: usize, col: usize) -> Option<String> {
+    let line = content.lines().nth(row)?;
+    let before = line.get(..col.min(line.len()))?;
+    let re = cached_regex!(r"(?:AddDynamic|AddUniqueDynamic|RemoveDynamic|RemoveAll)\s*\([^,()]*,\s*&\s*([A-Za-z0-9_]+)::\w*$");
+    let cap = re.captures(before)?;
+    Some(cap.get(1)?.as_str().to_string())
+}
+
+fn fetch_delegate_handlers(conn: &Connection, class_name: &str) -> anyhow::Result<Vec<CompletionItem>> {
+    let resolved = resolve_typedef(conn, class_name)?;
+    let mut stmt = conn.prepare("
+        SELECT m.name, m.return_type, m.detail, f.path FROM members m JOIN classes c ON m.class_id = c.id LEFT JOIN files f ON c.file_id = f.id
+        WHERE c.name = ? AND m.type = 'function' AND m.flags LIKE '%UFUNCTION%'
+    ")?;
+    let mut items = Vec::new();
+    let mut seen = HashMap::new();
+    for cls in class_ancestors(conn, &resolved)? {
+        let rows = stmt.query_map([&cls], |row| {
+            let name: String = row.get(0)?;
+            let mut item = CompletionItem::new(&name, 2, &row.get::<_, Option<String>>(1)?.unwrap_or_default());
+            item.documentation = Some(row.get::<_, Option<String>>(2)?.unwrap_or_default());
+            item.data = Some(CompletionItemData { class: cls.clone(), file: row.get(3)?, ..Default::default() });
+            Ok(item)
+        })?;
+        for item in rows {
+            let item = item?;
+            // オーバーライドは最も派生したクラスの宣言を使う
+            if seen.insert(item.label.clone(), true).is_none() { items.push(item); }
+        }
+    }
+    Ok(items)
+}
+
+#[derive(Debug)]
+enum LiteralContext {
+    LogCategory(String),
+    ConsoleVariable(String),
+    LocNamespace { prefix: String, quoted: bool },
+    LocKey { namespace: Option<String>, prefix: String },
+    /// UPROPERTY(|) などの指定子。macro_name は meta = (|) の中なら "meta"
+    Specifier { macro_name: String, typed: Vec<String>, prefix: String },
+}
+
+fn detect_literal_context(content: &str, row: usize, col: usize) -> Option<LiteralContext> {
+    let line = content.lines().nth(row)?;
+    let before = line.get(..col.min(line.len()))?;
+
+    // UE_LOG(|, ...) / UE_LOGFMT(|, ...) の第1引数
+    let log_re = cached_regex!(r"\bUE_LOG(?:FMT)?\s*\(\s*([A-Za-z0-9_]*)$");
+    if let Some(cap) = log_re.captures(before) {
+        return Some(LiteralContext::LogCategory(cap.get(1).map_or("", |m| m.as_str()).to_string()));
+    }
+
+    // FindConsoleVariable(TEXT("|")) や TAutoConsoleVariable<int32> CVar(TEXT("|"), ...) の名前文字列
+    let cvar_re = cached_regex!(r#"\b(?:FindConsoleVariable|FindConsoleObject|FindTConsoleVariableData\w*|RegisterConsoleVariable\w*|RegisterConsoleCommand|ExecuteConsoleCommand|ConsoleCommand|TAutoConsoleVariable\s*<[^>]*>\s*\w+|FAutoConsoleVariableRef\s*\w+|FAutoConsoleCommand\w*\s*\w+)\s*\(\s*(?:TEXT\s*\(\s*)?"([^"]*)$"#);
+    if let Some(cap) = cvar_re.captures(before) {
+        return Some(LiteralContext::ConsoleVariable(cap.get(1).map_or("", |m| m.as_str()).to_string()));
+    }
+
+    // NSLOCTEXT(|, ...) / NSLOCTEXT("|", ...) / #define LOCTEXT_NAMESPACE "|" の名前空間
+    let ns_re = cached_regex!(r#"(?:\bNSLOCTEXT\s*\(|#\s*define\s+LOCTEXT_NAMESPACE)\s*(?:"([^"]*)|(\w*))$"#);
+    if let Some(cap) = ns_re.captures(before) {
+        return Some(match cap.get(1) {
+            Some(m) => LiteralContext::LocNamespace { prefix: m.as_str().to_string(), quoted: true },
+            None => LiteralContext::LocNamespace { prefix: cap.get(2).map_or("", |m| m.as_str()).to_string(), quoted: false },
+        });
+    }
+    // NSLOCTEXT("Namespace", "|", ...) のキー
+    let ns_key_re = cached_regex!(r#"\bNSLOCTEXT\s*\(\s*"([^"]*)"\s*,\s*"([^"]*)$"#);
+    if let Some(cap) = ns_key_re.captures(before) {
+        return Some(LiteralContext::LocKey {
+            namespace: cap.get(1).map(|m| m.as_str().to_string()),
+            prefix: cap.get(2).map_or("", |m| m.as_str()).to_string(),
+        });
+    }
+    // LOCTEXT("|", ...) のキー (直前の LOCTEXT_NAMESPACE で絞り込む)
+    let key_re = cached_regex!(r#"\bLOCTEXT\s*\(\s*"([^"]*)$"#);
+    if let Some(cap) = key_re.captures(before) {
+        let define_re = cached_regex!(r#"#\s*define\s+LOCTEXT_NAMESPACE\s+"([^"]*)""#);
+        let preceding: String = content.lines().take(row).collect::<Vec<_>>().join("\n");
+        let namespace = define_re.captures_iter(&preceding).last().and_then(|c| c.get(1)).map(|m| m.as_str().to_string());
+        return Some(LiteralContext::LocKey { namespace, prefix: cap.get(1).map_or("", |m| m.as_str()).to_string() });
+    }
+    detect_specifier_context(content, row, col)
+}
+
+/// UPROPERTY(EditAnywhere, |) / UFUNCTION(meta = (|)) のように、リフレクションマクロの括弧内で指定子を入力している位置
+/// 複数行にまたがる引数も見る。Category = | のような値の位置や文字列の中では None
+fn detect_specifier_context(content: &str, row: usize, col: usize) -> Option<LiteralContext> {
+    let line = content.lines().nth(row)?;
+    // マクロの引数が何十行も続くことはないので直前の数行だけを見る
+    let first = row.saturating_sub(SPECIFIER_LOOKBACK_LINES);
+    let mut before: String = content.lines().skip(first).take(row - first).map(|l| format!("{}\n", l)).collect();
+    before.push_str(line.get(..col.min(line.len()))?);
+    let re = cached_regex!(r"\b(UPROPERTY|UFUNCTION|UCLASS|USTRUCT|UENUM|UINTERFACE|UPARAM|UMETA)\s*\(");
+    let m = re.captures_iter(&before).last()?;
+    let macro_name = m.get(1)?.as_str();
+    let args = &before[m.get(0)?.end()..];
+
+    // 括弧の深さごとに指定子を区切る。stack の先頭がマクロ直下、meta = ( の中なら 2 段目
+    let mut groups: Vec<(String, Vec<String>)> = vec![(macro_name.to_string(), Vec::new())];
+    let mut current = String::new();
+    let mut in_string = false;
+    for ch in args.chars() {
+        match ch {
+            '"' => { in_string = !in_string; current.push(ch); }
+            _ if in_string => current.push(ch),
+            '(' => {
+                let key = current.split('=').next().unwrap_or("").trim().to_string();
+                groups.push((if key.eq_ignore_ascii_case("meta") { "meta".to_string() } else { String::new() }, Vec::new()));
+                current.clear();
+            }
+            ')' => {
+                groups.pop();
+                if groups.is_empty() { return None; }
+                current.clear();
+            }
+            ',' => {
+                let key = current.split('=').next().unwrap_or("").trim().to_string();
+                if let Some(group) = groups.last_mut() { if !key.is_empty() { group.1.push(key); } }
+                current.clear();
+            }
+            _ => current.push(ch),
+        }
+    }
+    if in_string || current.contains('=') { return None; }
+    let (name, typed) = groups.pop()?;
+    // Category = (...) のような値のかっこの中
+    if name.is_empty() { return None; }
+    let prefix = current.trim();
+    if !prefix.chars().all(|c| c.is_alphanumeric() || c == '_') { return None; }
+    Some(LiteralContext::Specifier { macro_name: name, typed, prefix: prefix.to_string() })
+}
+
+const SPECIFIER_LOOKBACK_LINES: usize = 20;
+
+/// リフレクションマクロごとのよく使う指定子
+fn reflection_specifiers(macro_name: &str) -> &'static [&'static str] {
+    match macro_name {
+        "UPROPERTY" => &[
+            "EditAnywhere", "EditDefaultsOnly", "EditInstanceOnly", "VisibleAnywhere", "VisibleDefaultsOnly", "VisibleInstanceOnly",
+            "BlueprintReadWrite", "BlueprintReadOnly", "BlueprintAssignable", "BlueprintCallable", "BlueprintAuthorityOnly",
+            "BlueprintGetter", "BlueprintSetter", "Category", "Config", "GlobalConfig", "Transient", "DuplicateTransient",
+            "NonTransactional", "SaveGame", "Replicated", "ReplicatedUsing", "NotReplicated", "Instanced", "Export", "Interp",
+            "AdvancedDisplay", "SimpleDisplay", "AssetRegistrySearchable", "NoClear", "EditFixedSize", "meta",
+        ],
+        "UFUNCTION" => &[
+            "BlueprintCallable", "BlueprintPure", "BlueprintImplementableEvent", "BlueprintNativeEvent", "BlueprintAuthorityOnly",
+            "BlueprintCosmetic", "BlueprintGetter", "BlueprintSetter", "CallInEditor", "Category", "Exec",
+            "Server", "Client", "NetMulticast", "Reliable", "Unreliable", "WithValidation", "SealedEvent", "meta",
+        ],
+        "UCLASS" => &[
+            "Blueprintable", "NotBlueprintable", "BlueprintType", "NotBlueprintType", "Abstract", "Const", "Config", "DefaultConfig",
+            "Transient", "NonTransient", "MinimalAPI", "Within", "ClassGroup", "EditInlineNew", "NotEditInlineNew",
+            "Placeable", "NotPlaceable", "Deprecated", "HideCategories", "ShowCategories", "AutoExpandCategories",
+            "CollapseCategories", "DontCollapseCategories", "HideDropdown", "meta",
+        ],
+        "USTRUCT" => &["BlueprintType", "Atomic", "NoExport", "Immutable", "meta"],
+        "UENUM" => &["BlueprintType", "Flags", "meta"],
+        "UINTERFACE" => &["Blueprintable", "NotBlueprintable", "BlueprintType", "MinimalAPI", "meta"],
+        "UPARAM" => &["ref", "DisplayName"],
+        "UMETA" => &["DisplayName", "ToolTip", "Hidden"],
+        "meta" => &[
+            "DisplayName", "ToolTip", "ShortTooltip", "Keywords", "ClampMin", "ClampMax", "UIMin", "UIMax",
+            "AllowPrivateAccess", "EditCondition", "EditConditionHides", "InlineEditConditionToggle",
+            "BindWidget", "BindWidgetOptional", "ExposeOnSpawn", "MakeEditWidget", "AllowedClasses", "MustImplement",
+            "DefaultToSelf", "HidePin", "WorldContext", "CompactNodeTitle", "ExpandEnumAsExecs", "DeterminesOutputType",
+        ],
+        _ => &[],
+    }
+}
+
+fn fetch_literal_candidates(conn: &Connection, literal_ctx: &LiteralContext) -> anyhow::Result<Vec<CompletionItem>> {
+    let mut result = Vec::new();
+    match literal_ctx {
+        LiteralContext::LogCategory(prefix) => {
+            let mut stmt = conn.prepare("SELECT DISTINCT name FROM log_categories WHERE name LIKE ? ORDER BY name LIMIT 200")?;
+            let rows = stmt.query_map([format!("{}%", prefix)], |row| {
+                let name: String = row.get(0)?;
+                Ok(CompletionItem::new(&name, 21, "log category"))
+            })?;
+            for r in rows { result.push(r?); }
+        }
+        LiteralContext::ConsoleVariable(prefix) => {
+            let mut stmt = conn.prepare("SELECT name, MAX(kind) FROM cvars WHERE name LIKE ? GROUP BY name ORDER BY name LIMIT 200")?;
+            let rows = stmt.query_map([format!("{}%", prefix)], |row| {
+                let name: String = row.get(0)?;
+                let kind: Option<String> = row.get(1)?;
+                let is_command = kind.as_deref() == Some("command");
+                Ok(if is_command {
+                    CompletionItem::new(&name, 3, "console command")
+                } else {
+                    CompletionItem::new(&name, 12, "console variable")
+                })
+            })?;
+            for r in rows { result.push(r?); }
+        }
+        LiteralContext::LocNamespace { prefix, quoted } => {
+            let mut stmt = conn.prepare("SELECT DISTINCT namespace FROM loc_texts WHERE namespace LIKE ? ORDER BY namespace LIMIT 200")?;
+            let rows = stmt.query_map([format!("{}%", prefix)], |row| {
+                let name: String = row.get(0)?;
+                let mut item = CompletionItem::new(&name, 9, "localization namespace");
+                if !*quoted { item.insert_text = format!("\"{}\"", name); }
+                Ok(item)
+            })?;
+            for r in rows { result.push(r?); }
+        }
+        LiteralContext::LocKey { namespace, prefix } => {
+            let mut stmt = conn.prepare("SELECT DISTINCT key, namespace FROM loc_texts WHERE key != '' AND key LIKE ? AND (? IS NULL OR namespace = ?) ORDER BY key LIMIT 200")?;
+            let rows = stmt.query_map(rusqlite::params![format!("{}%", prefix), namespace, namespace], |row| {
+                let key: String = row.get(0)?;
+                let ns: String = row.get(1)?;
+                Ok(CompletionItem::new(&key, 1, &format!("localization key ({})", ns)))
+            })?;
+            for r in rows { result.push(r?); }
+        }
+        LiteralContext::Specifier { macro_name, typed, prefix } => {
+            // 入力済みの指定子は出さない
+            let detail = format!("{} specifier", macro_name);
+            for specifier in reflection_specifiers(macro_name) {
+                if typed.iter().any(|t| t.eq_ignore_ascii_case(specifier)) { continue; }
+                if !specifier.to_lowercase().starts_with(&prefix.to_lowercase()) { continue; }
+                result.push(CompletionItem::new(specifier, 14, &detail));
+            }
+        }
+    }
+    Ok(result)
+}
+
+fn get_node_text<'a>(node: &Node, content: &'a str) -> &'a str {
+    let range = node.byte_range();
+    if range.end <= content.len() {
+        &content[range.start..range.end]
+    } else {
+        ""
+    }
+}
+
+fn get_prev_meaningful_sibling(node: Node) -> Option<Node> {
+    let mut curr = node.prev_sibling();
+    while let Some(n) = curr {
+        let kind = n.kind();
+        if kind != "comment" && kind != " " && kind != "\n" && kind != "\r" {
+            return Some(n);
+        }
+        curr = n.prev_sibling();
+    }
+    None
+}
+
+fn resolve_expression_type(
+    conn: &Connection,
+    node: Node,
+    root: &Node,
+    content: &str,
+    cursor_row: usize,
+) -> anyhow::Result<Option<String>> {
+    // GetA()->GetB()->... は 1 リンクごとに再帰するので、深さだけで打ち切る
+    let depth = RESOLVE_DEPTH.with(|d| d.get());
+    if depth >= MAX_CHAIN_DEPTH {
+        tracing::warn!("Expression chain deeper than {} at '{}'", MAX_CHAIN_DEPTH, get_node_text(&node, content));
+        return Ok(None);
+    }
+    RESOLVE_DEPTH.with(|d| d.set(depth + 1));
+    let resolved = resolve_expression_type_inner(conn, node, root, content, cursor_row);
+    RESOLVE_DEPTH.with(|d| d.set(depth));
+    let resolved = resolved?;
+    trace_step(|| json!({ "step": "expression", "nodeKind": node.kind(), "text": get_node_text(&node, content), "type": resolved }));
+    Ok(resolved)
+}
+
+const MAX_CHAIN_DEPTH: usize = 16;
+
+thread_local! {
+    static RESOLVE_DEPTH: Cell<usize> = Cell::new(0);
+}
+
+fn resolve_expression_type_inner(
+    conn: &Connection,
+    node: Node,
+    root: &Node,
+    content: &str,
+    cursor_row: usize,
+) -> anyhow::Result<Option<String>> {
+    let kind = node.kind();
+    tracing::trace!("resolve_expression_type(kind='{}', text='{}')", kind, get_node_text(&node, content));
+
+    match kind {
+        "this" => {
+            let cls = get_enclosing_class_name(&node, content);
+            tracing::trace!("Resolved 'this' to class: {:?}", cls);
+            Ok(cls)
+        }
+        "identifier" | "type_identifier" | "field_identifier" | "namespace_identifier" | "scoped_type_identifier" => {
+            let name = get_node_text(&node, content).trim();
+            if name.is_empty() { return Ok(None); }
+            if name == "this" {
+                return Ok(get_enclosing_class_name(&node, content));
+            }
+            if let Some(depth) = super_depth(name) {
+                return resolve_super_class(conn, &node, content, depth);
+            }
+            if let Some(t) = infer_variable_type(conn, name, root, content, cursor_row)? {
+                return Ok(Some(t));
+            }
+            if let Some(current_class) = get_enclosing_class_name(&node, content) {
+                // コンストラクタで MeshComp = CreateDefaultSubobject<UStaticMeshComponent>(...) と代入されたメンバーは宣言より具体的な型
+                if let Some(t) = infer_from_constructor_assignment(conn, name, &current_class, root, content)? {
+                    return Ok(Some(t));
+                }
+                tracing::trace!("Checking if '{}' is a member variable of '{}'", name, current_class);
+                if let Some(rt) = find_member_return_type(conn, &current_class, name)? {
+                    return Ok(Some(rt));
+                }
+            }
+            
+            // Fallback: Check if it's a known type (Class or Enum)
+            if is_known_type(conn, name)? {
+                return Ok(Some(name.to_string()));
+            }
+
+            Ok(None)
+        }
+        "qualified_identifier" => {
+            let text = get_node_text(&node, content);
+            if let Some(depth) = super_depth(text) {
+                return resolve_super_class(conn, &node, content, depth);
+            }
+            // Outer::Inner は外側のスコープまで一致する型を優先し、無ければ末尾の名前だけで探す
+            if let Some((prefix, leaf)) = text.rsplit_once("::") {
+                if let Some(qualified) = resolve_scope_chain(conn, prefix, leaf)? {
+                    return Ok(Some(qualified));
+                }
+            }
+            if is_known_type(conn, text)? {
+                return Ok(Some(text.to_string()));
+            }
+            // FVector::ZeroVector のような静的メンバーは宣言された型
+            resolve_qualified_member(conn, node, content)
+        }
+        "call_expression" => {
+            if let Some(func_node) = node.child_by_field_name("function") {
+                if func_node.kind() == "field_expression" {
+                    if let Some(obj_node) = func_node.child_by_field_name("argument") {
+                        if let Some(obj_type) = resolve_expression_type(conn, obj_node, root, content, cursor_row)? {
+                            if let Some(field_node) = func_node.child_by_field_name("field") {
+                                // Actor->GetComponentByClass<UMyComp>()
+                                if field_node.kind() == "template_method" {
+                                    if let Some((name, args)) = split_template_call(field_node, content) {
+                                        return resolve_template_member_call(conn, &obj_type, &name, &args);
+                                    }
+                                }
+                                let field_name = get_node_text(&field_node, content).trim();
+                                // WeakActor.Get() / SoftMesh.LoadSynchronous() はテンプレート引数の型
+                                if SMART_POINTER_ACCESSORS.contains(&field_name) {
+                                    if let Some(inner) = smart_pointer_target(conn, func_node, obj_node, root, content, cursor_row)? {
+                                        return Ok(Some(inner));
+                                    }
+                                }
+                                // Array.FindByKey(K)-> / Map.Find(K)-> は要素のポインタ
+                                if CONTAINER_ELEMENT_METHODS.contains(&field_name) {
+                                    if let Some(raw) = resolve_raw_expression_type(conn, obj_node, root, content, cursor_row)? {
+                                        if let Some(element) = container_method_type(&raw, field_name) {
+                                            return Ok(Some(extract_clean_type(&element)));
+                                        }
+                                    }
+                                }
+                                if field_name == "GetDefaultSubobjectByName" {
+                                    if let Some(t) = resolve_subobject_by_name(conn, node, &[obj_type.as_str()], content)? {
+                                        return Ok(Some(t));
+                                    }
+                                }
+                                // Actor->GetComponentByClass(UMyComp::StaticClass())
+                                if CLASS_ARGUMENT_RETURNING_METHODS.contains(&field_name) {
+                                    if let Some(cls) = static_class_argument(node, content) {
+                                        return Ok(Some(cls));
+                                    }
+                                }
+                                // Obj.OnDone() が TFunction<FFoo()> のメンバー変数ならその戻り値型
+                                if let Some(return_type) = callable_member_return_type(conn, &obj_type, field_name)? {
+                                    return Ok(Some(return_type));
+                                }
+                                return find_member_return_type(conn, &obj_type, field_name);
+                            }
+                        }
+                    }
+                } else if func_node.kind() == "qualified_identifier" {
+                    // UGameInstance::GetSubsystem<UMySubsystem>(GI) は戻り値の T* をテンプレート引数で置き換える
+                    if let Some((scope, name, args)) = qualified_template_call(func_node, content) {
+                        let owner = match super_depth(&scope) {
+                            Some(depth) => resolve_super_class(conn, &func_node, content, depth)?,
+                            None => Some(scope),
+                        };
+                        return match owner {
+                            Some(owner) => resolve_template_member_call(conn, &owner, &name, &args),
+                            None => Ok(None),
+                        };
+                    }
+                    // UGameplayStatics::GetPlayerController(...) のような静的関数呼び出し / Super::GetOwner()
+                    return resolve_qualified_member(conn, func_node, content);
+                } else if func_node.kind() == "template_function" {
+                    // FindFProperty<FIntProperty>(...) / CastField<FObjectProperty>(...) など
+                    // 型はテンプレート引数だけで決まるので、Cast<AEnemy>(Actors[i]) の引数は解決しない
+                    if let Some((name, args)) = split_template_call(func_node, content) {
+                        if TEMPLATE_ARG_RETURNING_FUNCTIONS.contains(&name.as_str()) {
+                            return Ok(args.first().map(|arg| extract_clean_type(arg)));
+                        }
+                        // this->GetController<AMyController>() の省略形
+                        if let Some(current_class) = get_enclosing_class_name(&node, content) {
+                            return resolve_template_member_call(conn, &current_class, &name, &args);
+                        }
+                    }
+                } else {
+                    // (*FuncPtr)() / FuncPtr() は関数ポインタ宣言の戻り値型
+                    if let Some(return_type) = function_pointer_return_type(func_node, root, content, cursor_row)? {
+                        return Ok(Some(return_type));
+                    }
+                    let func_name = get_node_text(&func_node, content).trim();
+                    // Callback() は TFunction<FFoo()> のローカル変数/メンバー変数ならその戻り値型
+                    if func_node.kind() == "identifier" {
+                        if let Some((t_node, _)) = find_declaration(func_name, root, content, cursor_row)? {
+                            if let Some(return_type) = callable_return_type(get_node_text(&t_node, content)) {
+                                return Ok(Some(return_type));
+                            }
+                        }
+                        if let Some(current_class) = get_enclosing_class_name(&node, content) {
+                            if let Some(return_type) = callable_member_return_type(conn, &current_class, func_name)? {
+                                return Ok(Some(return_type));
+                            }
+                        }
+                    }
+                    // NewObject(this, UMyComp::StaticClass()) は StaticClass の型
+                    if CLASS_ARGUMENT_RETURNING_FUNCTIONS.contains(&func_name) {
+                        if let Some(cls) = static_class_argument(node, content) {
+                            return Ok(Some(cls));
+                        }
+                    }
+                    if let Some(current_class) = get_enclosing_class_name(&node, content) {
+                        if func_name == "GetDefaultSubobjectByName" {
+                            if let Some(t) = resolve_subobject_by_name(conn, node, &[current_class.as_str()], content)? {
+                                return Ok(Some(t));
+                            }
+                        }
+                        return find_member_return_type(conn, &current_class, func_name);
+                    }
+                }
+            }
+            Ok(None)
+        }
+        "field_expression" => {
+            if let Some(obj_node) = node.child_by_field_name("argument") {
+                if let Some(field_node) = node.child_by_field_name("field") {
+                    let field_name = get_node_text(&field_node, content).trim();
+                    // for (auto& Pair : Map) { Pair.Value-> } はテンプレート引数の型
+                    if let Some(member) = pair_member_type(conn, obj_node, field_name, root, content, cursor_row)? {
+                        return Ok(Some(extract_clean_type(&member)));
+                    }
+                }
+                if let Some(obj_type) = resolve_expression_type(conn, obj_node, root, content, cursor_row)? {
+                    if let Some(field_node) = node.child_by_field_name("field") {
+                        return find_member_return_type(conn, &obj_type, get_node_text(&field_node, content).trim());
+                    }
+                }
+            }
+            Ok(None)
+        }
+        // 文末の `GetWorldTimerManager().` がエラー回復で (identifier) (argument_list) "." に分かれた場合
+        "argument_list" => {
+            let Some(callee) = get_prev_meaningful_sibling(node) else { return Ok(None) };
+            match callee.kind() {
+                "identifier" => match get_enclosing_class_name(&callee, content) {
+                    Some(current_class) => find_member_return_type(conn, &current_class, get_node_text(&callee, content).trim()),
+                    None => Ok(None),
+                },
+                "qualified_identifier" => resolve_qualified_member(conn, callee, content),
+                "field_expression" => resolve_expression_type(conn, callee, root, content, cursor_row),
+                _ => Ok(None),
+            }
+        }
+        "parenthesized_expression" => match node.named_child(0) {
+            Some(inner) => resolve_expression_type(conn, inner, root, content, cursor_row),
+            None => Ok(None),
+        },
+        // (*Ptr). / (*this). / (*It). はポインタやイテレーターを 1 段外した型。(&Obj)-> は Obj と同じ型
+        "pointer_expression" => {
+            let op = node.child_by_field_name("operator").map(|o| get_node_text(&o, content).trim()).unwrap_or("");
+            let Some(arg) = node.child_by_field_name("argument") else { return Ok(None) };
+            if op == "*" {
+                if let Some(raw) = resolve_raw_expression_type(conn, arg, root, content, cursor_row)? {
+                    let pointee = extract_clean_type(&dereference_raw_type(&raw));
+                    if !pointee.is_empty() { return Ok(Some(pointee)); }
+                }
+            }
+            resolve_expression_type(conn, arg, root, content, cursor_row)
+        }
+        // ((AMyActor*)Ptr)-> の C スタイルキャストはキャスト先の型
+        "cast_expression" => Ok(node.child_by_field_name("type")
+            .map(|t| extract_clean_type(get_node_text(&t, content)))
+            .filter(|t| !t.is_empty())),
+        "conditional_expression" => {
+            // (bA ? DerivedA : DerivedB)-> は両方の共通の基底クラス
+            let consequence = match node.child_by_field_name("consequence") {
+                Some(n) => resolve_expression_type(conn, n, root, content, cursor_row)?,
+                None => None,
+            };
+            let alternative = match node.child_by_field_name("alternative") {
+                Some(n) => resolve_expression_type(conn, n, root, content, cursor_row)?,
+                None => None,
+            };
+            match (consequence, alternative) {
+                (Some(a), Some(b)) if a != b => Ok(Some(nearest_common_base(conn, &a, &b)?.unwrap_or(a))),
+                (Some(a), _) => Ok(Some(a)),
+                (None, b) => Ok(b),
+            }
+        }
+        "subscript_expression" => {
+            // MyArray[i]. / MyMap[Key]-> (代入の左辺でも同様)
+            if let Some(obj_node) = node.child_by_field_name("argument") {
+                if let Some(container) = resolve_raw_expression_type(conn, obj_node, root, content, cursor_row)? {
+                    if let Some(element) = subscript_element_type_in_db(conn, &container)? {
+                        tracing::debug!("Subscript on '{}' -> '{}'", container, element);
+                        return Ok(Some(extract_clean_type(&element)));
+                    }
+                }
+            }
+            Ok(None)
+        }
+        // strict: 未対応のノード種別 (文法の更新漏れなど) をエラーとして報告する
+        _ if active_config(|c| c.strict) => Err(anyhow::anyhow!("Unhandled node kind '{}' in type resolution: '{}'", kind, get_node_text(&node, content))),
+        _ => Ok(None)
+    }
+}
+
+fn find_member_return_type(conn: &Connection, class_name: &str, member_name: &str) -> anyhow::Result<Option<String>> {
+    Ok(find_member_raw_return_type(conn, class_name, member_name)?.map(|rt| extract_clean_type(&rt)))
+}
+
+/// テンプレート引数を残したままのメンバーの型 (TArray<FFoo> など)
+fn find_member_raw_return_type(conn: &Connection, class_name: &str, member_name: &str) -> anyhow::Result<Option<String>> {
+    let clean_class = extract_clean_type(class_name);
+    let resolved_class = resolve_typedef(conn, &clean_class)?;
+    tracing::trace!("Searching member '{}' in class '{}' (and parents)", member_name, resolved_class);
+    
+    for cls in class_ancestors(conn, &resolved_class)? {
+        let mut stmt = conn.prepare_cached("
+            SELECT m.return_type FROM members m JOIN classes c ON m.class_id = c.id 
+            WHERE c.name = ? AND m.name = ? 
+            ORDER BY (CASE WHEN m.return_type = 'T' OR m.return_type = 'T*' OR m.return_type = 'void' THEN 1 ELSE 0 END) ASC, length(m.return_type) DESC 
+            LIMIT 1
+        ")?;
+        let mut rows = stmt.query([&cls, member_name])?;
+        if let Some(row) = rows.next()? {
+            if let Some(rt) = row.get::<_, Option<String>>(0)? {
+                if !extract_clean_type(&rt).is_empty() {
+                    tracing::trace!("Found member '{}' -> '{}' in '{}'", member_name, rt, cls);
+                    trace_step(|| json!({ "step": "member", "class": resolved_class, "member": member_name, "foundIn": cls, "type": rt }));
+                    return Ok(Some(rt));
+                }
+            }
+        }
+    }
+    // 索引されていない FString などは組み込みの一覧から (S.ToUpper(). の連鎖用)
+    if let Some((_, rt, _)) = core_string_members(&resolved_class).and_then(|members| members.iter().find(|(name, _, _)| *name == member_name)) {
+        trace_step(|| json!({ "step": "member", "class": resolved_class, "member": member_name, "foundIn": "builtin", "type": rt }));
+        return Ok(Some(rt.to_string()));
+    }
+    trace_step(|| json!({ "step": "member", "class": resolved_class, "member": member_name, "foundIn": null, "type": null }));
+    Ok(None)
+}
+
+/// a の祖先 (自身を含む、近い順) のうち最初に b の祖先にも現れるクラス
+fn nearest_common_base(conn: &Connection, a: &str, b: &str) -> anyhow::Result<Option<String>> {
+    let a_ancestors = class_ancestors(conn, &resolve_typedef(conn, a)?)?;
+    let b_ancestors = class_ancestors(conn, &resolve_typedef(conn, b)?)?;
+    Ok(a_ancestors.into_iter().find(|cls| b_ancestors.iter().any(|other| other.eq_ignore_ascii_case(cls))))
+}
+
+/// 自身を含む祖先クラス名の一覧 (幅優先で近い順、同じ深さは宣言順)。completion_cache にキャッシュされる
+fn class_ancestors(conn: &Connection, class_name: &str) -> anyhow::Result<Vec<String>> {
+    if let Some(cached) = completion_cache::get_ancestors(class_name) {
+        return Ok(cached);
+    }
+    let mut result = Vec::new();
+    let mut queue = VecDeque::from([class_name.to_string()]);
+    let mut visited = HashMap::new();
+    while let Some(cls) = queue.pop_front() {
+        if visited.contains_key(&cls) { continue; }
+        visited.insert(cls.clone(), true);
+        
+        let mut p_stmt = conn.prepare_cached("SELECT parent_name FROM inheritance i JOIN classes c ON i.child_id = c.id WHERE c.name = ? ORDER BY i.id")?;
+        let p_rows = p_stmt.query_map([&cls], |r| Ok(r.get::<_, String>(0)?))?;
+        for p in p_rows { queue.push_back(p?); }
+        result.push(cls);
+    }
+    completion_cache::put_ancestors(class_name, &result, source_files(conn, &result)?);
+    Ok(result)
+}
+
+/// names を定義しているファイルのパス (completion_cache の個別破棄用)
+fn source_files(conn: &Connection, names: &[String]) -> anyhow::Result<Vec<String>> {
+    let mut stmt = conn.prepare("SELECT DISTINCT f.path FROM classes c JOIN files f ON c.file_id = f.id WHERE c.name = ?")?;
+    let mut files: Vec<String> = Vec::new();
+    for name in names {
+        for path in stmt.query_map([name], |row| row.get::<_, String>(0))? {
+            let path = path?;
+            if !files.contains(&path) { files.push(path); }
+        }
+    }
+    Ok(files)
+}
+
+/// 列挙子を宣言順に返す ([{name, value, expression, displayName, documentation}])
+/// 値の省略された列挙子は直前の値 + 1、計算できない式 (外部定数など) は value を null にする
+pub fn enum_values(conn: &Connection, enum_name: &str) -> anyhow::Result<Value> {
+    let mut stmt = conn.prepare(
+        "SELECT c.id FROM classes c WHERE c.name = ? AND c.symbol_type IN ('enum', 'UENUM')
+         AND EXISTS (SELECT 1 FROM enum_values ev WHERE ev.enum_id = c.id) ORDER BY c.id LIMIT 1",
+    )?;
+    let mut rows = stmt.query([enum_name])?;
+    let enum_id: i64 = match rows.next()? {
+        Some(row) => row.get(0)?,
+        None => return Ok(json!([])),
+    };
+    let result: Vec<Value> = load_enum_values(conn, enum_id)?.into_iter().map(|v| json!({
+        "name": v.name,
+        "value": v.value,
+        "expression": v.expression,
+        "displayName": v.display_name,
+        "documentation": v.documentation,
+    })).collect();
+    Ok(json!(result))
+}
+
+struct EnumValue {
+    name: String,
+    value: Option<i64>,
+    expression: Option<String>,
+    display_name: Option<String>,
+    documentation: Option<String>,
+    flags: String,
+}
+
+impl EnumValue {
+    // 補完の detail: "= 5" / ビットフラグなどの式は "= 1 << 3 (8)"
+    fn detail(&self) -> String {
+        match (&self.expression, self.value) {
+            (Some(expr), Some(v)) if *expr != v.to_string() => format!("= {} ({})", expr, v),
+            (Some(expr), None) => format!("= {}", expr),
+            (_, Some(v)) => format!("= {}", v),
+            (None, None) => "enum item".to_string(),
+        }
+    }
+}
+
+fn load_enum_values(conn: &Connection, enum_id: i64) -> anyhow::Result<Vec<EnumValue>> {
+    let mut stmt = conn.prepare_cached("SELECT name, value, display_name, documentation, flags FROM enum_values WHERE enum_id = ? ORDER BY id")?;
+    let rows = stmt.query_map([enum_id], |row| {
+        Ok(EnumValue {
+            name: row.get(0)?,
+            value: None,
+            expression: row.get(1)?,
+            display_name: row.get(2)?,
+            documentation: row.get(3)?,
+            flags: row.get::<_, Option<String>>(4)?.unwrap_or_default(),
+        })
+    })?;
+    let mut known: HashMap<String, i64> = HashMap::new();
+    let mut next: Option<i64> = Some(0);
+    let mut result = Vec::new();
+    for row in rows {
+        let mut entry = row?;
+        entry.value = match &entry.expression {
+            Some(expr) => eval_enum_expression(expr, &known),
+            None => next,
+        };
+        if let Some(v) = entry.value { known.insert(entry.name.clone(), v); }
+        next = entry.value.and_then(|v| v.checked_add(1));
+        result.push(entry);
+    }
+    Ok(result)
+}
+
+// 5 / 0x10 / -1 / 1 << 3 / A | B / 前の列挙子名 程度の定数式
+fn eval_enum_expression(expr: &str, known: &HashMap<String, i64>) -> Option<i64> {
+    let expr = expr.trim();
+    if let Some(inner) = expr.strip_prefix('(').and_then(|e| e.strip_suffix(')')) {
+        return eval_enum_expression(inner, known);
+    }
+    for op in ["|", "<<", "+", "-"] {
+        if let Some(pos) = expr.rfind(op).filter(|&p| p > 0) {
+            let lhs = eval_enum_expression(&expr[..pos], known)?;
+            let rhs = eval_enum_expression(&expr[pos + op.len()..], known)?;
+            return match op {
+                "|" => Some(lhs | rhs),
+                "<<" => u32::try_from(rhs).ok().and_then(|r| lhs.checked_shl(r)),
+                "+" => lhs.checked_add(rhs),
+                _ => lhs.checked_sub(rhs),
+            };
+        }
+    }
+    if let Some(rest) = expr.strip_prefix('-') {
+        return eval_enum_expression(rest, known).map(|v| -v);
+    }
+    let literal = expr.trim_end_matches(['u', 'U', 'l', 'L']);
+    if let Some(hex) = literal.strip_prefix("0x").or_else(|| literal.strip_prefix("0X")) {
+        return i64::from_str_radix(hex, 16).ok();
+    }
+    if let Ok(v) = literal.parse::<i64>() {
+        return Some(v);
+    }
+    known.get(expr.rsplit("::").next().unwrap_or(expr)).copied()
+}
+
+/// クラスと祖先がすべてインデックス済みか (継承メンバーが欠ける原因の切り分け用)
+pub fn verify_type_complete(conn: &Connection, class_name: &str) -> anyhow::Result<TypeCompleteness> {
+    let resolved = resolve_typedef(conn, class_name)?;
+    let mut report = TypeCompleteness { class_name: resolved.clone(), ..Default::default() };
+    for cls in class_ancestors(conn, &resolved)? {
+        let indexed = is_known_type(conn, &cls)?;
+        if cls == resolved {
+            report.indexed = indexed;
+        } else if indexed {
+            report.indexed_ancestors.push(cls);
+        } else {
+            report.missing_ancestors.push(cls);
+        }
+    }
+    Ok(report)
+}
+
+/// コンテナ式の生の型を解決する (ローカル変数 → メンバー変数 → メンバー関数の戻り値)
+fn resolve_raw_expression_type(
+    conn: &Connection,
+    node: Node,
+    root: &Node,
+    content: &str,
+    cursor_row: usize,
+) -> anyhow::Result<Option<String>> {
+    match node.kind() {
+        "identifier" | "field_identifier" => {
+            if let Some(raw) = infer_raw_expression_type(node, root, content, cursor_row, 0)? {
+                return Ok(Some(raw));
+            }
+            let name = get_node_text(&node, content).trim();
+            // for (auto& Pair : Map) の Pair は TPair<K, V> のまま返す (Pair.Value の型引数を取り出すため)
+            if let Some((t_node, d_node)) = find_declaration(name, root, content, cursor_row)? {
+                // for (auto X : X) のような自己参照は辿らない
+                let in_own_range = d_node.parent().and_then(|p| p.child_by_field_name("right"))
+                    .map_or(false, |r| r.start_byte() <= node.start_byte() && node.end_byte() <= r.end_byte());
+                if is_auto_type(get_node_text(&t_node, content)) && !in_own_range {
+                    if let Some(element) = infer_range_element_type(conn, &d_node, root, content, cursor_row)? {
+                        return Ok(Some(element));
+                    }
+                }
+            }
+            match get_enclosing_class_name(&node, content) {
+                Some(current_class) => find_member_raw_return_type(conn, &current_class, name),
+                None => Ok(None),
+            }
+        }
+        "field_expression" => {
+            if let (Some(obj_node), Some(field_node)) = (node.child_by_field_name("argument"), node.child_by_field_name("field")) {
+                let field_name = get_node_text(&field_node, content).trim();
+                if let Some(member) = pair_member_type(conn, obj_node, field_name, root, content, cursor_row)? {
+                    return Ok(Some(member));
+                }
+                if let Some(obj_type) = resolve_expression_type(conn, obj_node, root, content, cursor_row)? {
+                    return find_member_raw_return_type(conn, &obj_type, field_name);
+                }
+            }
+            Ok(None)
+        }
+        "call_expression" => {
+            if let Some(func_node) = node.child_by_field_name("function") {
+                if func_node.kind() == "field_expression" {
+                    if let (Some(obj_node), Some(field_node)) = (func_node.child_by_field_name("argument"), func_node.child_by_field_name("field")) {
+                        let field_name = get_node_text(&field_node, content).trim();
+                        if CONTAINER_ELEMENT_METHODS.contains(&field_name) {
+                            if let Some(raw) = resolve_raw_expression_type(conn, obj_node, root, content, cursor_row)? {
+                                if let Some(element) = container_method_type(&raw, field_name) {
+                                    return Ok(Some(element));
+                                }
+                            }
+                        }
+                        if let Some(obj_type) = resolve_expression_type(conn, obj_node, root, content, cursor_row)? {
+                            return find_member_raw_return_type(conn, &obj_type, field_name);
+                        }
+                    }
+                }
+            }
+            infer_raw_expression_type(node, root, content, cursor_row, 0)
+        }
+        // (Items)[0] / (bA ? ItemsA : ItemsB)[0] もコンテナの型引数を残したまま中身をたどる
+        "parenthesized_expression" => match node.named_child(0) {
+            Some(inner) => resolve_raw_expression_type(conn, inner, root, content, cursor_row),
+            None => Ok(None),
+        },
+        "conditional_expression" => {
+            for field in ["consequence", "alternative"] {
+                if let Some(branch) = node.child_by_field_name(field) {
+                    if let Some(raw) = resolve_raw_expression_type(conn, branch, root, content, cursor_row)? {
+                        return Ok(Some(raw));
+                    }
+                }
+            }
+            Ok(None)
+        }
+        // Map[Key][0] の内側 (TArray<FFoo>) もテンプレート引数付きで返す
+        "subscript_expression" => match node.child_by_field_name("argument") {
+            Some(obj_node) => match resolve_raw_expression_type(conn, obj_node, root, content, cursor_row)? {
+                Some(container) => subscript_element_type_in_db(conn, &container),
+                None => Ok(None),
+            },
+            None => Ok(None),
+        },
+        "this" => Ok(None),
+        _ => infer_raw_expression_type(node, root, content, cursor_row, 0),
+    }
+}
+
+/// 第1テンプレート引数をそのまま戻り値型 (T*) とするグローバル関数
+const TEMPLATE_ARG_RETURNING_FUNCTIONS: &[&str] = &[
+    "Cast", "CastChecked", "ExactCast", "InterfaceCast",
+    "FindFProperty", "FindUField", "FindField", "FindFieldChecked",
+    "CastField", "CastFieldChecked", "CastFieldCheckedNullAllowed", "ExactCastField",
+    "FindObject", "FindObjectChecked", "FindFirstObject", "LoadObject", "NewObject",
+    "CastEnsured", "DuplicateObject", "StaticCast",
+    "static_cast", "dynamic_cast", "reinterpret_cast", "const_cast",
+];
+
+/// 第1テンプレート引数を戻り値型とするメンバー関数 (DB 上は非テンプレート版のオーバーロードが優先されるため)
+const TEMPLATE_ARG_RETURNING_METHODS: &[&str] = &[
+    "GetComponentByClass", "FindComponentByClass", "GetTypedOuter",
+    "GetController", "GetInstigator", "GetInstigatorController",
+    "GetGameInstance", "GetGameMode", "GetAuthGameMode", "GetGameState", "GetPlayerState", "GetPawn", "GetHUD", "GetOwner",
+    "GetSubsystem", "GetLocalPlayerSubsystem", "GetEngineSubsystem",
+    "CreateDefaultSubobject", "CreateEditorOnlyDefaultSubobject", "CreateOptionalDefaultSubobject",
+];
+
+/// スマートポインタの中身を返すメンバー関数
+const SMART_POINTER_ACCESSORS: &[&str] = &["Get", "GetEvenIfUnreachable", "LoadSynchronous"];
+
+/// `.Get()` が中身 (T*) を返すラッパー (TSubclassOf / TSoftClassPtr の Get() は UClass* なので含めない)
+const SMART_POINTER_TEMPLATES: &[&str] = &[
+    "TObjectPtr", "TWeakObjectPtr", "TSoftObjectPtr", "TStrongObjectPtr", "TSharedPtr", "TSharedRef", "TUniquePtr",
+];
+
+fn smart_pointer_target(conn: &Connection, callee: Node, obj_node: Node, root: &Node, content: &str, cursor_row: usize) -> anyhow::Result<Option<String>> {
+    // WeakActor->Get() は中身のメンバー呼び出し
+    let is_dot = callee.child_by_field_name("operator").map_or(false, |op| get_node_text(&op, content) == ".");
+    if !is_dot { return Ok(None); }
+    let raw = match resolve_raw_expression_type(conn, obj_node, root, content, cursor_row)? {
+        Some(r) => r,
+        None => return Ok(None),
+    };
+    if extract_clean_type(&raw) == "FWeakObjectPtr" {
+        return Ok(Some("UObject".to_string()));
+    }
+    match split_template_args(&raw) {
+        Some((wrapper, args)) if SMART_POINTER_TEMPLATES.contains(&wrapper.as_str()) => Ok(args.first().map(|arg| extract_clean_type(arg))),
+        _ => Ok(None),
+    }
+}
+
+/// UClass* 引数 (X::StaticClass()) の型を返すメンバー関数
+const CLASS_ARGUMENT_RETURNING_METHODS: &[&str] = &[
+    "GetComponentByClass", "FindComponentByClass",
+    "CreateDefaultSubobject", "CreateEditorOnlyDefaultSubobject", "CreateOptionalDefaultSubobject",
+];
+
+/// 非テンプレート版で UClass* 引数 (NewObject(this, UMyComp::StaticClass())) の型を返す関数
+const CLASS_ARGUMENT_RETURNING_FUNCTIONS: &[&str] = &[
+    "NewObject", "CreateDefaultSubobject", "CreateEditorOnlyDefaultSubobject", "CreateOptionalDefaultSubobject",
+];
+
+fn resolve_template_member_call(conn: &Connection, obj_type: &str, name: &str, args: &[String]) -> anyhow::Result<Option<String>> {
+    let template_arg = args.first().map(|arg| extract_clean_type(arg));
+    if TEMPLATE_ARG_RETURNING_METHODS.contains(&name) {
+        return Ok(template_arg);
+    }
+    match find_member_return_type(conn, obj_type, name)? {
+        Some(rt) if is_template_placeholder(&rt) => Ok(template_arg),
+        Some(rt) => Ok(Some(rt)),
+        None => Ok(None),
+    }
+}
+
+fn is_template_placeholder(type_name: &str) -> bool {
+    type_name.len() == 1 || TEMPLATE_PARAMETER_NAMES.contains(&type_name)
+}
+
+/// エンジンのテンプレート関数で使われる型パラメータ名 (template <class TSubsystemClass> TSubsystemClass* GetSubsystem())
+const TEMPLATE_PARAMETER_NAMES: &[&str] = &[
+    "TClass", "ComponentType", "TSubsystemClass", "TReturnType", "TUserClass", "TObjectClass", "UserClass", "ObjectType",
+];
+
+/// `Func(UMyComp::StaticClass())` の UMyComp (NewObject(Outer, UMyComp::StaticClass()) のように先頭以外の引数も見る)
+fn static_class_argument(call_node: Node, content: &str) -> Option<String> {
+    let args = call_node.child_by_field_name("arguments")?;
+    let re = cached_regex!(r"^([A-Za-z0-9_:]+)::StaticClass\s*\(\s*\)$");
+    let mut cursor = args.walk();
+    let found = args.named_children(&mut cursor).find_map(|arg| {
+        re.captures(get_node_text(&arg, content).trim()).and_then(|cap| cap.get(1).map(|m| extract_clean_type(m.as_str())))
+    });
+    found
+}
+
+fn function_pointer_return_type(callee: Node, root: &Node, content: &str, cursor_row: usize) -> anyhow::Result<Option<String>> {
+    let mut node = callee;
+    loop {
+        match node.kind() {
+            "parenthesized_expression" => match node.named_child(0) {
+                Some(inner) => node = inner,
+                None => return Ok(None),
+            },
+            "pointer_expression" if node.child(0).map_or(false, |op| op.kind() == "*") => match node.child_by_field_name("argument") {
+                Some(inner) => node = inner,
+                None => return Ok(None),
+            },
+            "identifier" => break,
+            _ => return Ok(None),
+        }
+    }
+    let name = get_node_text(&node, content).trim();
+    let Some((t_node, d_node)) = find_declaration(name, root, content, cursor_row)? else { return Ok(None) };
+    // FMyStruct (*FuncPtr)(int32) の宣言子は function_declarator(parenthesized_declarator(pointer_declarator))
+    let is_function_pointer = find_descendant_by_kind(d_node, "function_declarator")
+        .and_then(|f| f.child_by_field_name("declarator"))
+        .map_or(false, |d| d.kind() == "parenthesized_declarator");
+    if !is_function_pointer { return Ok(None); }
+    Ok(Some(extract_clean_type(get_node_text(&t_node, content))))
+}
+
+const CALLABLE_TEMPLATES: &[&str] = &["TFunction", "TUniqueFunction", "TFunctionRef"];
+
+// TFunction<FFoo*(int32)> -> "FFoo*(int32)"
+fn callable_signature(raw: &str) -> Option<String> {
+    let (wrapper, args) = split_template_args(raw)?;
+    if !CALLABLE_TEMPLATES.contains(&wrapper.as_str()) { return None; }
+    args.into_iter().next().filter(|sig| sig.contains('('))
+}
+
+// TFunction<FFoo*(int32)> -> "FFoo"。void を返すものは None
+fn callable_return_type(raw: &str) -> Option<String> {
+    let signature = callable_signature(raw)?;
+    let return_type = extract_clean_type(&signature[..signature.find('(')?]);
+    if return_type.is_empty() || return_type == "void" { return None; }
+    Some(return_type)
+}
+
+/// クラス (と祖先) の TFunction 型のメンバー変数を呼んだときの戻り値型。関数メンバーは対象外
+fn callable_member_return_type(conn: &Connection, class_name: &str, member_name: &str) -> anyhow::Result<Option<String>> {
+    let resolved_class = resolve_typedef(conn, class_name)?;
+    let mut stmt = conn.prepare_cached("SELECT m.return_type FROM members m JOIN classes c ON m.class_id = c.id WHERE c.name = ? AND m.name = ? AND m.type != 'function' LIMIT 1")?;
+    for cls in class_ancestors(conn, &resolved_class)? {
+        let mut rows = stmt.query([&cls, member_name])?;
+        if let Some(row) = rows.next()? {
+            return Ok(row.get::<_, Option<String>>(0)?.and_then(|rt| callable_return_type(&rt)));
+        }
+    }
+    Ok(None)
+}
+
+/// TFunction などはクラスとして索引されていないので、呼び出し (operator()) のシグネチャと API を候補にする
+fn callable_items(class_name: &str, raw: Option<&str>) -> Vec<CompletionItem> {
+    let signature = raw.and_then(callable_signature).unwrap_or_default();
+    let mut methods: Vec<(&str, &str)> = vec![("operator()", signature.as_str()), ("CheckCallable", "void")];
+    if class_name != "TFunctionRef" {
+        methods.extend([("IsSet", "bool"), ("Reset", "void")]);
+    }
+    methods.into_iter().map(|(name, detail)| {
+        let mut item = CompletionItem::new(name, 2, detail);
+        item.data = Some(CompletionItemData { class: class_name.to_string(), ..Default::default() });
+        item
+    }).collect()
+}
+
+/// GetDefaultSubobjectByName(TEXT("Mesh")) の名前と一致するメンバー変数があればその型 (subobject_name_heuristic)
+fn resolve_subobject_by_name(conn: &Connection, call_node: Node, classes: &[&str], content: &str) -> anyhow::Result<Option<String>> {
+    if !active_config(|c| c.subobject_name_heuristic) { return Ok(None); }
+    let args = match call_node.child_by_field_name("arguments") { Some(a) => a, None => return Ok(None) };
+    let re = cached_regex!(r#""([A-Za-z0-9_]+)""#);
+    let name = match re.captures(get_node_text(&args, content)).and_then(|c| c.get(1)) {
+        Some(m) => m.as_str().to_string(),
+        None => return Ok(None),
+    };
+    let mut candidates: Vec<String> = classes.iter().map(|c| c.to_string()).collect();
+    if let Some(current_class) = get_enclosing_class_name(&call_node, content) {
+        candidates.push(current_class);
+    }
+    for cls in candidates {
+        if let Some(t) = find_member_return_type(conn, &cls, &name)? {
+            tracing::debug!("GetDefaultSubobjectByName(\"{}\") matched member -> '{}'", name, t);
+            return Ok(Some(t));
+        }
+    }
+    Ok(None)
+}
+
+/// `Name<Arg1, Arg2>` -> ("Name", ["Arg1", "Arg2"])
+fn split_template_call(node: Node, content: &str) -> Option<(String, Vec<String>)> {
+    let name_node = node.child_by_field_name("name")?;
+    let args_node = node.child_by_field_name("arguments")?;
+    let name = get_node_text(&name_node, content).trim().to_string();
+    let (_, args) = split_template_args(&format!("{}{}", name, get_node_text(&args_node, content)))?;
+    Some((name, args))
+}
+
+// A::B::C の C のスコープノードから、外側の qualified_identifier をたどって "A::B" のような全体を返す
+fn full_scope_text(scope_node: Node, content: &str) -> String {
+    let mut parts = vec![get_node_text(&scope_node, content).trim().to_string()];
+    let mut curr = scope_node.parent();
+    while let Some(qualified) = curr.filter(|q| q.kind() == "qualified_identifier") {
+        match qualified.parent() {
+            Some(outer) if outer.kind() == "qualified_identifier" && outer.child_by_field_name("name") == Some(qualified) => {
+                if let Some(outer_scope) = outer.child_by_field_name("scope") {
+                    parts.insert(0, get_node_text(&outer_scope, content).trim().to_string());
+                }
+                curr = Some(outer);
+            }
+            _ => break,
+        }
+    }
+    parts.join("::")
+}
+
+/// namespace (またはネスト元クラス) が prefix に一致する leaf 型
+// 一致すれば修飾名 ("Outer::Inner") のまま返し、メンバー検索で同名の別クラスと区別できるようにする
+fn resolve_scope_chain(conn: &Connection, prefix: &str, leaf: &str) -> anyhow::Result<Option<String>> {
+    let leaf = leaf.trim();
+    let prefix: String = prefix.split("::").map(|s| s.trim()).collect::<Vec<_>>().join("::");
+    if qualified_class_rows(conn, &prefix, leaf)?.is_empty() { return Ok(None); }
+    Ok(Some(format!("{}::{}", prefix, leaf)))
+}
+
+/// ネストした型は name = "Inner", namespace = "Outer" (または "NS::Outer") で索引されている。
+/// 修飾名のまま name に入っている DB もあるので "Outer::Inner" そのものも探す
+fn qualified_class_rows(conn: &Connection, prefix: &str, leaf: &str) -> anyhow::Result<Vec<(i64, Option<String>)>> {
+    let qualified = format!("{}::{}", prefix, leaf);
+    let mut stmt = conn.prepare_cached(
+        "SELECT c.id, f.path FROM classes c LEFT JOIN members m ON c.id = m.class_id LEFT JOIN files f ON c.file_id = f.id
+         WHERE (c.name = ?1 AND (c.namespace = ?2 OR c.namespace LIKE '%::' || ?2)) OR c.name = ?3
+         GROUP BY c.id ORDER BY COUNT(m.id) DESC",
+    )?;
+    let rows = stmt.query_map([leaf, prefix, qualified.as_str()], |row| Ok((row.get(0)?, row.get(1)?)))?.collect::<Result<_, _>>()?;
+    Ok(rows)
+}
+
+// Super -> 1, Super::Super -> 2, ThisClass -> 0
+fn super_depth(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    for segment in text.split("::").map(|s| s.trim()) {
+        match segment {
+            "Super" => depth += 1,
+            "ThisClass" if depth == 0 => {}
+            _ => return None,
+        }
+    }
+    Some(depth)
+}
+
+/// 囲んでいるメソッドのクラスから最初の親を depth 段たどる (UE の Super typedef)
+/// 親が記録されていなければ None (Super:: の候補は空になる)
+fn resolve_super_class(conn: &Connection, node: &Node, content: &str, depth: usize) -> anyhow::Result<Option<String>> {
+    let Some(mut current) = get_enclosing_class_name(node, content) else { return Ok(None) };
+    // inheritance は宣言順に入るので id の最小が直近の基底クラス。古い DB 用に classes.base_class も見る
+    let mut stmt = conn.prepare(
+        "SELECT parent FROM (
+             SELECT i.parent_name AS parent, 0 AS source, i.id AS ord FROM inheritance i JOIN classes c ON i.child_id = c.id WHERE c.name = ?1
+             UNION ALL
+             SELECT base_class, 1, id FROM classes WHERE name = ?1 AND base_class IS NOT NULL AND base_class != ''
+         ) ORDER BY source, ord LIMIT 1",
+    )?;
+    for _ in 0..depth {
+        let mut rows = stmt.query([&current])?;
+        match rows.next()? {
+            Some(row) => current = extract_clean_type(&row.get::<_, String>(0)?),
+            None => return Ok(None),
+        }
+    }
+    tracing::debug!("Resolved Super (depth {}) to '{}'", depth, current);
+    Ok(Some(current))
+}
+
+// ラムダ式は function_definition ではないのでそのまま通過し、外側のメソッドのクラスを返す
+fn get_enclosing_class_name(start_node: &Node, content: &str) -> Option<String> {
+    let mut curr_opt = Some(*start_node);
+    let mut in_error = false;
+    while let Some(curr) = curr_opt {
+        in_error |= curr.is_error();
+        let kind = curr.kind();
+        if kind == "class_specifier" || kind == "struct_specifier" || 
+           kind == "unreal_class_declaration" || kind == "unreal_struct_declaration" {
+            if let Some(name) = class_declaration_name(&curr, content) {
+                tracing::debug!("Enclosing class found via specifier: '{}'", name);
+                return Some(name);
+            }
+        } else if kind == "lambda_expression" {
+            // [this]() { ... } / [&]() { ... } の本体ではラムダを定義したメソッドのクラスを探し続ける
+        } else if kind == "function_definition" {
+            if let Some(decl) = curr.child_by_field_name("declarator") {
+                if let Some(qualified) = find_qualified_identifier(decl) {
+                    if let Some(scope) = qualified.child_by_field_name("scope") {
+                        let text = get_node_text(&scope, content).trim().trim_end_matches("::");
+                        let clean = extract_clean_type(text);
+                        tracing::debug!("Enclosing class found via qualified method: '{}'", clean);
+                        return Some(clean);
+                    }
+                }
+            }
+        }
+        curr_opt = curr.parent();
+    }
+    // ヘッダーのインラインメソッドを入力中でクラス本体が ERROR になった場合は、閉じていない class/struct { をテキストから探す
+    if in_error {
+        return unclosed_class_before(content, start_node.start_byte());
+    }
+    None
+}
+
+/// class MYGAME_API AMyActor のようにエクスポートマクロが付くと name が MYGAME_API になったり
+/// 付かなかったりするので、その場合は本体より前のテキストからクラス名を取る
+fn class_declaration_name(node: &Node, content: &str) -> Option<String> {
+    let name = node.child_by_field_name("name").map(|n| get_node_text(&n, content).trim().to_string());
+    if let Some(name) = name.filter(|n| !n.is_empty() && !n.ends_with("_API")) {
+        return Some(name);
+    }
+    let head_end = node.child_by_field_name("body").map_or(node.end_byte(), |b| b.start_byte());
+    let head = content.get(node.start_byte()..head_end)?;
+    let re = cached_regex!(r"\b(?:class|struct)\s+(?:[A-Z0-9_]+_API\s+)?([A-Za-z_]\w*)");
+    let name = re.captures(head)?.get(1)?.as_str();
+    if name.ends_with("_API") { return None; }
+    Some(name.to_string())
+}
+
+fn unclosed_class_before(content: &str, offset: usize) -> Option<String> {
+    let before = content.get(..offset)?;
+    let re = cached_regex!(r"\b(?:class|struct)\s+(?:[A-Z0-9_]+_API\s+)?([A-Za-z_]\w*)\s*(?:final\s*)?(?::[^;{]*)?\{");
+    let candidates: Vec<(String, usize)> = re.captures_iter(before)
+        .filter_map(|cap| Some((cap.get(1)?.as_str().to_string(), cap.get(0)?.end())))
+        .collect();
+    for (name, body_start) in candidates.into_iter().rev() {
+        let mut depth = 1i32;
+        for ch in before[body_start..].chars() {
+            match ch {
+                '{' => depth += 1,
+                '}' => depth -= 1,
+                _ => {}
+            }
+            if depth == 0 { break; }
+        }
+        if depth > 0 {
+            tracing::debug!("Enclosing class found via unclosed body: '{}'", name);
+            return Some(name);
+        }
+    }
+    None
+}
+
+// 関数名の宣言子だけをたどる (引数の型 FFoo::EMode や戻り値の型は見ない)
+fn find_qualified_identifier(node: Node) -> Option<Node> {
+    let mut curr = node;
+    loop {
+        match curr.kind() {
+            "qualified_identifier" => return Some(curr),
+            "function_declarator" | "pointer_declarator" | "reference_declarator" | "parenthesized_declarator" => {
+                curr = curr.child_by_field_name("declarator").or_else(|| curr.named_child(0))?;
+            }
+            _ => return None,
+        }
+    }
+}
+
+// Scope::Member の型 (Scope が Super / Super::Super なら親クラスのメンバー)
+fn resolve_qualified_member(conn: &Connection, node: Node, content: &str) -> anyhow::Result<Option<String>> {
+    let Some((scope, name)) = split_qualified_identifier(node, content) else { return Ok(None) };
+    let full_scope = get_node_text(&node, content).trim().rsplit_once("::").map_or("", |(s, _)| s);
+    let owner = match super_depth(full_scope) {
+        Some(depth) => match resolve_super_class(conn, &node, content, depth)? {
+            Some(cls) => cls,
+            None => return Ok(None),
+        },
+        None => scope,
+    };
+    find_member_return_type(conn, &owner, &name)
+}
+
+// A::B::Func<UFoo> -> ("B", "Func", ["UFoo"])
+fn qualified_template_call(node: Node, content: &str) -> Option<(String, String, Vec<String>)> {
+    let mut name = node.child_by_field_name("name")?;
+    let mut scope = node.child_by_field_name("scope")?;
+    while name.kind() == "qualified_identifier" {
+        scope = name.child_by_field_name("scope")?;
+        name = name.child_by_field_name("name")?;
+    }
+    if name.kind() != "template_function" { return None; }
+    let (func, args) = split_template_call(name, content)?;
+    Some((extract_clean_type(get_node_text(&scope, content)), func, args))
+}
+
+// A::B::Func -> ("B", "Func")
+fn split_qualified_identifier(node: Node, content: &str) -> Option<(String, String)> {
+    let mut scope = node.child_by_field_name("scope")?;
+    let mut name = node.child_by_field_name("name")?;
+    while name.kind() == "qualified_identifier" {
+        scope = name.child_by_field_name("scope")?;
+        name = name.child_by_field_name("name")?;
+    }
+    let scope_text = extract_clean_type(get_node_text(&scope, content));
+    let name_text = get_node_text(&name, content).trim().to_string();
+    if scope_text.is_empty() || name_text.is_empty() { return None; }
+    Some((scope_text, name_text))
+}
+
+fn resolve_typedef(conn: &Connection, type_name: &str) -> anyhow::Result<String> {
+    let current = extract_clean_type(type_name);
+    if current.is_empty() || current == "T" || current == "void" { return Ok(current); }
+    if let Some(cached) = completion_cache::get_resolved_type(&current) {
+        trace_step(|| json!({ "step": "typedef", "from": current, "to": cached, "cached": true }));
+        return Ok(cached);
+    }
+    let chain = typedef_chain(conn, &current)?;
+    let resolved = chain.last().cloned().unwrap_or_else(|| current.clone());
+    completion_cache::put_resolved_type(&current, &resolved, source_files(conn, &chain)?);
+    trace_step(|| json!({ "step": "typedef", "from": current, "to": resolved, "cached": false }));
+    Ok(resolved)
+}
+
+/// 別名から最終的な型までの経路 (["FMyAlias", "FOtherAlias", "FFoo"])。先頭は指定した名前
+pub fn resolve_typedef_chain(conn: &Connection, name: &str) -> anyhow::Result<Vec<String>> {
+    let clean = extract_clean_type(name);
+    if clean.is_empty() { return Ok(Vec::new()); }
+    typedef_chain(conn, &clean)
+}
+
+fn typedef_chain(conn: &Connection, start: &str) -> anyhow::Result<Vec<String>> {
+    let mut chain = vec![start.to_string()];
+    // using A = B; using B = A; のような循環は直前に解決できた名前で止める
+    let max_steps = active_config(|c| c.max_typedef_steps).unwrap_or(DEFAULT_MAX_TYPEDEF_STEPS);
+    let mut visited: HashMap<String, bool> = HashMap::new();
+    visited.insert(start.to_string(), true);
+    let mut stmt = conn.prepare("SELECT base_class FROM classes WHERE name = ? AND symbol_type = 'typedef' ORDER BY (CASE WHEN base_class IS NOT NULL AND base_class != '' THEN 0 ELSE 1 END) ASC LIMIT 1")?;
+    for _ in 0..max_steps {
+        let current = chain.last().cloned().unwrap_or_default();
+        let mut rows = stmt.query([&current])?;
+        let Some(row) = rows.next()? else { break };
+        let Some(base) = row.get::<_, Option<String>>(0)? else { break };
+        let clean = extract_clean_type(&base);
+        if clean.is_empty() || visited.contains_key(&clean) {
+            if !clean.is_empty() && clean != current {
+                tracing::warn!("Typedef cycle detected at '{}' while resolving '{}'", clean, start);
+            }
+            break;
+        }
+        visited.insert(clean.clone(), true);
+        chain.push(clean);
+    }
+    // FTransform / FVector などは UE_DECLARE_LWC_TYPE マクロで TTransform<double> 等の別名として宣言される
+    if chain.len() == 1 {
+        if let Some(lwc) = lwc_template_name(start) {
+            let mut class_stmt = conn.prepare("SELECT 1 FROM classes WHERE name = ? AND symbol_type != 'typedef' LIMIT 1")?;
+            if !class_stmt.exists([start])? {
+                chain.push(lwc.to_string());
+            }
+        }
+    }
+    Ok(chain)
+}
+
+const DEFAULT_MAX_TYPEDEF_STEPS: usize = 32;
+
+fn lwc_template_name(name: &str) -> Option<&'static str> {
+    const LWC_TYPES: &[(&str, &str)] = &[
+        ("Vector2", "TVector2"), ("Vector4", "TVector4"), ("Vector", "TVector"), ("Rotator", "TRotator"),
+        ("Quat", "TQuat"), ("Transform", "TTransform"), ("Matrix", "TMatrix"), ("Plane", "TPlane"),
+        ("Box2", "TBox2"), ("Box", "TBox"), ("Sphere", "TSphere"), ("Ray", "TRay"),
+    ];
+    let rest = name.strip_prefix('F')?;
+    LWC_TYPES.iter().find_map(|(base, template)| {
+        let suffix = rest.strip_prefix(base)?;
+        ["", "D", "f", "d", "2f", "2d", "3f", "3d", "4f", "4d", "44f", "44d"].contains(&suffix).then_some(*template)
+    })
+}
+
+// 戻り値の bool はタイムアウトで走査を打ち切ったかどうか
+fn fetch_members_recursive(conn: &Connection, class_name: &str, access_filter: AccessFilter, deadline: Option<Instant>) -> anyhow::Result<(Vec<CompletionItem>, bool)> {
+    let mut result = Vec::new();
+    // (クラス名, 継承の深さ)。深さは sortText に使い、自身のメンバーを祖先のメンバーより上に出す
+    // 幅優先でたどるので、浅い祖先はより深い経路からも届く場合でも先に (小さい深さで) 処理される
+    let mut queue = VecDeque::from([(class_name.to_string(), 0usize)]);
+    let mut visited = HashMap::new();
+    // 名前 + 引数で重複を判定し、先に見つかった (最も派生側の) オーバーライドだけを残す。引数の異なるオーバーロードは残る
+    let mut seen_signatures: HashMap<(String, String), bool> = HashMap::new();
+    while let Some((mut current, depth)) = queue.pop_front() {
+        if visited.contains_key(&current) { continue; }
+        if is_past(deadline) {
+            tracing::warn!("Completion timed out in '{}' hierarchy at '{}', returning {} partial results", class_name, current, result.len());
+            return Ok((result, true));
+        }
+        visited.insert(current.clone(), true);
+        // Outer::Inner は外側のスコープまで一致する行を優先し、無ければ短い名前で探す
+        let mut class_rows = match current.rsplit_once("::") {
+            Some((prefix, leaf)) => {
+                let rows = qualified_class_rows(conn, prefix, leaf)?;
+                current = leaf.to_string();
+                visited.insert(current.clone(), true);
+                rows
+            }
+            None => Vec::new(),
+        };
+        
+        // 前方宣言/定義/実装ファイルで classes の行が分かれることがあるので、同名の行をすべてまとめる
+        if class_rows.is_empty() {
+            let mut stmt = conn.prepare_cached("SELECT c.id, f.path FROM classes c LEFT JOIN members m ON c.id = m.class_id LEFT JOIN files f ON c.file_id = f.id WHERE c.name = ? GROUP BY c.id ORDER BY COUNT(m.id) DESC")?;
+            class_rows = stmt.query_map([&current], |row| Ok((row.get(0)?, row.get(1)?)))?.collect::<Result<_, _>>()?;
+        }
+        if class_rows.is_empty() {
+            let mut fallback_stmt = conn.prepare_cached("SELECT c.id, f.path FROM classes c LEFT JOIN members m ON c.id = m.class_id LEFT JOIN files f ON c.file_id = f.id WHERE LOWER(c.name) = LOWER(?) GROUP BY c.id ORDER BY COUNT(m.id) DESC LIMIT 1")?;
+            class_rows = fallback_stmt.query_map([&current], |row| Ok((row.get(0)?, row.get(1)?)))?.collect::<Result<_, _>>()?;
+        }
+        // デリゲート型は宣言マクロ (DECLARE_DYNAMIC_MULTICAST_DELEGATE_OneParam など) を基底として索引されている
+        if class_rows.is_empty() && current.starts_with("DECLARE_") {
+            result.extend(delegate_api_items(&current).into_iter().map(|mut item| {
+                item.sort_text = Some(format!("{:02}{}", depth, item.label));
+                item
+            }));
+            continue;
+        }
+        // FString などのコア型が索引されていなければ組み込みの一覧を使う
+        if class_rows.is_empty() {
+            if let Some(items) = core_string_items(&current) {
+                result.extend(items.into_iter().map(|mut item| {
+                    item.sort_text = Some(format!("{:02}{}", depth, item.label));
+                    item
+                }));
+                continue;
+            }
+        }
+        // enum と同名のクラスや構造体があっても EMyEnum:: では列挙子だけを出し、継承もたどらない
+        let mut enum_stmt = conn.prepare_cached("SELECT 1 FROM classes WHERE id = ? AND symbol_type IN ('enum', 'UENUM')")?;
+        let mut enum_ids = HashMap::new();
+        for (class_id, _) in &class_rows {
+            if enum_stmt.exists([class_id])? { enum_ids.insert(*class_id, true); }
+        }
+        let is_enum = !enum_ids.is_empty();
+        if is_enum {
+            class_rows.retain(|(class_id, _)| enum_ids.contains_key(class_id));
+        }
+        // 先の行 (メンバーが多い方) にある名前は後の行では重複として扱う (オーバーロードは同じ行内なので残る)
+        let mut seen_labels: HashMap<String, bool> = HashMap::new();
+        for (class_id, file) in class_rows {
+            let source = CompletionItemData { class: current.clone(), file, ..Default::default() };
+            let mut row_items = Vec::new();
+            let mut mem_stmt = conn.prepare_cached("SELECT name, type, return_type, access, is_static, detail, flags, category FROM members WHERE class_id = ?")?;
+            let mem_rows = mem_stmt.query_map([class_id], |row| {
+                let m_name: String = row.get(0)?;
+                let m_type: String = row.get(1)?;
+                let r_type: Option<String> = row.get(2)?;
+                let is_static = row.get::<_, Option<bool>>(4)?.unwrap_or(false);
+                let detail: Option<String> = row.get(5)?;
+                let flags = row.get::<_, Option<String>>(6)?.unwrap_or_default();
+                let category: Option<String> = row.get(7)?;
+                let access = row.get::<_, Option<String>>(3)?.unwrap_or_else(|| "public".to_string());
+                // annotate_access ではアクセスできないメンバーも印を付けて残す
+                let inaccessible = !access_filter.allows(&access);
+                if inaccessible && !active_config(|c| c.annotate_access) { return Ok(None); }
+                let kind = map_kind(&m_type, &m_name, &current);
+                // operator[] などは型解決用に索引しているだけで、Obj. の後に入力するものではない。デストラクターも明示的には呼ばない
+                if kind == 24 || m_name.starts_with('~') { return Ok(None); }
+                // 基底クラスのコンストラクターは派生クラスのレシーバーからは呼べない
+                if kind == 4 && depth > 0 { return Ok(None); }
+                let mut item = CompletionItem::new(&m_name, kind, &r_type.unwrap_or_default());
+                if inaccessible {
+                    item.detail = item.detail.map(|d| format!("{} {}", access, d).trim_end().to_string());
+                }
+                if m_type == "function" && active_config(|c| c.snippets) {
+                    item.insert_text = function_snippet(&m_name, detail.as_deref().unwrap_or(""));
+                    item.insert_text_format = Some(2);
+                }
+                item.documentation = Some(detail.unwrap_or_default());
+                if let Some(category) = category.as_ref().filter(|_| active_config(|c| c.category_label_details)) {
+                    item.label_details = Some(CompletionItemLabelDetails { detail: None, description: Some(category.clone()) });
+                }
+                item.data = Some(CompletionItemData { is_static, flags, category, access: Some(access), ..source.clone() });
+                Ok(Some(item))
+            })?;
+            if !is_enum {
+                for m in mem_rows { row_items.extend(m?); }
+            }
+            // コンストラクターは通常のメンバーより後ろに並べる
+            for item in row_items.iter_mut() {
+                item.sort_text = Some(format!("{:02}{}", if item.kind == 4 { 99 } else { depth }, item.label));
+            }
+            // 列挙子は名前順ではなく宣言順に並べる
+            for (index, value) in load_enum_values(conn, class_id)?.into_iter().enumerate() {
+                let mut item = CompletionItem::new(&value.name, 20, &value.detail());
+                item.sort_text = Some(format!("{:02}{:04}", depth, index));
+                // UMETA(DisplayName) と列挙子のコメント
+                let documentation: Vec<String> = [value.display_name.clone(), value.documentation.clone()].into_iter().flatten().collect();
+                if !documentation.is_empty() { item.documentation = Some(documentation.join("\n\n")); }
+                item.data = Some(CompletionItemData { flags: value.flags.clone(), ..source.clone() });
+                row_items.push(item);
+            }
+            row_items.retain(|item| !seen_labels.contains_key(&item.label));
+            for item in &row_items { seen_labels.insert(item.label.clone(), true); }
+            row_items.retain(|item| {
+                let signature = (item.label.clone(), parameter_signature(item.documentation.as_deref().unwrap_or("")));
+                seen_signatures.insert(signature, true).is_none()
+            });
+            if active_config(|c| c.lazy_resolve) {
+                for item in row_items.iter_mut() {
+                    item.detail = None;
+                    item.documentation = None;
+                    if let Some(data) = item.data.as_mut() {
+                        data.class_id = Some(class_id);
+                        data.name = Some(item.label.clone());
+                    }
+                }
+            }
+            result.extend(row_items);
+            if is_enum { continue; }
+
+            let mut parent_stmt = conn.prepare_cached("SELECT parent_name FROM inheritance WHERE child_id = ? ORDER BY id")?;
+            let p_rows = parent_stmt.query_map([class_id], |row| Ok(row.get::<_, String>(0)?))?;
+            for p in p_rows { queue.push_back((p?, depth + 1)); }
+        }
+    }
+    Ok((result, false))
+}
+
+/// デリゲート宣言マクロの種類 (シングル/マルチキャスト, ダイナミック) に応じた TDelegate / TMulticastDelegate の API
+fn delegate_api_items(macro_name: &str) -> Vec<CompletionItem> {
+    let multicast = macro_name.contains("MULTICAST") || macro_name.starts_with("DECLARE_EVENT");
+    let dynamic = macro_name.contains("DYNAMIC");
+    let methods: &[(&str, &str)] = match (multicast, dynamic) {
+        (true, true) => &[
+            ("Broadcast", "void"), ("IsBound", "bool"), ("Clear", "void"), ("AddDynamic", "void"), ("AddUniqueDynamic", "void"),
+            ("RemoveDynamic", "void"), ("IsAlreadyBound", "bool"), ("Contains", "bool"), ("RemoveAll", "void"),
+        ],
+        (true, false) => &[
+            ("Broadcast", "void"), ("IsBound", "bool"), ("IsBoundToObject", "bool"), ("Clear", "void"), ("Add", "FDelegateHandle"),
+            ("AddUObject", "FDelegateHandle"), ("AddLambda", "FDelegateHandle"), ("AddWeakLambda", "FDelegateHandle"), ("AddSP", "FDelegateHandle"),
+            ("AddRaw", "FDelegateHandle"), ("AddStatic", "FDelegateHandle"), ("AddUFunction", "FDelegateHandle"), ("Remove", "bool"), ("RemoveAll", "int32"),
+        ],
+        (false, true) => &[
+            ("Execute", ""), ("ExecuteIfBound", "bool"), ("IsBound", "bool"), ("Unbind", "void"), ("Clear", "void"),
+            ("BindDynamic", "void"), ("BindUFunction", "void"),
+        ],
+        (false, false) => &[
+            ("Execute", ""), ("ExecuteIfBound", "bool"), ("IsBound", "bool"), ("IsBoundToObject", "bool"), ("Unbind", "void"),
+            ("BindUObject", "void"), ("BindLambda", "void"), ("BindWeakLambda", "void"), ("BindSP", "void"), ("BindRaw", "void"),
+            ("BindStatic", "void"), ("BindUFunction", "void"),
+        ],
+    };
+    methods.iter().map(|(name, return_type)| {
+        let mut item = CompletionItem::new(name, 2, return_type);
+        item.data = Some(CompletionItemData { class: macro_name.to_string(), ..Default::default() });
+        item
+    }).collect()
+}
+
+/// エンジンを索引していない環境でも補完できるように、FString/FName/FText の主なメソッドを持っておく
+/// (名前, 戻り値の型, static か)
+fn core_string_members(class_name: &str) -> Option<&'static [(&'static str, &'static str, bool)]> {
+    let members: &[(&str, &str, bool)] = match class_name {
+        "FString" => &[
+            ("Len", "int32", false), ("IsEmpty", "bool", false), ("Empty", "void", false), ("Reset", "void", false),
+            ("Append", "FString&", false), ("AppendChar", "FString&", false), ("Contains", "bool", false), ("Find", "int32", false),
+            ("StartsWith", "bool", false), ("EndsWith", "bool", false), ("Equals", "bool", false), ("Compare", "int32", false),
+            ("Left", "FString", false), ("Right", "FString", false), ("Mid", "FString", false), ("LeftChop", "FString", false),
+            ("RightChop", "FString", false), ("ToUpper", "FString", false), ("ToLower", "FString", false), ("TrimStart", "FString", false),
+            ("TrimEnd", "FString", false), ("TrimStartAndEnd", "FString", false), ("Replace", "FString", false),
+            ("ReplaceInline", "int32", false), ("Split", "bool", false), ("ParseIntoArray", "int32", false), ("Reverse", "FString", false),
+            ("IsNumeric", "bool", false), ("GetCharArray", "TArray<TCHAR>&", false), ("InsertAt", "void", false), ("RemoveAt", "void", false),
+            ("Printf", "FString", true), ("Format", "FString", true), ("FromInt", "FString", true), ("SanitizeFloat", "FString", true),
+            ("Join", "FString", true), ("Chr", "FString", true),
+        ],
+        "FName" => &[
+            ("ToString", "FString", false), ("IsNone", "bool", false), ("IsValid", "bool", false), ("GetNumber", "int32", false),
+            ("SetNumber", "void", false), ("GetPlainNameString", "FString", false), ("GetComparisonIndex", "FNameEntryId", false),
+            ("IsEqual", "bool", false), ("Compare", "int32", false), ("LexicalLess", "bool", false), ("FastLess", "bool", false),
+        ],
+        "FText" => &[
+            ("ToString", "FString&", false), ("IsEmpty", "bool", false), ("IsEmptyOrWhitespace", "bool", false), ("EqualTo", "bool", false),
+            ("CompareTo", "int32", false), ("ToUpper", "FText", false), ("ToLower", "FText", false), ("IsNumeric", "bool", false),
+            ("IsCultureInvariant", "bool", false), ("IsTransient", "bool", false), ("BuildSourceString", "FString", false),
+            ("FromString", "FText", true), ("FromName", "FText", true), ("FromStringTable", "FText", true), ("AsCultureInvariant", "FText", true),
+            ("AsNumber", "FText", true), ("AsPercent", "FText", true), ("AsCurrency", "FText", true), ("AsDate", "FText", true),
+            ("AsTime", "FText", true), ("AsDateTime", "FText", true), ("Format", "FText", true), ("FormatNamed", "FText", true),
+            ("FormatOrdered", "FText", true), ("Join", "FText", true), ("GetEmpty", "const FText&", true),
+        ],
+        _ => return None,
+    };
+    Some(members)
+}
+
+fn core_string_items(class_name: &str) -> Option<Vec<CompletionItem>> {
+    Some(core_string_members(class_name)?.iter().map(|(name, return_type, is_static)| {
+        let mut item = CompletionItem::new(name, 2, return_type);
+        item.data = Some(CompletionItemData { class: class_name.to_string(), is_static: *is_static, ..Default::default() });
+        item
+    }).collect())
+}
+
+fn is_operator_name(name: &str) -> bool {
+    name.strip_prefix("operator").map_or(false, |rest| rest.starts_with(' ') || (!rest.is_empty() && !rest.starts_with(|c: char| c.is_alphanumeric() || c == '_')))
+}
+
+// 関数のうち operator / コンストラクター (クラスと同名) / デストラクター (~クラス名) は名前で区別する
+fn map_kind(k: &str, name: &str, class_name: &str) -> i64 {
+    match k {
+        "function" if is_operator_name(name) => 24,
+        "function" if name == class_name || name.strip_prefix('~') == Some(class_name) => 4,
+        "function" => 2,
+        "variable" | "property" => 5,
+        "enum_item" => 20,
+        _ => 1,
+    }
+}
+
+fn is_known_type(conn: &Connection, name: &str) -> anyhow::Result<bool> {
+    let clean = extract_clean_type(name);
+    if clean.is_empty() { return Ok(false); }
+    // 組み込みの一覧を持つコア型は索引の有無やインクルードに関係なく既知
+    if core_string_members(&clean).is_some() { return Ok(true); }
+    // include_scope: インクルードの情報があれば、そのヘッダーで宣言された型だけを既知とする
+    let includes = active_config(|c| if c.include_scope { c.includes.clone() } else { None }).unwrap_or_default();
+    if !includes.is_empty() {
+        let conditions = vec!["REPLACE(f.path, '\\', '/') LIKE ? ESCAPE '\\'"; includes.len()].join(" OR ");
+        let sql = format!("SELECT 1 FROM classes c JOIN files f ON c.file_id = f.id WHERE LOWER(c.name) = LOWER(?) AND ({}) LIMIT 1", conditions);
+        // My_Actor.h の _ が任意の 1 文字にならないよう LIKE の特殊文字をエスケープする
+        let patterns: Vec<String> = includes.iter().map(|inc| {
+            let path = inc.replace('\\', "/");
+            let escaped: String = path.trim_start_matches('/').chars()
+                .map(|c| if c == '_' || c == '%' { format!("\\{}", c) } else { c.to_string() })
+                .collect();
+            format!("%/{}", escaped)
+        }).collect();
+        let mut values: Vec<&dyn rusqlite::ToSql> = vec![&clean];
+        values.extend(patterns.iter().map(|p| p as &dyn rusqlite::ToSql));
+        // SQL はインクルード数ごとに同じ文になるので、準備済みの文を使い回す
+        let mut stmt = conn.prepare_cached(&sql)?;
+        return Ok(stmt.exists(values.as_slice())?);
+    }
+    let mut stmt = conn.prepare_cached("SELECT 1 FROM classes WHERE LOWER(name) = LOWER(?) LIMIT 1")?;
+    Ok(stmt.exists([&clean])?)
+}
+
+/// バッファ内の #include "X.h" / #include <X.h> のパス (*.generated.h は除く)
+fn buffer_includes(content: &str) -> Vec<String> {
+    let re = cached_regex!(r#"(?m)^\s*#\s*include\s*["<]([^">]+)[">]"#);
+    re.captures_iter(content)
+        .filter_map(|cap| cap.get(1).map(|m| m.as_str().trim().to_string()))
+        .filter(|path| !path.ends_with(".generated.h"))
+        .collect()
+}
+
+fn infer_variable_type(conn: &Connection, target_name: &str, root: &Node, content: &str, cursor_row: usize) -> anyhow::Result<Option<String>> {
+    // どの経路でも TObjectPtr<UFoo> などのラッパーと修飾子を外した型名にそろえる
+    Ok(infer_declared_variable_type(conn, target_name, root, content, cursor_row)?.map(|t| extract_clean_type(&t)))
+}
+
+fn infer_declared_variable_type(conn: &Connection, target_name: &str, root: &Node, content: &str, cursor_row: usize) -> anyhow::Result<Option<String>> {
+    if let Some((t_node, d_node)) = find_declaration(target_name, root, content, cursor_row)? {
+        // auto [Key, Value] = ... / for (auto& [Key, Value] : Map)
+        if let Some(bound) = infer_structured_binding_type(conn, target_name, &d_node, root, content, cursor_row)? {
+            return Ok(Some(bound));
+        }
+        let type_text = get_node_text(&t_node, content).trim();
+        if !is_auto_type(type_text) {
+            return Ok(Some(type_text.to_string()));
+        }
+        // for (auto& Item : Container) -> コンテナの要素型
+        if let Some(element) = infer_range_element_type(conn, &d_node, root, content, cursor_row)? {
+            return Ok(Some(element));
+        }
+        // auto& Ref = Hit; / auto Loc = Hit.Location; は右辺の変数・メンバーの型 (値型の構造体もそのまま)
+        if let Some(value) = d_node.child_by_field_name("value").filter(|v| ["identifier", "field_expression", "subscript_expression"].contains(&v.kind())) {
+            if get_node_text(&value, content).trim() != target_name {
+                // この宣言の行までの宣言だけを見るので auto A = B; auto B = A; のような相互参照でも止まる
+                if let Some(t) = resolve_expression_type(conn, value, root, content, d_node.start_position().row)? {
+                    return Ok(Some(t));
+                }
+            }
+        }
+    }
+    // [Comp = MeshComp]() { Comp-> } の初期化キャプチャ
+    if let Some(init) = find_init_capture(target_name, root, content, cursor_row) {
+        if let Some(t) = resolve_expression_type(conn, init, root, content, cursor_row)? {
+            return Ok(Some(t));
+        }
+    }
+    infer_from_assignment(conn, target_name, root, content, cursor_row)
+}
+
+/// カーソルを含むラムダの `[Name = Expr]` の Expr
+fn find_init_capture<'t>(target_name: &str, root: &Node<'t>, content: &str, cursor_row: usize) -> Option<Node<'t>> {
+    let point = Point::new(cursor_row, 0);
+    let mut curr = root.descendant_for_point_range(point, point);
+    // 行頭のノードから上へたどり、カーソル行を含むラムダのキャプチャを内側から順に見る
+    while let Some(node) = curr {
+        if node.kind() == "lambda_expression" && node.start_position().row <= cursor_row && cursor_row <= node.end_position().row {
+            if let Some(captures) = node.child_by_field_name("captures") {
+                let mut walker = captures.walk();
+                for capture in captures.named_children(&mut walker) {
+                    if !["assignment_expression", "lambda_capture_initializer"].contains(&capture.kind()) { continue; }
+                    let (Some(left), Some(right)) = (capture.child_by_field_name("left"), capture.child_by_field_name("right")) else { continue };
+                    if get_node_text(&left, content).trim() == target_name { return Some(right); }
+                }
+            }
+        }
+        curr = node.parent();
+    }
+    None
+}
+
+/// auto / auto* / const auto& / auto&& など、修飾子を除くと auto になる型
+fn is_auto_type(type_text: &str) -> bool {
+    let stripped = type_text.replace(['*', '&'], " ");
+    let mut words = stripped.split_whitespace().filter(|w| !["const", "volatile", "static", "constexpr", "inline"].contains(w));
+    words.next() == Some("auto") && words.next().is_none()
+}
+
+/// カーソルより前にある最も近い宣言の (型ノード, 宣言子ノード) を返す
+fn find_declaration<'t>(target_name: &str, root: &Node<'t>, content: &str, cursor_row: usize) -> anyhow::Result<Option<(Node<'t>, Node<'t>)>> {
+    // static / thread_local などの記憶域指定子は type の外側の兄弟ノードなので static ローカル変数も一致する
+    let query_str = "
+      (declaration type: (_) @type declarator: (_) @decl)
+      (parameter_declaration type: (_) @type declarator: (_) @decl)
+      (for_range_loop type: (_) @type declarator: (_) @decl)
+      (condition_clause (declaration type: (_) @type declarator: (_) @decl))
+    ";
+    let query = cached_query!(query_str);
+    let mut cursor = QueryCursor::new();
+    let mut matches = cursor.matches(&query, *root, content.as_bytes());
+    let mut best = None;
+    let mut best_row = 0;
+    while let Some(m) = matches.next() {
+        let mut type_node = None;
+        let mut decl_nodes = Vec::new();
+        for cap in m.captures {
+            let c_name = query.capture_names()[cap.index as usize];
+            if c_name == "type" { type_node = Some(cap.node); }
+            else if c_name == "decl" { decl_nodes.push(cap.node); }
+        }
+        if let Some(t_node) = type_node {
+            for d_node in decl_nodes {
+                if declares_identifier(&d_node, target_name, content) {
+                    let row = d_node.start_position().row;
+                    if row <= cursor_row && (best.is_none() || row >= best_row) {
+                        best = Some((t_node, d_node));
+                        best_row = row;
+                    }
+                }
+            }
+        }
+    }
+    Ok(best)
+}
+
+fn infer_structured_binding_type(conn: &Connection, target_name: &str, decl_node: &Node, root: &Node, content: &str, cursor_row: usize) -> anyhow::Result<Option<String>> {
+    let binding = match find_descendant_by_kind(*decl_node, "structured_binding_declarator") {
+        Some(b) => b,
+        None => return Ok(None),
+    };
+    let mut walker = binding.walk();
+    let index = binding.named_children(&mut walker)
+        .filter(|c| c.kind() == "identifier")
+        .position(|c| get_node_text(&c, content).trim() == target_name);
+    let index = match index { Some(i) => i, None => return Ok(None) };
+
+    let in_range_for = decl_node.parent().map(|p| p.kind() == "for_range_loop").unwrap_or(false);
+    let source_type = if in_range_for {
+        // for (auto& [K, V] : Map) -> 要素型 (TPair<K, V>) を分解
+        infer_range_element_type(conn, decl_node, root, content, cursor_row)?
+    } else {
+        match decl_node.child_by_field_name("value") {
+            Some(value) => infer_raw_expression_type(value, root, content, cursor_row, 0)?,
+            None => None,
+        }
+    };
+    Ok(source_type.and_then(|raw| decompose_type(&raw).get(index).cloned()))
+}
+
+/// range-for の宣言子ならコンテナ (TArray, TMap, C 配列) の要素型を返す
+fn infer_range_element_type(conn: &Connection, decl_node: &Node, root: &Node, content: &str, cursor_row: usize) -> anyhow::Result<Option<String>> {
+    let range = match decl_node.parent().filter(|p| p.kind() == "for_range_loop").and_then(|p| p.child_by_field_name("right")) {
+        Some(r) => r,
+        None => return Ok(None),
+    };
+    let element = resolve_raw_expression_type(conn, range, root, content, cursor_row)?
+        .and_then(|container| container_element_type(&container));
+    // TArray<UActorComponent*> Out; GetComponents(UMyComp::StaticClass(), Out) の要素は UMyComp として扱う
+    if element.as_deref().map_or(true, |e| extract_clean_type(e) == "UActorComponent") && range.kind() == "identifier" {
+        if let Some(hinted) = gathered_component_type(get_node_text(&range, content).trim(), content, cursor_row) {
+            return Ok(Some(format!("{}*", hinted)));
+        }
+    }
+    Ok(element)
+}
+
+/// カーソルより前の GetComponents<T>(Out) / GetComponents(T::StaticClass(), Out) で Out に集めたコンポーネントの型
+fn gathered_component_type(container: &str, content: &str, cursor_row: usize) -> Option<String> {
+    let re = cached_regex!(
+        r"\bGetComponents\s*(?:<\s*([A-Za-z0-9_]+)\s*\*?\s*>)?\s*\(\s*(?:([A-Za-z0-9_]+)\s*::\s*StaticClass\s*\(\s*\)\s*,\s*)?([A-Za-z0-9_]+)\s*[,)]"
+    );
+    let before: String = content.lines().take(cursor_row + 1).collect::<Vec<_>>().join("\n");
+    let caps = re.captures_iter(&before).filter(|caps| &caps[3] == container).last()?;
+    caps.get(1).or_else(|| caps.get(2)).map(|m| m.as_str().to_string())
+}
+
+fn find_descendant_by_kind<'t>(node: Node<'t>, kind: &str) -> Option<Node<'t>> {
+    if node.kind() == kind { return Some(node); }
+    for i in 0..node.child_count() {
+        if let Some(child) = node.child(i as u32) {
+            if let Some(found) = find_descendant_by_kind(child, kind) { return Some(found); }
+        }
+    }
+    None
+}
+
+/// 式の型をテンプレート引数付きの生テキストで推論する (TMap<FName, FFoo> など)
+fn infer_raw_expression_type(node: Node, root: &Node, content: &str, cursor_row: usize, depth: usize) -> anyhow::Result<Option<String>> {
+    if depth > 8 { return Ok(None); }
+    match node.kind() {
+        "identifier" => {
+            let name = get_node_text(&node, content).trim();
+            if let Some((t_node, d_node)) = find_declaration(name, root, content, cursor_row)? {
+                let type_text = get_node_text(&t_node, content).trim();
+                if !is_auto_type(type_text) {
+                    // FFoo Items[8] -> "FFoo[]"
+                    if find_descendant_by_kind(d_node, "array_declarator").is_some() {
+                        return Ok(Some(format!("{}[]", type_text)));
+                    }
+                    return Ok(Some(type_text.to_string()));
+                }
+                if let Some(value) = d_node.child_by_field_name("value") {
+                    return infer_raw_expression_type(value, root, content, cursor_row, depth + 1);
+                }
+            }
+            Ok(None)
+        }
+        "pointer_expression" => {
+            let op = node.child_by_field_name("operator").map(|o| get_node_text(&o, content).trim()).unwrap_or("");
+            match node.child_by_field_name("argument") {
+                Some(arg) if op == "*" => Ok(infer_raw_expression_type(arg, root, content, cursor_row, depth + 1)?
+                    .map(|raw| dereference_raw_type(&raw))),
+                _ => Ok(None),
+            }
+        }
+        "parenthesized_expression" => match node.named_child(0) {
+            Some(inner) => infer_raw_expression_type(inner, root, content, cursor_row, depth + 1),
+            None => Ok(None),
+        },
+        "call_expression" => {
+            // Map.CreateIterator() / Map.CreateConstIterator()
+            let func = match node.child_by_field_name("function") { Some(f) => f, None => return Ok(None) };
+            if func.kind() != "field_expression" { return Ok(None); }
+            let field = func.child_by_field_name("field").map(|f| get_node_text(&f, content).trim()).unwrap_or("");
+            if !["CreateIterator", "CreateConstIterator"].contains(&field) { return Ok(None); }
+            match func.child_by_field_name("argument") {
+                Some(obj) => Ok(infer_raw_expression_type(obj, root, content, cursor_row, depth + 1)?
+                    .map(|container| format!("{}::TIterator", container))),
+                None => Ok(None),
+            }
+        }
+        _ => Ok(None),
+    }
+}
+
+/// `*X` の結果型: イテレータなら要素型、ポインタ/スマートポインタなら指す先
+fn dereference_raw_type(raw: &str) -> String {
+    let t = raw.trim();
+    for suffix in ["::TIterator", "::TConstIterator", "::TRangedForIterator", "::TRangedForConstIterator"] {
+        if let Some(container) = t.strip_suffix(suffix) {
+            return container_element_type(container).unwrap_or_else(|| container.to_string());
+        }
+    }
+    if let Some(pointee) = t.strip_suffix('*') {
+        return pointee.trim().to_string();
+    }
+    if let Some((wrapper, args)) = split_template_args(t) {
+        if ["TObjectPtr", "TSharedPtr", "TSharedRef", "TUniquePtr", "TWeakObjectPtr", "TFieldIterator"].contains(&wrapper.as_str()) {
+            if let Some(inner) = args.into_iter().next() { return inner; }
+        }
+    }
+    t.to_string()
+}
+
+/// コンテナの要素型 (TArray<T> -> T, TMap<K, V> -> TPair<K, V>, T[] -> T)
+fn container_element_type(raw: &str) -> Option<String> {
+    if let Some(element) = raw.trim().strip_suffix("[]") {
+        return Some(element.trim().to_string());
+    }
+    let (wrapper, args) = split_template_args(raw)?;
+    match wrapper.as_str() {
+        "TArray" | "TSet" | "TArrayView" | "TConstArrayView" | "TSparseArray" | "TIndirectArray" | "TChunkedArray"
+        | "TInlineComponentArray" | "TStaticArray" | "TDoubleLinkedList" | "vector" | "set" => args.into_iter().next(),
+        "TMap" | "TMultiMap" | "TSortedMap" | "map" | "unordered_map" if args.len() >= 2 => {
+            Some(format!("TPair<{}, {}>", args[0], args[1]))
+        }
+        _ => None,
+    }
+}
+
+/// 要素 (へのポインタ/参照) を返すコンテナのメンバー関数
+const CONTAINER_ELEMENT_METHODS: &[&str] = &[
+    "FindByPredicate", "FindByKey", "Last", "Top", "GetData", "Pop", "Add_GetRef", "Emplace_GetRef", "AddDefaulted_GetRef",
+    "Find", "FindRef", "FindChecked", "FindOrAdd",
+    "GetValue", "GetPtrOrNull", "Get",
+];
+
+/// Array.FindByKey(K) -> T, Map.Find(K) -> V, Opt.GetPtrOrNull() -> T (TArray::Find はインデックスを返すので対象外)
+fn container_method_type(raw: &str, method: &str) -> Option<String> {
+    let (wrapper, args) = split_template_args(raw)?;
+    match wrapper.as_str() {
+        "TMap" | "TMultiMap" | "TSortedMap" if ["Find", "FindRef", "FindChecked", "FindOrAdd"].contains(&method) => args.get(1).cloned(),
+        "TSet" if ["Find", "FindChecked"].contains(&method) => args.first().cloned(),
+        "TOptional" if ["GetValue", "GetPtrOrNull", "Get"].contains(&method) => args.first().cloned(),
+        "TArray" | "TArrayView" | "TConstArrayView" | "TInlineComponentArray" | "TIndirectArray" | "TStaticArray"
+            if !["Find", "FindRef", "FindChecked", "FindOrAdd", "GetValue", "GetPtrOrNull", "Get"].contains(&method) => args.first().cloned(),
+        _ => None,
+    }
+}
+
+/// `X[i]` の結果型 (TArray<T> -> T, TMap<K, V> -> V, T* -> T)
+fn subscript_element_type(raw: &str) -> Option<String> {
+    if let Some((wrapper, args)) = split_template_args(raw) {
+        if ["TMap", "TMultiMap", "TSortedMap", "map", "unordered_map"].contains(&wrapper.as_str()) {
+            return args.get(1).cloned();
+        }
+    }
+    if let Some(element) = container_element_type(raw) {
+        return Some(element);
+    }
+    raw.trim().strip_suffix('*').map(|pointee| pointee.trim().to_string())
+}
+
+/// コンテナ以外 (FMyGrid Grid; Grid[0].) はクラスの operator[] の戻り値型
+fn subscript_element_type_in_db(conn: &Connection, container: &str) -> anyhow::Result<Option<String>> {
+    if let Some(element) = subscript_element_type(container) {
+        return Ok(Some(element));
+    }
+    find_member_raw_return_type(conn, container, "operator[]")
+}
+
+/// TPair<K, V> の Key / Value (std::pair の first / second) の生の型
+fn pair_member_type(conn: &Connection, obj_node: Node, field_name: &str, root: &Node, content: &str, cursor_row: usize) -> anyhow::Result<Option<String>> {
+    let index = match field_name {
+        "Key" | "first" => 0,
+        "Value" | "second" => 1,
+        _ => return Ok(None),
+    };
+    let raw = match resolve_raw_expression_type(conn, obj_node, root, content, cursor_row)? {
+        Some(r) => r,
+        None => return Ok(None),
+    };
+    match split_template_args(&raw) {
+        Some((wrapper, args)) if ["TPair", "TKeyValuePair", "pair"].contains(&wrapper.as_str()) => Ok(args.get(index).cloned()),
+        _ => Ok(None),
+    }
+}
+
+/// 構造化束縛で分解される各要素の型 (TPair<K, V> -> [K, V])
+fn decompose_type(raw: &str) -> Vec<String> {
+    match split_template_args(raw) {
+        Some((wrapper, args)) if ["TPair", "TTuple", "TKeyValuePair", "pair", "tuple"].contains(&wrapper.as_str()) => args,
+        _ => Vec::new(),
+    }
+}
+
+/// "const TMap<FName, TArray<FFoo>>&" -> ("TMap", ["FName", "TArray<FFoo>"])
+fn split_template_args(raw: &str) -> Option<(String, Vec<String>)> {
+    let start = raw.find('<')?;
+    let end = raw.rfind('>')?;
+    if end <= start { return None; }
+    let wrapper = raw[..start].split_whitespace().last()?.rsplit("::").next()?.to_string();
+    let mut args = Vec::new();
+    let mut depth = 0i32;
+    let mut current = String::new();
+    for ch in raw[start + 1..end].chars() {
+        match ch {
+            '<' | '(' => { depth += 1; current.push(ch); }
+            '>' | ')' => { depth -= 1; current.push(ch); }
+            ',' if depth == 0 => { args.push(current.trim().to_string()); current.clear(); }
+            _ => current.push(ch),
+        }
+    }
+    if !current.trim().is_empty() { args.push(current.trim().to_string()); }
+    Some((wrapper, args))
+}
+
+/// 宣言子が target_name そのものを宣言しているか (初期化式 IsValid(Comp) や配列サイズの識別子は見ない)
+fn declares_identifier(node: &Node, target_name: &str, content: &str) -> bool {
+    match node.kind() {
+        "identifier" | "field_identifier" => get_node_text(node, content).trim() == target_name,
+        "structured_binding_declarator" => {
+            let mut walker = node.walk();
+            let found = node.named_children(&mut walker).any(|c| c.kind() == "identifier" && get_node_text(&c, content).trim() == target_name);
+            found
+        }
+        // & / && と括弧の宣言子は declarator フィールドを持たない
+        "reference_declarator" | "parenthesized_declarator" => match node.named_child(node.named_child_count().saturating_sub(1) as u32) {
+            Some(inner) => declares_identifier(&inner, target_name, content),
+            None => false,
+        },
+        _ => match node.child_by_field_name("declarator") {
+            Some(inner) => declares_identifier(&inner, target_name, content),
+            None => false,
+        },
+    }
+}
+
+/// 同じバッファにある class_name のコンストラクタ (本体の代入と初期化子リスト) から target_name に入れた値の型を推論する
+fn infer_from_constructor_assignment(conn: &Connection, target_name: &str, class_name: &str, root: &Node, content: &str) -> anyhow::Result<Option<String>> {
+    let ctor_query = cached_query!("(function_definition declarator: (function_declarator declarator: (_) @name)) @func");
+    let assign_query = cached_query!("
+      (assignment_expression left: (_) @left right: (_) @value)
+      (field_initializer (field_identifier) @left (argument_list (_) @value))
+    ");
+    let mut cursor = QueryCursor::new();
+    let mut matches = cursor.matches(&ctor_query, *root, content.as_bytes());
+    while let Some(m) = matches.next() {
+        let mut name_node = None;
+        let mut func_node = None;
+        for cap in m.captures {
+            match ctor_query.capture_names()[cap.index as usize] {
+                "name" => name_node = Some(cap.node),
+                "func" => func_node = Some(cap.node),
+                _ => {}
+            }
+        }
+        let (Some(name_node), Some(func_node)) = (name_node, func_node) else { continue };
+        // AMyActor::AMyActor() またはクラス本体内の AMyActor()
+        let segments: Vec<&str> = get_node_text(&name_node, content).split("::").map(str::trim).collect();
+        let is_ctor = segments.last() == Some(&class_name) && (segments.len() == 1 || segments[segments.len() - 2] == class_name);
+        if !is_ctor { continue; }
+        let mut assign_cursor = QueryCursor::new();
+        let mut assigns = assign_cursor.matches(&assign_query, func_node, content.as_bytes());
+        while let Some(a) = assigns.next() {
+            let mut left = None;
+            let mut value = None;
+            for cap in a.captures {
+                match assign_query.capture_names()[cap.index as usize] {
+                    "left" => left = Some(cap.node),
+                    "value" => value = Some(cap.node),
+                    _ => {}
+                }
+            }
+            let (Some(left), Some(value)) = (left, value) else { continue };
+            // MeshComp = ... / this->MeshComp = ...
+            let assigned = match left.kind() {
+                "identifier" | "field_identifier" => get_node_text(&left, content).trim() == target_name,
+                "field_expression" => left.child_by_field_name("argument").map_or(false, |obj| obj.kind() == "this")
+                    && left.child_by_field_name("field").map_or(false, |f| get_node_text(&f, content).trim() == target_name),
+                _ => false,
+            };
+            if !assigned { continue; }
+            // Init() のような関数呼び出しを型名と取り違えないよう、索引にある型だけを採用する
+            if let Some(t) = infer_from_value_text(get_node_text(&value, content))? {
+                if is_known_type(conn, &t)? { return Ok(Some(t)); }
+            }
+        }
+    }
+    Ok(None)
+}
+
+fn infer_from_assignment(conn: &Connection, target_name: &str, root: &Node, content: &str, cursor_row: usize) -> anyhow::Result<Option<String>> {
+    let query_str = "
+      (declaration declarator: (init_declarator declarator: (_) @decl value: (_) @value))
+      (assignment_expression left: (_) @decl right: (_) @value)
+    ";
+    let query = cached_query!(query_str);
+    let mut cursor = QueryCursor::new();
+    let mut matches = cursor.matches(&query, *root, content.as_bytes());
+    // カーソルより前で最も近い代入を使う
+    let mut best: Option<(usize, Node)> = None;
+    while let Some(m) = matches.next() {
+        let mut decl_node = None;
+        let mut value_node = None;
+        for cap in m.captures {
+            let c_name = query.capture_names()[cap.index as usize];
+            if c_name == "decl" { decl_node = Some(cap.node); }
+            else if c_name == "value" { value_node = Some(cap.node); }
+        }
+        if let (Some(d_node), Some(v_node)) = (decl_node, value_node) {
+            // Weapon.Stats = ... は Weapon 自体への代入ではない
+            if ["field_expression", "subscript_expression", "call_expression"].contains(&d_node.kind()) { continue; }
+            if declares_identifier(&d_node, target_name, content) {
+                let row = d_node.start_position().row;
+                if row <= cursor_row && best.map_or(true, |(best_row, _)| row >= best_row) {
+                    best = Some((row, v_node));
+                }
+            }
+        }
+    }
+    let Some((row, v_node)) = best else { return Ok(None) };
+    let inferred = infer_from_value_text(get_node_text(&v_node, content))?;
+    // const auto& T = GetActorTransform(); の関数名は型ではないので、呼び出しの戻り値型を解決する
+    // (Comp = Comp->GetChild() のように右辺が自身を参照する場合は辿らない)
+    let known = match &inferred {
+        Some(t) => is_known_type(conn, t)?,
+        None => false,
+    };
+    if v_node.kind() == "call_expression" && !known && !mentions_identifier(get_node_text(&v_node, content), target_name) {
+        if let Some(t) = resolve_expression_type(conn, v_node, root, content, row)? {
+            return Ok(Some(t));
+        }
+    }
+    Ok(inferred)
+}
+
+fn mentions_identifier(text: &str, name: &str) -> bool {
+    !name.is_empty() && crate::scanner::find_word(text, name).is_some()
+}
+
+fn infer_from_value_text(text: &str) -> anyhow::Result<Option<String>> {
+    let text = text.trim();
+    if let Some(cap) = cached_regex!(r"CreateDefaultSubobject\s*<\s*([a-zA-Z0-9_:]+)").captures(text) {
+        return Ok(Some(extract_clean_type(cap.get(1).unwrap().as_str())));
+    }
+    if let Some(cap) = cached_regex!(r"([a-zA-Z0-9_]+)\s*<\s*([a-zA-Z0-9_:]+)").captures(text) {
+        let func = cap.get(1).unwrap().as_str();
+        let inner = cap.get(2).unwrap().as_str();
+        // Cast<IDamageable>(Actor) や NewObject<UFoo>() はテンプレート引数の型
+        if ["TObjectPtr", "TSharedPtr"].contains(&func) || TEMPLATE_ARG_RETURNING_FUNCTIONS.contains(&func) {
+            return Ok(Some(extract_clean_type(inner)));
+        }
+        return Ok(Some(extract_clean_type(func)));
+    }
+    if let Some(cap) = cached_regex!(r"^([a-zA-Z0-9_]+)\s*\(.*?([a-zA-Z0-9_:]+)::StaticClass\s*\(").captures(text) {
+        if CLASS_ARGUMENT_RETURNING_FUNCTIONS.contains(&cap.get(1).unwrap().as_str()) {
+            return Ok(Some(extract_clean_type(cap.get(2).unwrap().as_str())));
+        }
+    }
+    if let Some(cap) = cached_regex!(r"^([a-zA-Z0-9_:]+)\s*\(").captures(text) {
+        return Ok(Some(extract_clean_type(cap.get(1).unwrap().as_str())));
+    }
+    Ok(None)
+}
+
+// 補完時は中身の型として扱うラッパーテンプレート
+const UNWRAPPED_TEMPLATES: &[&str] = &["TObjectPtr", "TSharedPtr", "TUniquePtr", "TWeakObjectPtr", "TSubclassOf", "TSoftObjectPtr", "TSoftClassPtr", "TEnumAsByte", "TFieldIterator", "TScriptInterface"];
+
+fn extract_clean_type(raw: &str) -> String {
+    let mut clean = raw.trim().to_string();
+    if let Some(start) = clean.find('<') {
+        if let Some(end) = clean.rfind('>') {
+            let wrapper = clean[..start].trim();
+            let inner = &clean[start+1..end];
+            if UNWRAPPED_TEMPLATES.contains(&wrapper) {
+                return extract_clean_type(inner);
+            }
+            clean = wrapper.to_string();
+        }
+    }
+    // 属性 [[nodiscard]]、修飾子のキーワード、MYGAME_API を外す (補完のたびに何度も呼ばれるので正規表現は 1 つにまとめる)
+    let noise = cached_regex!(r"\[\[[^\]]*\]\]|\b(?:const|typename|struct|class|enum|virtual|static|inline|FORCEINLINE|FORCEINLINE_DEBUGGABLE|UE_NODISCARD|constexpr|explicit|mutable|volatile|thread_local|[A-Z0-9_]+_API)\b");
+    clean = noise.replace_all(&clean, "").to_string();
     clean = clean.replace('*', " ").replace('&', " ");
     let final_type = clean.split_whitespace()
         .last()
@@ -493,4 +3458,2119 @@ fn extract_clean_type(raw: &str) -> String {
         .unwrap_or("")
         .to_string();
     final_type
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use crate::types::ItemHook;
+
+    // よく使う UE の型を小さくまねたヘッダー
+    const ENGINE_HEADER: &str = r#"
+class UObject
+{
+public:
+    UWorld* GetWorld() const;
+    UObject* GetDefaultSubobjectByName(FName Name);
+};
+
+class UActorComponent : public UObject
+{
+public:
+    void Activate(bool bReset);
+    AActor* GetOwner() const;
+};
+
+class USceneComponent : public UActorComponent
+{
+public:
+    FVector GetComponentLocation() const;
+};
+
+class AActor : public UObject
+{
+public:
+    AActor* GetOwner() const;
+    FVector GetActorLocation() const;
+    USceneComponent* GetRootComponent() const;
+    const FTransform& GetActorTransform() const;
+    FTimerManager& GetWorldTimerManager() const;
+    virtual void Tick(float DeltaSeconds);
+    virtual void BeginPlay();
+};
+
+struct FTransform
+{
+    FVector GetLocation() const;
+    FQuat GetRotation() const;
+};
+
+class FTimerManager
+{
+public:
+    void SetTimer(FTimerHandle& InOutHandle, float InRate, bool bInLoop);
+    void ClearTimer(FTimerHandle& InHandle);
+    bool IsTimerActive(FTimerHandle InHandle) const;
+};
+"#;
+
+    const GAME_HEADER: &str = r#"
+class AMyActor : public AActor
+{
+public:
+    virtual void Tick(float DeltaSeconds) override;
+    virtual void BeginPlay() override;
+    TObjectPtr<USceneComponent> Mesh;
+    TArray<AActor*> Targets;
+    int32 Health;
+};
+"#;
+
+    pub(super) fn indexed(files: &[(&str, &str)]) -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        db::init_db(&conn).unwrap();
+        for (path, content) in files {
+            db::reindex_file(&conn, path, content).unwrap();
+        }
+        conn
+    }
+
+    fn engine() -> Connection {
+        indexed(&[("Engine/Actor.h", ENGINE_HEADER), ("Game/MyActor.h", GAME_HEADER)])
+    }
+
+    /// source 中の `|` を取り除いた内容と、その位置 (行, 列)
+    pub(super) fn cursor(source: &str) -> (String, u32, u32) {
+        let offset = source.find('|').expect("cursor marker");
+        let line_start = source[..offset].rfind('\n').map_or(0, |i| i + 1);
+        let line = source[..offset].matches('\n').count() as u32;
+        (source.replacen('|', "", 1), line, (offset - line_start) as u32)
+    }
+
+    /// source 中の `|` の位置で補完する
+    pub(super) fn complete_with(conn: &Connection, source: &str, config: &CompletionConfig) -> CompletionList {
+        let (content, line, character) = cursor(source);
+        with_config(config, || complete_with_config(conn, &content, line, character, None, config)).unwrap()
+    }
+
+    pub(super) fn complete(conn: &Connection, source: &str) -> CompletionList {
+        complete_with(conn, source, &CompletionConfig::default())
+    }
+
+    pub(super) fn labels(list: &CompletionList) -> Vec<&str> {
+        list.items.iter().map(|i| i.label.as_str()).collect()
+    }
+
+    // AMyActor::BeginPlay の本体に body を置いた .cpp
+    fn in_method(body: &str) -> String {
+        format!("void AMyActor::BeginPlay()\n{{\n    {}\n}}\n", body)
+    }
+
+    #[test]
+    fn this_lists_own_and_inherited_members() {
+        let conn = engine();
+        let list = complete(&conn, &in_method("this->|"));
+        let labels = labels(&list);
+        assert!(labels.contains(&"Health"));
+        assert!(labels.contains(&"GetActorLocation"));
+        assert!(labels.contains(&"GetWorld"));
+        assert!(!list.is_incomplete);
+    }
+
+    #[test]
+    fn local_pointer_resolves_to_declared_type() {
+        let conn = engine();
+        let list = complete(&conn, &in_method("AActor* Other = nullptr;\n    Other->|"));
+        let labels = labels(&list);
+        assert!(labels.contains(&"GetActorLocation"));
+        assert!(!labels.contains(&"Health"));
+    }
+
+    #[test]
+    fn cast_resolves_to_template_argument() {
+        let conn = engine();
+        let list = complete(&conn, &in_method("AActor* Other = nullptr;\n    Cast<AMyActor>(Other)->|"));
+        assert!(labels(&list).contains(&"Health"));
+    }
+
+    #[test]
+    fn call_result_resolves_to_return_type() {
+        let conn = engine();
+        let list = complete(&conn, &in_method("GetRootComponent()->|"));
+        let labels = labels(&list);
+        assert!(labels.contains(&"GetComponentLocation"));
+        assert!(labels.contains(&"Activate"));
+    }
+
+    #[test]
+    fn call_chain_resolves_each_step() {
+        let conn = engine();
+        let list = complete(&conn, &in_method("GetRootComponent()->GetOwner()->|"));
+        assert!(labels(&list).contains(&"GetActorLocation"));
+    }
+
+    #[test]
+    fn object_ptr_member_unwraps_to_pointee() {
+        let conn = engine();
+        let list = complete(&conn, &in_method("Mesh->|"));
+        assert!(labels(&list).contains(&"GetComponentLocation"));
+    }
+
+    #[test]
+    fn array_subscript_resolves_to_element_type() {
+        let conn = engine();
+        let list = complete(&conn, &in_method("Targets[0]->|"));
+        assert!(labels(&list).contains(&"GetActorLocation"));
+    }
+
+    #[test]
+    fn range_for_variable_resolves_to_element_type() {
+        let conn = engine();
+        let list = complete(&conn, &in_method("for (AActor* Target : Targets)\n    {\n        Target->|\n    }"));
+        assert!(labels(&list).contains(&"GetActorLocation"));
+    }
+
+    #[test]
+    fn super_resolves_to_direct_base() {
+        let conn = engine();
+        let list = complete(&conn, &in_method("Super::|"));
+        let labels = labels(&list);
+        assert!(labels.contains(&"Tick"));
+        assert!(labels.contains(&"GetActorLocation"));
+        assert!(!labels.contains(&"Health"));
+    }
+
+    #[test]
+    fn own_members_sort_before_inherited_ones() {
+        let conn = engine();
+        let list = complete(&conn, &in_method("this->|"));
+        let sort_text = |label: &str| list.items.iter().find(|i| i.label == label).and_then(|i| i.sort_text.clone()).unwrap();
+        assert!(sort_text("Health") < sort_text("GetActorLocation"));
+        assert!(sort_text("GetActorLocation") < sort_text("GetWorld"));
+    }
+
+    // C0 : C1 : ... : C{depth}
+    fn deep_hierarchy(depth: usize) -> String {
+        let mut header = String::new();
+        for i in 0..depth {
+            header.push_str(&format!("class C{} : public C{}\n{{\npublic:\n    void F{}();\n}};\n", i, i + 1, i));
+        }
+        header.push_str(&format!("class C{}\n{{\npublic:\n    void F{}();\n}};\n", depth, depth));
+        header
+    }
+
+    #[test]
+    fn timeout_returns_partial_members_as_incomplete() {
+        let depth = 500;
+        let conn = indexed(&[("Deep.h", &deep_hierarchy(depth))]);
+        let (all, incomplete) = fetch_members_recursive(&conn, "C0", AccessFilter::AllAccess, None).unwrap();
+        assert_eq!(all.len(), depth + 1);
+        assert!(!incomplete);
+
+        // 所要時間は環境によるので、途中で打ち切られる予算が見つかるまで広げる
+        let partial = [1u64, 2, 4, 8, 16, 32, 64].iter().find_map(|ms| {
+            let deadline = Some(Instant::now() + std::time::Duration::from_millis(*ms));
+            let (items, incomplete) = fetch_members_recursive(&conn, "C0", AccessFilter::AllAccess, deadline).unwrap();
+            (incomplete && !items.is_empty()).then_some(items)
+        }).expect("no budget produced a partial result");
+        assert!(partial.len() < all.len());
+        // 打ち切りまでに集めた分は派生側から順に残る
+        assert_eq!(partial[0].label, "F0");
+    }
+
+    #[test]
+    fn expired_timeout_marks_list_incomplete() {
+        let conn = indexed(&[("Deep.h", &deep_hierarchy(50))]);
+        let config = CompletionConfig { timeout: Some(std::time::Duration::ZERO), ..Default::default() };
+        let list = complete_with(&conn, "void C0::F0()\n{\n    this->|\n}\n", &config);
+        assert!(list.is_incomplete);
+        assert!(list.items.len() < 51);
+    }
+
+    const PAWN_HEADER: &str = r#"
+class UObject
+{
+public:
+    UWorld* GetWorld() const;
+};
+
+class ISelectable : public UObject
+{
+public:
+    virtual void Select();
+};
+
+class AActor : public UObject
+{
+public:
+    FVector GetActorLocation() const;
+};
+
+class APawn : public AActor
+{
+public:
+    AController* GetController() const;
+};
+
+class ACharacter : public APawn
+{
+public:
+    void Jump();
+};
+
+class AHero : public ACharacter, public ISelectable
+{
+};
+
+class AVillain : public APawn
+{
+};
+"#;
+
+    #[test]
+    fn ancestors_are_listed_nearest_first() {
+        let conn = indexed(&[("Pawn.h", PAWN_HEADER)]);
+        let ancestors = class_ancestors(&conn, "AHero").unwrap();
+        assert_eq!(ancestors, ["AHero", "ACharacter", "ISelectable", "APawn", "UObject", "AActor"]);
+    }
+
+    #[test]
+    fn ternary_resolves_to_nearest_common_base() {
+        let conn = indexed(&[("Pawn.h", PAWN_HEADER)]);
+        assert_eq!(nearest_common_base(&conn, "AHero", "AVillain").unwrap().as_deref(), Some("APawn"));
+
+        let source = "void Spawn(bool bHero)\n{\n    AHero* Hero = nullptr;\n    AVillain* Villain = nullptr;\n    (bHero ? Hero : Villain)->|\n}\n";
+        let list = complete(&conn, source);
+        let labels = labels(&list);
+        assert!(labels.contains(&"GetController"));
+        assert!(labels.contains(&"GetActorLocation"));
+        assert!(!labels.contains(&"Jump"));
+        assert!(!labels.contains(&"Select"));
+    }
+
+    const ALIAS_HEADER: &str = r#"
+struct FTarget
+{
+    float Weight;
+};
+
+using FAlias1 = FAlias2;
+using FAlias2 = FAlias3;
+typedef FAlias4 FAlias3;
+using FAlias4 = FAlias5;
+using FAlias5 = FTarget;
+
+using FLoopA = FLoopB;
+using FLoopB = FLoopC;
+using FLoopC = FLoopA;
+"#;
+
+    #[test]
+    fn five_alias_chain_resolves_to_target() {
+        let conn = indexed(&[("Alias.h", ALIAS_HEADER)]);
+        let chain = resolve_typedef_chain(&conn, "FAlias1").unwrap();
+        assert_eq!(chain, ["FAlias1", "FAlias2", "FAlias3", "FAlias4", "FAlias5", "FTarget"]);
+
+        let list = complete(&conn, "void Use()\n{\n    FAlias1 Value;\n    Value.|\n}\n");
+        assert!(labels(&list).contains(&"Weight"));
+    }
+
+    #[test]
+    fn typedef_chain_stops_at_max_steps() {
+        let conn = indexed(&[("Alias.h", ALIAS_HEADER)]);
+        let config = CompletionConfig { max_typedef_steps: Some(3), ..Default::default() };
+        let chain = with_config(&config, || resolve_typedef_chain(&conn, "FAlias1")).unwrap();
+        assert_eq!(chain, ["FAlias1", "FAlias2", "FAlias3", "FAlias4"]);
+    }
+
+    #[test]
+    fn typedef_cycle_terminates() {
+        let conn = indexed(&[("Alias.h", ALIAS_HEADER)]);
+        let chain = resolve_typedef_chain(&conn, "FLoopA").unwrap();
+        assert_eq!(chain, ["FLoopA", "FLoopB", "FLoopC"]);
+
+        // 上限を大きくしても循環で止まり、補完もそのまま返る
+        let config = CompletionConfig { max_typedef_steps: Some(10_000), ..Default::default() };
+        let chain = with_config(&config, || resolve_typedef_chain(&conn, "FLoopB")).unwrap();
+        assert_eq!(chain, ["FLoopB", "FLoopC", "FLoopA"]);
+        let list = complete_with(&conn, "void Use()\n{\n    FLoopA Value;\n    Value.|\n}\n", &config);
+        assert!(!list.is_incomplete);
+    }
+
+    #[test]
+    fn invalidate_recomputes_cached_typedef() {
+        let conn = indexed(&[("Game/Types.h", "using FMyAlias = FOld;\n")]);
+        db::bump_generation(&conn).unwrap();
+        completion_cache::sync(&conn).unwrap();
+        assert_eq!(resolve_typedef(&conn, "FMyAlias").unwrap(), "FOld");
+
+        // キャッシュがあるうちは DB の内容が変わっても前の結果を返す
+        conn.execute("UPDATE classes SET base_class = 'FNew' WHERE name = 'FMyAlias'", []).unwrap();
+        assert_eq!(resolve_typedef(&conn, "FMyAlias").unwrap(), "FOld");
+
+        assert_eq!(completion_cache::invalidate(&conn, &[std::path::PathBuf::from("Game/Other.h")]).unwrap(), 0);
+        assert_eq!(resolve_typedef(&conn, "FMyAlias").unwrap(), "FOld");
+        assert_eq!(completion_cache::invalidate(&conn, &[std::path::PathBuf::from("Game/Types.h")]).unwrap(), 1);
+        assert_eq!(resolve_typedef(&conn, "FMyAlias").unwrap(), "FNew");
+    }
+
+    fn member_classes<'a>(items: &'a [CompletionItem], label: &str) -> Vec<&'a str> {
+        items.iter().filter(|i| i.label == label).filter_map(|i| i.data.as_ref()).map(|d| d.class.as_str()).collect()
+    }
+
+    #[test]
+    fn override_hides_base_declaration() {
+        let header = "class ABase\n{\npublic:\n    virtual void Tick(float DeltaSeconds);\n    void Fire(int32 Count);\n};\n\nclass ADerived : public ABase\n{\npublic:\n    virtual void Tick(float DeltaSeconds) override;\n    void Fire();\n};\n";
+        let conn = indexed(&[("Derived.h", header)]);
+        let (items, _) = fetch_members_recursive(&conn, "ADerived", AccessFilter::AllAccess, None).unwrap();
+        assert_eq!(member_classes(&items, "Tick"), ["ADerived"]);
+        // 引数の異なるオーバーロードはどちらも残る
+        assert_eq!(member_classes(&items, "Fire"), ["ADerived", "ABase"]);
+    }
+
+    #[test]
+    fn nearest_override_wins_across_multiple_bases() {
+        // A : B, C / C : D のとき、深さ 1 の B の方が深さ 2 の D より近い
+        let header = "class B\n{\npublic:\n    virtual void Tick();\n};\n\nclass D\n{\npublic:\n    virtual void Tick();\n};\n\nclass C : public D\n{\npublic:\n    void Jump();\n};\n\nclass A : public B, public C\n{\n};\n";
+        let conn = indexed(&[("Multi.h", header)]);
+        let (items, _) = fetch_members_recursive(&conn, "A", AccessFilter::AllAccess, None).unwrap();
+        assert_eq!(member_classes(&items, "Tick"), ["B"]);
+        assert_eq!(member_classes(&items, "Jump"), ["C"]);
+    }
+
+    #[test]
+    fn sort_depth_uses_shortest_inheritance_path() {
+        // UShared は A の直接の基底であり、X を経由して深さ 2 からも届く
+        let header = "class UShared\n{\npublic:\n    void Share();\n};\n\nclass X : public UShared\n{\npublic:\n    void Cross();\n};\n\nclass A : public UShared, public X\n{\npublic:\n    void Own();\n};\n";
+        let conn = indexed(&[("Diamond.h", header)]);
+        let (items, _) = fetch_members_recursive(&conn, "A", AccessFilter::AllAccess, None).unwrap();
+        let sort_text = |label: &str| items.iter().find(|i| i.label == label).and_then(|i| i.sort_text.clone()).unwrap();
+        assert_eq!(sort_text("Own"), "00Own");
+        assert_eq!(sort_text("Share"), "01Share");
+        assert_eq!(sort_text("Cross"), "01Cross");
+        assert_eq!(items.iter().filter(|i| i.label == "Share").count(), 1);
+    }
+
+    #[test]
+    fn class_lookup_falls_back_to_case_insensitive_match() {
+        let conn = engine();
+        // 2 回目は準備済みの文で同じ結果になる
+        for _ in 0..2 {
+            let (items, _) = fetch_members_recursive(&conn, "amyactor", AccessFilter::AllAccess, None).unwrap();
+            let labels: Vec<&str> = items.iter().map(|i| i.label.as_str()).collect();
+            assert!(labels.contains(&"Health"));
+            assert!(labels.contains(&"GetActorLocation"));
+        }
+    }
+
+    #[test]
+    fn map_kind_distinguishes_special_functions() {
+        assert_eq!(map_kind("function", "operator[]", "FHandle"), 24);
+        assert_eq!(map_kind("function", "operator==", "FHandle"), 24);
+        assert_eq!(map_kind("function", "operator bool", "FHandle"), 24);
+        assert_eq!(map_kind("function", "FHandle", "FHandle"), 4);
+        assert_eq!(map_kind("function", "~FHandle", "FHandle"), 4);
+        // operator で始まるだけの普通の名前は関数のまま
+        assert_eq!(map_kind("function", "operatorCount", "FHandle"), 2);
+        assert_eq!(map_kind("function", "OperatorName", "FHandle"), 2);
+        assert_eq!(map_kind("property", "Value", "FHandle"), 5);
+        assert_eq!(map_kind("enum_item", "Value", "EHandle"), 20);
+    }
+
+    #[test]
+    fn operators_and_destructors_are_hidden_from_member_lists() {
+        let header = "class FHandle\n{\npublic:\n    FHandle(int32 InValue);\n    ~FHandle();\n    int32& operator [](int32 Index);\n    operator bool() const;\n    int32 GetValue() const;\n};\n\nclass FNamedHandle : public FHandle\n{\npublic:\n    FNamedHandle();\n};\n";
+        let conn = indexed(&[("Handle.h", header)]);
+        let (items, _) = fetch_members_recursive(&conn, "FHandle", AccessFilter::AllAccess, None).unwrap();
+        let labels: Vec<&str> = items.iter().map(|i| i.label.as_str()).collect();
+        assert!(labels.contains(&"GetValue"));
+        assert!(!labels.iter().any(|l| l.starts_with("operator") || l.starts_with('~')));
+        // コンストラクターは種別 4 で通常のメンバーより後ろに並ぶ
+        let constructor = items.iter().find(|i| i.label == "FHandle").unwrap();
+        assert_eq!(constructor.kind, 4);
+        assert_eq!(constructor.sort_text.as_deref(), Some("99FHandle"));
+
+        // 基底クラスのコンストラクターは派生クラスでは出さない
+        let (items, _) = fetch_members_recursive(&conn, "FNamedHandle", AccessFilter::AllAccess, None).unwrap();
+        let labels: Vec<&str> = items.iter().map(|i| i.label.as_str()).collect();
+        assert!(labels.contains(&"FNamedHandle"));
+        assert!(labels.contains(&"GetValue"));
+        assert!(!labels.contains(&"FHandle"));
+    }
+
+    const CONSOLE_HEADER: &str = r#"
+DECLARE_LOG_CATEGORY_EXTERN(LogMyGame, Log, All);
+DECLARE_LOG_CATEGORY_EXTERN(LogInventory, Log, All);
+
+static TAutoConsoleVariable<int32> CVarShowDebug(
+    TEXT("my.ShowDebug"),
+    0,
+    TEXT("Draws debug shapes"));
+static FAutoConsoleCommand DumpInventoryCommand(TEXT("my.DumpInventory"), TEXT("Dumps the inventory"), FConsoleCommandDelegate());
+"#;
+
+    #[test]
+    fn log_category_offered_in_ue_log_first_argument() {
+        let conn = indexed(&[("Game/Log.h", CONSOLE_HEADER)]);
+        let list = complete(&conn, &in_method("UE_LOG(|, Warning, TEXT(\"Hit\"));"));
+        assert_eq!(labels(&list), ["LogInventory", "LogMyGame"]);
+        assert!(list.items.iter().all(|i| i.kind == 21));
+
+        let list = complete(&conn, &in_method("UE_LOG(LogIn|, Warning, TEXT(\"Hit\"));"));
+        assert_eq!(labels(&list), ["LogInventory"]);
+    }
+
+    #[test]
+    fn console_names_offered_in_registration_strings() {
+        let conn = indexed(&[("Game/Log.h", CONSOLE_HEADER)]);
+        let list = complete(&conn, &in_method("IConsoleManager::Get().FindConsoleVariable(TEXT(\"my.|\"));"));
+        let kinds: Vec<(&str, i64)> = list.items.iter().map(|i| (i.label.as_str(), i.kind)).collect();
+        assert_eq!(kinds, [("my.DumpInventory", 3), ("my.ShowDebug", 12)]);
+    }
+
+    const ITEM_HEADER: &str = r#"
+struct FInventoryItem
+{
+    int32 Count;
+    float GetWeight() const;
+};
+
+class UItemHolder
+{
+public:
+    void Drop();
+};
+"#;
+
+    #[test]
+    fn structured_binding_over_map_iterator() {
+        let conn = indexed(&[("Item.h", ITEM_HEADER)]);
+        let source = "void Use()\n{\n    TMap<FName, FInventoryItem> Items;\n    auto It = Items.CreateIterator();\n    auto [K, V] = *It;\n    V.|\n}\n";
+        let list = complete(&conn, source);
+        let labels = labels(&list);
+        assert!(labels.contains(&"Count"));
+        assert!(labels.contains(&"GetWeight"));
+    }
+
+    #[test]
+    fn structured_binding_in_range_for_over_map() {
+        let conn = indexed(&[("Item.h", ITEM_HEADER)]);
+        let source = "void Use()\n{\n    TMap<FName, UItemHolder*> Holders;\n    for (auto& [Name, Holder] : Holders)\n    {\n        Holder->|\n    }\n}\n";
+        assert!(labels(&complete(&conn, source)).contains(&"Drop"));
+    }
+
+    #[test]
+    fn detailed_completion_reports_receiver_type() {
+        let conn = engine();
+        let (content, line, character) = cursor(&in_method("GetRootComponent()->|"));
+        let result = process_completion_detailed(&conn, &content, line, character).unwrap();
+        assert_eq!(result["receiverType"], "USceneComponent");
+        let items = result["items"].as_array().unwrap();
+        assert!(!items.is_empty());
+        assert!(items.iter().any(|i| i["label"] == "GetComponentLocation"));
+
+        let (content, line, character) = cursor("int32 X = |;\n");
+        let result = process_completion_detailed(&conn, &content, line, character).unwrap();
+        assert!(result["receiverType"].is_null());
+    }
+
+    #[test]
+    fn subscript_on_assignment_left_side_resolves_element() {
+        let conn = indexed(&[("Item.h", ITEM_HEADER)]);
+        for body in ["Items[Index].Co| = 5;", "Items[Index].| = 5;"] {
+            let source = format!("void Use(int32 Index)\n{{\n    TArray<FInventoryItem> Items;\n    {}\n}}\n", body);
+            let list = complete(&conn, &source);
+            assert!(labels(&list).contains(&"Count"), "{}", body);
+        }
+    }
+
+    const REFLECTION_HEADER: &str = r#"
+class FField
+{
+public:
+    FName GetFName() const;
+};
+
+class FProperty : public FField
+{
+public:
+    int32 GetSize() const;
+};
+
+class FObjectProperty : public FProperty
+{
+public:
+    UClass* PropertyClass;
+};
+
+class UStruct
+{
+public:
+    FProperty* FindPropertyByName(FName InName) const;
+};
+
+class UClass : public UStruct
+{
+};
+"#;
+
+    #[test]
+    fn reflection_lookups_resolve_to_property_types() {
+        let conn = indexed(&[("Reflection.h", REFLECTION_HEADER)]);
+        let prelude = "void Inspect(UClass* Class, FProperty* Prop)\n{\n    ";
+        let run = |body: &str| {
+            let list = complete(&conn, &format!("{}{}\n}}\n", prelude, body));
+            list.items.into_iter().map(|i| i.label).collect::<Vec<_>>()
+        };
+
+        let labels = run("FindFProperty<FObjectProperty>(Class, TEXT(\"Mesh\"))->|");
+        for expected in ["PropertyClass", "GetSize", "GetFName"] {
+            assert!(labels.iter().any(|l| l == expected), "missing {}", expected);
+        }
+        assert!(run("Class->FindPropertyByName(TEXT(\"Mesh\"))->|").iter().any(|l| l == "GetSize"));
+        assert!(run("CastField<FObjectProperty>(Prop)->PropertyClass->|").iter().any(|l| l == "FindPropertyByName"));
+        assert!(run("for (TFieldIterator<FProperty> It(Class); It; ++It)\n    {\n        It->|\n    }").iter().any(|l| l == "GetSize"));
+    }
+
+    const HEALTH_HEADER: &str = r#"
+class UHealthComponent : public UActorComponent
+{
+public:
+    float GetHealth() const;
+    void Heal(float Amount);
+};
+"#;
+
+    fn engine_with(extra: &[(&str, &str)]) -> Connection {
+        let mut files = vec![("Engine/Actor.h", ENGINE_HEADER), ("Game/MyActor.h", GAME_HEADER)];
+        files.extend_from_slice(extra);
+        indexed(&files)
+    }
+
+    #[test]
+    fn get_component_by_class_resolves_template_and_static_class_forms() {
+        let conn = engine_with(&[("Game/Health.h", HEALTH_HEADER)]);
+        for body in [
+            "GetComponentByClass<UHealthComponent>()->|",
+            "AActor* Other = nullptr;\n    Other->GetComponentByClass<UHealthComponent>()->|",
+            "AActor* Other = nullptr;\n    Other->FindComponentByClass<UHealthComponent>()->|",
+            "AActor* Other = nullptr;\n    Other->GetComponentByClass(UHealthComponent::StaticClass())->|",
+        ] {
+            let list = complete(&conn, &in_method(body));
+            let labels = labels(&list);
+            assert!(labels.contains(&"GetHealth"), "{}", body);
+            assert!(labels.contains(&"GetOwner"), "{}", body);
+        }
+    }
+
+    #[test]
+    fn diagnostics_flag_unknown_members_on_resolved_receivers_only() {
+        let conn = engine();
+        let content = in_method(concat!(
+            "AActor* Other = nullptr;\n",
+            "    Other->GetActorLocatoin();\n",
+            "    Other->GetActorLocation();\n",
+            "    Health = 0;\n",
+            "    UUnindexedThing* Thing = nullptr;\n",
+            "    Thing->Whatever();\n",
+            "    Mystery.Foo();",
+        ));
+        let diagnostics = process_diagnostics(&conn, &content, None).unwrap();
+        let diagnostics = diagnostics.as_array().unwrap();
+        assert_eq!(diagnostics.len(), 1, "{:?}", diagnostics);
+        assert_eq!(diagnostics[0]["message"], "'GetActorLocatoin' is not a member of 'AActor'");
+        assert_eq!(diagnostics[0]["severity"], 2);
+        assert_eq!(diagnostics[0]["range"]["start"], json!({ "line": 3, "character": 11 }));
+        assert_eq!(diagnostics[0]["range"]["end"], json!({ "line": 3, "character": 27 }));
+    }
+
+    #[test]
+    fn range_for_over_c_array_resolves_element_type() {
+        let conn = indexed(&[("Item.h", ITEM_HEADER)]);
+        let source = "void Use()\n{\n    FInventoryItem Items[8];\n    for (auto& Item : Items)\n    {\n        Item.|\n    }\n}\n";
+        assert!(labels(&complete(&conn, source)).contains(&"Count"));
+
+        let source = "void Use()\n{\n    UItemHolder* Holders[4];\n    for (auto* Holder : Holders)\n    {\n        Holder->|\n    }\n}\n";
+        assert!(labels(&complete(&conn, source)).contains(&"Drop"));
+    }
+
+    const INTERFACE_HEADER: &str = r#"
+class UDamageable : public UInterface
+{
+};
+
+class IDamageable
+{
+public:
+    virtual void ApplyDamage(float Amount);
+    virtual bool IsDead() const;
+};
+"#;
+
+    #[test]
+    fn implements_guard_then_interface_cast() {
+        let conn = engine_with(&[("Game/Damageable.h", INTERFACE_HEADER)]);
+        let body = "AActor* Other = nullptr;\n    if (Other->Implements<UDamageable>())\n    {\n        Cast<IDamageable>(Other)->|\n    }";
+        let list = complete(&conn, &in_method(body));
+        let guarded = labels(&list);
+        assert!(guarded.contains(&"ApplyDamage"));
+        assert!(guarded.contains(&"IsDead"));
+        assert!(!guarded.contains(&"GetActorLocation"));
+
+        let body = "AActor* Other = nullptr;\n    if (IDamageable* Damageable = Cast<IDamageable>(Other))\n    {\n        Damageable->|\n    }";
+        assert!(labels(&complete(&conn, &in_method(body))).contains(&"ApplyDamage"));
+    }
+
+    const LOCALIZATION_SOURCE: &str = r#"
+#define LOCTEXT_NAMESPACE "MyGameHUD"
+const FText ScoreLabel = LOCTEXT("Score", "Score");
+#undef LOCTEXT_NAMESPACE
+
+const FText StartLabel = NSLOCTEXT("MyGameMenu", "Start", "Start");
+"#;
+
+    #[test]
+    fn localization_namespaces_and_keys_offered() {
+        let conn = indexed(&[("Game/Labels.cpp", LOCALIZATION_SOURCE)]);
+        let list = complete(&conn, "FText T = NSLOCTEXT(|, \"Key\", \"Text\");\n");
+        assert_eq!(labels(&list), ["MyGameHUD", "MyGameMenu"]);
+        // 引用符の外ならそのまま挿入できる文字列リテラルにする
+        assert_eq!(list.items[0].insert_text, "\"MyGameHUD\"");
+
+        let list = complete(&conn, "FText T = NSLOCTEXT(\"MyGameM|");
+        assert_eq!(labels(&list), ["MyGameMenu"]);
+        assert_eq!(list.items[0].insert_text, "MyGameMenu");
+
+        let list = complete(&conn, "FText T = NSLOCTEXT(\"MyGameMenu\", \"|");
+        assert_eq!(labels(&list), ["Start"]);
+
+        let list = complete(&conn, "#define LOCTEXT_NAMESPACE \"MyGameHUD\"\nFText T = LOCTEXT(\"|");
+        assert_eq!(labels(&list), ["Score"]);
+    }
+
+    #[test]
+    fn cast_to_base_then_value_member_chain() {
+        let conn = engine();
+        let list = complete(&conn, &in_method("UObject* Obj = nullptr;\n    Cast<AActor>(Obj)->GetActorTransform().|"));
+        let transform = labels(&list);
+        assert!(transform.contains(&"GetLocation"));
+        assert!(transform.contains(&"GetRotation"));
+
+        // 派生クラスから基底へのキャストでは基底のメンバーだけになる
+        let list = complete(&conn, &in_method("Cast<AActor>(this)->|"));
+        let base = labels(&list);
+        assert!(base.contains(&"GetActorTransform"));
+        assert!(!base.contains(&"Health"));
+    }
+
+    #[test]
+    fn unprefixed_filter_text_for_prefixed_types() {
+        let header = "namespace MyGame\n{\nclass UFoo : public UObject\n{\n};\n}\n";
+        let conn = engine_with(&[("Game/Foo.h", header)]);
+        let source = in_method("MyGame::|");
+
+        let config = CompletionConfig { unprefixed_filter_text: true, ..Default::default() };
+        let list = complete_with(&conn, &source, &config);
+        let item = list.items.iter().find(|i| i.label == "UFoo").expect("UFoo");
+        assert_eq!(item.filter_text.as_deref(), Some("Foo UFoo"));
+        assert_eq!(item.insert_text, "UFoo");
+
+        let list = complete(&conn, &source);
+        let item = list.items.iter().find(|i| i.label == "UFoo").expect("UFoo");
+        assert_eq!(item.filter_text, None);
+    }
+
+    #[test]
+    fn recency_boost_ranks_current_file_members_first() {
+        let base = "class ABase\n{\npublic:\n    void Fire(int32 Count);\n};\n";
+        let derived = "class ADerived : public ABase\n{\npublic:\n    void Fire();\n};\n";
+        let conn = indexed(&[("Game/Base.h", base), ("Engine/Derived.h", derived)]);
+        let (content, line, character) = cursor("void Use(ADerived* Weapon)\n{\n    Weapon->|\n}\n");
+        let fire_classes = |config: &CompletionConfig| {
+            let list = with_config(config, || complete_with_config(&conn, &content, line, character, Some("Game/Base.h".to_string()), config)).unwrap();
+            let mut fire: Vec<_> = list.items.into_iter().filter(|i| i.label == "Fire").collect();
+            fire.sort_by(|a, b| a.sort_text.cmp(&b.sort_text));
+            fire.into_iter().map(|i| i.data.unwrap().class).collect::<Vec<_>>()
+        };
+
+        // 既定では派生クラスの宣言が上
+        assert_eq!(fire_classes(&CompletionConfig::default()), ["ADerived", "ABase"]);
+        // 編集中のファイルで定義された同名メンバーがエンジン側より上に来る
+        let config = CompletionConfig { recency_boost: true, ..Default::default() };
+        assert_eq!(fire_classes(&config), ["ABase", "ADerived"]);
+    }
+
+    const WEAPON_HEADER: &str = r#"
+struct FDamageInfo
+{
+    float Amount;
+    bool bCritical;
+};
+
+struct FWeaponStats
+{
+    FDamageInfo Compute() const;
+};
+
+struct FWeapon
+{
+    FWeaponStats Stats;
+};
+
+class AShooter : public AActor
+{
+public:
+    FWeapon Weapon;
+    void Fire();
+};
+"#;
+
+    #[test]
+    fn member_field_then_method_chain() {
+        let conn = engine_with(&[("Game/Shooter.h", WEAPON_HEADER)]);
+        let list = complete(&conn, "void AShooter::Fire()\n{\n    Weapon.Stats.Compute().|\n}\n");
+        let damage = labels(&list);
+        assert!(damage.contains(&"Amount"));
+        assert!(damage.contains(&"bCritical"));
+        assert!(!damage.contains(&"Compute"));
+    }
+
+    #[test]
+    fn template_parameter_receivers() {
+        let conn = engine();
+        // 既定型も制約も無い T は空で返す (エラーにしない)
+        let list = complete(&conn, "template <typename T>\nvoid AMyActor::Apply(T* Target)\n{\n    Target->|\n}\n");
+        assert!(list.items.is_empty());
+
+        let list = complete(&conn, "template <typename T = AActor>\nvoid AMyActor::Apply(T* Target)\n{\n    Target->|\n}\n");
+        assert!(labels(&list).contains(&"GetActorLocation"));
+
+        let source = "template <typename T, typename = TEnableIf<TIsDerivedFrom<T, AActor>::Value>>\nvoid AMyActor::Apply(T* Target)\n{\n    Target->|\n}\n";
+        let list = complete(&conn, source);
+        assert!(labels(&list).contains(&"GetActorLocation"));
+    }
+
+    #[test]
+    fn subobject_name_heuristic_uses_matching_member() {
+        let conn = engine();
+        let config = CompletionConfig { subobject_name_heuristic: true, ..Default::default() };
+        for body in ["GetDefaultSubobjectByName(TEXT(\"Mesh\"))->|", "this->GetDefaultSubobjectByName(TEXT(\"Mesh\"))->|"] {
+            let list = complete_with(&conn, &in_method(body), &config);
+            assert!(labels(&list).contains(&"GetComponentLocation"), "{}", body);
+        }
+
+        // 無効時と一致するメンバーが無いときは宣言どおり UObject
+        let list = complete(&conn, &in_method("GetDefaultSubobjectByName(TEXT(\"Mesh\"))->|"));
+        assert!(labels(&list).contains(&"GetWorld"));
+        assert!(!labels(&list).contains(&"GetComponentLocation"));
+        let list = complete_with(&conn, &in_method("GetDefaultSubobjectByName(TEXT(\"Unknown\"))->|"), &config);
+        assert!(!labels(&list).contains(&"GetComponentLocation"));
+    }
+
+    #[test]
+    fn virtual_members_lists_overridable_functions() {
+        let header = concat!(
+            "class ABase\n{\npublic:\n    virtual void Tick(float DeltaSeconds);\n    void Fire();\n    virtual void Reload() final;\n",
+            "    virtual void Apply(int32 Amount);\n    virtual void Apply(float Scale);\n",
+            "    virtual void Aim(int32 Mode) final;\n    virtual void Aim(float Yaw);\n",
+            "protected:\n    virtual int32 GetAmmo() const;\n};\n\n",
+            "class ADerived : public ABase\n{\npublic:\n    virtual void Tick(float DeltaSeconds) override;\n    virtual void Apply(int32 Amount) override;\n};\n",
+        );
+        let conn = indexed(&[("Game/Weapon.h", header)]);
+        let result = virtual_members(&conn, "ADerived").unwrap();
+        let entries = result.as_array().unwrap();
+        let names: Vec<_> = entries.iter().map(|e| (e["name"].as_str().unwrap(), e["class"].as_str().unwrap())).collect();
+        // 一方のオーバーロードのオーバーライドや final は、もう一方を隠さない
+        assert_eq!(names, [("Tick", "ADerived"), ("Apply", "ADerived"), ("Apply", "ABase"), ("Aim", "ABase"), ("GetAmmo", "ABase")]);
+        assert_eq!(entries[2]["params"], "(float Scale)");
+        assert_eq!(entries[3]["params"], "(float Yaw)");
+        assert_eq!(entries[4]["access"], "protected");
+        assert_eq!(entries[0]["params"], "(float DeltaSeconds)");
+    }
+
+    #[test]
+    fn static_member_constant_chain() {
+        let header = "struct FVector\n{\n    double X;\n    double Y;\n    double Z;\n    static const FVector ZeroVector;\n};\n";
+        let conn = engine_with(&[("Core/Vector.h", header)]);
+        let list = complete(&conn, &in_method("FVector::ZeroVector.|"));
+        let vector = labels(&list);
+        assert!(vector.contains(&"X"));
+        assert!(vector.contains(&"Z"));
+
+        let config = CompletionConfig { prefix_filter: true, ..Default::default() };
+        let list = complete_with(&conn, &in_method("float Height = FVector::ZeroVector.X|"), &config);
+        assert_eq!(labels(&list), ["X"]);
+    }
+
+    #[test]
+    fn super_inside_lambda_uses_enclosing_method_class() {
+        let conn = engine();
+        for capture in ["[this]", "[&]", "[=, this]"] {
+            let body = format!("auto Deferred = {}()\n    {{\n        Super::|\n    }};", capture);
+            let list = complete(&conn, &in_method(&body));
+            let parent = labels(&list);
+            assert!(parent.contains(&"GetActorLocation"), "{}", capture);
+            assert!(!parent.contains(&"Health"), "{}", capture);
+        }
+    }
+
+    #[test]
+    fn qualified_insert_text_only_without_typed_scope() {
+        let header = "UENUM()\nenum class EWeaponType : uint8\n{\n    Rifle,\n    Pistol\n};\n\nclass AArmory : public AActor\n{\npublic:\n    static int32 MaxCount;\n};\n\nclass AMyArmory : public AArmory\n{\npublic:\n    void Restock();\n};\n";
+        let conn = engine_with(&[("Game/Armory.h", header)]);
+        let config = CompletionConfig { qualified_insert_text: true, ..Default::default() };
+        let insert_text = |source: &str, label: &str| {
+            let list = complete_with(&conn, source, &config);
+            list.items.into_iter().find(|i| i.label == label).map(|i| i.insert_text).expect(label)
+        };
+
+        // スコープが入力済みなら重ねない
+        assert_eq!(insert_text("void AMyArmory::Restock()\n{\n    auto Type = EWeaponType::Rif|\n}\n", "Rifle"), "Rifle");
+        assert_eq!(insert_text("void AMyArmory::Restock()\n{\n    int32 Count = AArmory::Max|\n}\n", "MaxCount"), "MaxCount");
+        // 未入力なら基底の静的メンバーはスコープ付き
+        assert_eq!(insert_text("void AMyArmory::Restock()\n{\n    int32 Count = Max|\n}\n", "MaxCount"), "AArmory::MaxCount");
+    }
+
+    #[test]
+    fn world_timer_manager_reference_chain() {
+        let conn = engine();
+        for body in [
+            "GetWorldTimerManager().|",
+            "this->GetWorldTimerManager().|",
+            "GetWorldTimerManager().SetTimer(Handle, 1.0f, false);\n    GetWorldTimerManager().|",
+            "FTimerManager& Timers = GetWorldTimerManager();\n    Timers.|",
+        ] {
+            let list = complete(&conn, &in_method(body));
+            let timers = labels(&list);
+            assert!(timers.contains(&"SetTimer"), "{}", body);
+            assert!(timers.contains(&"ClearTimer"), "{}", body);
+            assert!(timers.contains(&"IsTimerActive"), "{}", body);
+        }
+    }
+
+    #[test]
+    fn explain_resolution_traces_intermediate_types() {
+        let conn = engine();
+        let (content, line, character) = cursor(&in_method("GetOwner()->GetRootComponent()->|"));
+        let trace = explain_resolution(&conn, &content, line, character).unwrap();
+        assert_eq!(trace["receiverType"], "USceneComponent");
+
+        let steps = trace["steps"].as_array().unwrap();
+        let member = |name: &str| steps.iter().find(|s| s["step"] == "member" && s["member"] == name).unwrap_or_else(|| panic!("{} in {:?}", name, steps));
+        // 1 段目の戻り値 (AActor*) が 2 段目の検索先になる
+        assert_eq!(member("GetOwner")["foundIn"], "AActor");
+        assert!(member("GetOwner")["type"].as_str().unwrap().starts_with("AActor"));
+        assert_eq!(member("GetRootComponent")["class"], "AActor");
+        assert!(member("GetRootComponent")["type"].as_str().unwrap().starts_with("USceneComponent"));
+    }
+
+    #[test]
+    fn nearby_assignments_pick_the_matching_variable() {
+        let conn = engine_with(&[("Game/Health.h", HEALTH_HEADER)]);
+        let declarations = "auto Comp = NewObject<USceneComponent>(this);\n    auto CompB = NewObject<UHealthComponent>(this);\n    bool bOK = IsValid(Comp);\n    ";
+        let scene = complete(&conn, &in_method(&format!("{}Comp->|", declarations)));
+        assert!(labels(&scene).contains(&"GetComponentLocation"));
+        assert!(!labels(&scene).contains(&"Heal"));
+        let health = complete(&conn, &in_method(&format!("{}CompB->|", declarations)));
+        assert!(labels(&health).contains(&"Heal"));
+        assert!(!labels(&health).contains(&"GetComponentLocation"));
+
+        // カーソルより前で最も近い代入を使い、後ろの代入は見ない
+        let reassigned = complete(&conn, &in_method("auto Comp = NewObject<USceneComponent>(this);\n    Comp = NewObject<UHealthComponent>(this);\n    Comp->|"));
+        assert!(labels(&reassigned).contains(&"Heal"));
+        let later = complete(&conn, &in_method("auto Comp = NewObject<USceneComponent>(this);\n    Comp->|\n    Comp = NewObject<UHealthComponent>(this);"));
+        assert!(labels(&later).contains(&"GetComponentLocation"));
+        assert!(!labels(&later).contains(&"Heal"));
+    }
+
+    const HANDLER_HEADER: &str = r#"
+UCLASS()
+class AMyShooter : public AActor
+{
+    GENERATED_BODY()
+public:
+    UFUNCTION()
+    void OnHit(AActor* Other);
+
+    void Helper();
+};
+"#;
+
+    #[test]
+    fn delegate_binding_variants_complete_ufunction_handlers() {
+        let conn = engine_with(&[("Game/MyShooter.h", HANDLER_HEADER)]);
+        for binding in ["AddDynamic", "AddUniqueDynamic", "RemoveDynamic", "RemoveAll"] {
+            let list = complete(&conn, &format!("void AMyShooter::Helper()\n{{\n    OnHitDelegate.{}(this, &AMyShooter::|);\n}}\n", binding));
+            assert_eq!(labels(&list), ["OnHit"], "{}", binding);
+        }
+    }
+
+    #[test]
+    fn function_pointer_call_result() {
+        let conn = engine();
+        for call in ["(*MakeTransform)(1.0f).|", "MakeTransform(1.0f).|"] {
+            let body = format!("FTransform (*MakeTransform)(float Scale) = nullptr;\n    {}", call);
+            let list = complete(&conn, &in_method(&body));
+            assert!(labels(&list).contains(&"GetLocation"), "{}", call);
+        }
+    }
+
+    #[test]
+    fn item_defaults_hold_shared_insert_text_format() {
+        let conn = engine();
+        let config = CompletionConfig { item_defaults: true, ..Default::default() };
+        let list = complete_with(&conn, &in_method("this->|"), &config);
+        assert_eq!(list.item_defaults.as_ref().and_then(|d| d.insert_text_format), Some(1));
+        assert!(list.items.iter().all(|i| i.insert_text_format.is_none()));
+
+        let response = completion_response(list);
+        assert_eq!(response["itemDefaults"]["insertTextFormat"], 1);
+        assert!(response["items"].as_array().unwrap().iter().all(|i| i.get("insertTextFormat").is_none()));
+
+        // スニペットと通常の候補が混ざるなら各候補に残す
+        let config = CompletionConfig { item_defaults: true, snippets: true, ..Default::default() };
+        let list = complete_with(&conn, &in_method("this->|"), &config);
+        assert!(list.item_defaults.is_none());
+        assert!(list.items.iter().all(|i| i.insert_text_format.is_some()));
+    }
+
+    #[test]
+    fn templated_pawn_accessors_resolve_to_template_argument() {
+        let header = "class AController : public AActor\n{\n};\n\nclass AMyController : public AController\n{\npublic:\n    void ShowMenu();\n};\n\nclass AMyPawn : public APawn\n{\npublic:\n    void Honk();\n};\n";
+        let conn = indexed(&[("Pawn.h", PAWN_HEADER), ("Game/MyPawn.h", header)]);
+        let method = |body: &str| format!("void AMyPawn::Honk()\n{{\n    {}\n}}\n", body);
+        for body in ["GetController<AMyController>()->|", "this->GetController<AMyController>()->|"] {
+            let list = complete(&conn, &method(body));
+            assert!(labels(&list).contains(&"ShowMenu"), "{}", body);
+        }
+        let list = complete(&conn, &method("GetInstigator<AMyPawn>()->|"));
+        assert!(labels(&list).contains(&"Honk"));
+    }
+
+    #[test]
+    fn verify_type_complete_flags_missing_middle_ancestor() {
+        // AMiddle (AActor の派生) のヘッダーが索引されていない
+        let header = "class AGameActor : public AMiddle\n{\npublic:\n    void Fire();\n};\n";
+        let conn = engine_with(&[("Game/GameActor.h", header)]);
+        let report = verify_type_complete(&conn, "AGameActor").unwrap();
+        assert!(report.indexed);
+        assert_eq!(report.missing_ancestors, ["AMiddle"]);
+        assert!(report.indexed_ancestors.is_empty());
+
+        let report = verify_type_complete(&conn, "AMyActor").unwrap();
+        assert!(report.indexed);
+        assert_eq!(report.indexed_ancestors, ["AActor", "UObject"]);
+        assert!(report.missing_ancestors.is_empty());
+    }
+
+    #[test]
+    fn templated_game_getters_and_chained_game_mode() {
+        let header = "class UWorld : public UObject\n{\npublic:\n    AGameModeBase* GetAuthGameMode() const;\n};\n\nclass AMyGameMode : public AActor\n{\npublic:\n    void StartMatch();\n};\n\nclass UMyGameInstance : public UObject\n{\npublic:\n    void SaveProgress();\n};\n";
+        let conn = engine_with(&[("Game/MyGameMode.h", header)]);
+        let list = complete(&conn, &in_method("GetWorld()->GetAuthGameMode<AMyGameMode>()->|"));
+        assert!(labels(&list).contains(&"StartMatch"));
+        let list = complete(&conn, &in_method("GetGameInstance<UMyGameInstance>()->|"));
+        assert!(labels(&list).contains(&"SaveProgress"));
+    }
+
+    #[test]
+    fn deprecated_and_editor_only_members_sink() {
+        let header = "class AGun : public AActor\n{\npublic:\n    void Fire();\n    [[deprecated(\"Use Fire\")]] void Aim();\n#if WITH_EDITOR\n    void DebugDraw();\n#endif\n};\n";
+        let conn = engine_with(&[("Game/Gun.h", header)]);
+        let source = "void Use(AGun* Gun)\n{\n    Gun->|\n}\n";
+        let order = |config: &CompletionConfig| {
+            let mut items = complete_with(&conn, source, config).items;
+            items.retain(|i| ["Fire", "Aim", "DebugDraw"].contains(&i.label.as_str()));
+            items.sort_by(|a, b| a.sort_text.cmp(&b.sort_text));
+            items.into_iter().map(|i| i.label).collect::<Vec<_>>()
+        };
+
+        assert_eq!(order(&CompletionConfig::default()), ["Aim", "DebugDraw", "Fire"]);
+        let config = CompletionConfig { deprecated_penalty: 1, editor_only_penalty: 2, ..Default::default() };
+        assert_eq!(order(&config), ["Fire", "Aim", "DebugDraw"]);
+    }
+
+    #[test]
+    fn static_local_variable() {
+        let conn = engine();
+        for declaration in ["static FTransform Cached;", "static const FTransform Cached;", "static FTransform Cached = FTransform();"] {
+            let list = complete(&conn, &in_method(&format!("{}\n    Cached.|", declaration)));
+            assert!(labels(&list).contains(&"GetLocation"), "{}", declaration);
+        }
+        let list = complete(&conn, &in_method("static AActor* Owner = nullptr;\n    Owner->|"));
+        assert!(labels(&list).contains(&"GetActorLocation"));
+    }
+
+    #[test]
+    fn weak_pointer_get_resolves_inner_type() {
+        let conn = engine();
+        for body in [
+            "TWeakObjectPtr<AActor> WeakActor;\n    WeakActor.Get()->|",
+            "TWeakObjectPtr<AActor> WeakActor;\n    WeakActor->|",
+        ] {
+            let list = complete(&conn, &in_method(body));
+            assert!(labels(&list).contains(&"GetActorLocation"), "{}", body);
+        }
+    }
+
+    #[test]
+    fn smart_pointer_locals_and_members_unwrap() {
+        let conn = engine();
+        for declaration in ["TObjectPtr<USceneComponent> Root;", "TWeakObjectPtr<USceneComponent> Root;", "USceneComponent* Root = nullptr;"] {
+            let list = complete(&conn, &in_method(&format!("{}\n    Root->|", declaration)));
+            let scene = labels(&list);
+            assert!(scene.contains(&"GetComponentLocation"), "{}", declaration);
+            assert!(scene.contains(&"Activate"), "{}", declaration);
+        }
+        // メンバー変数 TObjectPtr<USceneComponent> Mesh
+        let list = complete(&conn, &in_method("Mesh->|"));
+        assert!(labels(&list).contains(&"GetComponentLocation"));
+    }
+
+    #[test]
+    fn strict_mode_reports_unhandled_contexts() {
+        let conn = engine();
+        let strict = CompletionConfig { strict: true, ..Default::default() };
+        let lenient = CompletionConfig::default();
+        let run = |source: &str, config: &CompletionConfig| {
+            let (content, line, character) = cursor(source);
+            with_config(config, || complete_with_config(&conn, &content, line, character, None, config))
+        };
+
+        // 型解決で扱わないノード種別 (binary_expression)
+        let source = in_method("(Health + 1).|");
+        let err = run(&source, &strict).unwrap_err();
+        assert!(err.to_string().contains("binary_expression"), "{}", err);
+        assert!(run(&source, &lenient).unwrap().items.is_empty());
+
+        // クラスの外で補完対象が見つからない位置
+        let source = "int32 Counter = 0;|\n";
+        assert!(run(source, &strict).is_err());
+        assert!(run(source, &lenient).unwrap().items.is_empty());
+    }
+
+    const CHAIN_HEADER: &str = r#"
+class UStageD : public UObject
+{
+public:
+    void Finish();
+};
+
+class UStageC : public UObject
+{
+public:
+    UStageD* GetD() const;
+};
+
+class UStageB : public UObject
+{
+public:
+    UStageC* GetC() const;
+};
+
+class UStageA : public UObject
+{
+public:
+    UStageB* GetB() const;
+};
+
+class UChainRoot : public UObject
+{
+public:
+    UStageA* GetA() const;
+    void Run();
+};
+"#;
+
+    #[test]
+    fn four_deep_call_chain() {
+        let conn = engine_with(&[("Game/Chain.h", CHAIN_HEADER)]);
+        let run = |body: &str| complete(&conn, &format!("void UChainRoot::Run()\n{{\n    {}\n}}\n", body));
+        let stage_d = run("GetA()->GetB()->GetC()->GetD()->|");
+        assert!(labels(&stage_d).contains(&"Finish"));
+        assert!(!labels(&stage_d).contains(&"GetD"));
+        assert!(labels(&run("this->GetA()->GetB()->GetC()->GetD()->|")).contains(&"Finish"));
+        // 途中で止めればその段の型
+        let stage_c = run("GetA()->GetB()->GetC()->|");
+        assert!(labels(&stage_c).contains(&"GetD"));
+        assert!(!labels(&stage_c).contains(&"Finish"));
+    }
+
+    #[test]
+    fn cast_of_subscript_uses_template_argument() {
+        let conn = engine();
+        for body in [
+            "Cast<AMyActor>(Targets[0])->|",
+            "Cast<AMyActor>(Targets[Targets.Num() - 1])->|",
+            "TArray<UObject*> Objects;\n    Cast<AMyActor>(Objects[Index + 1])->|",
+        ] {
+            let list = complete(&conn, &in_method(body));
+            assert!(labels(&list).contains(&"Health"), "{}", body);
+        }
+    }
+
+    #[test]
+    fn members_split_across_class_rows_are_merged() {
+        let first = "class USplit : public UObject\n{\npublic:\n    void First();\n};\n";
+        let second = "class USplit\n{\npublic:\n    void Second();\n    int32 Count;\n};\n";
+        let conn = engine_with(&[("Game/Split.h", first), ("Game/SplitExtra.h", second)]);
+        let list = complete(&conn, "void Use(USplit* Split)\n{\n    Split->|\n}\n");
+        let merged = labels(&list);
+        assert!(merged.contains(&"First"));
+        assert!(merged.contains(&"Second"));
+        assert!(merged.contains(&"Count"));
+        assert!(merged.contains(&"GetWorld"));
+        assert_eq!(merged.iter().filter(|l| **l == "First").count(), 1);
+    }
+
+    #[test]
+    fn find_by_key_returns_element_pointer() {
+        let conn = engine_with(&[("Game/Item.h", ITEM_HEADER)]);
+        for call in ["Items.FindByKey(Key)->|", "Items.FindByPredicate([](const FInventoryItem& Item) { return Item.Count > 0; })->|"] {
+            let list = complete(&conn, &in_method(&format!("TArray<FInventoryItem> Items;\n    {}", call)));
+            let item = labels(&list);
+            assert!(item.contains(&"Count"), "{}", call);
+            assert!(item.contains(&"GetWeight"), "{}", call);
+        }
+    }
+
+    #[test]
+    fn namespace_label_details_disambiguate_same_named_types() {
+        let first = "namespace GameA\n{\nnamespace Shared\n{\nstruct FConfig\n{\n};\n}\n}\n";
+        let second = "namespace GameB\n{\nnamespace Shared\n{\nstruct FConfig\n{\n};\n}\n}\n";
+        let conn = engine_with(&[("Game/A.h", first), ("Game/B.h", second)]);
+        let source = in_method("Shared::|");
+
+        let config = CompletionConfig { namespace_label_details: true, ..Default::default() };
+        let list = complete_with(&conn, &source, &config);
+        let mut descriptions: Vec<_> = list.items.iter()
+            .filter(|i| i.label == "FConfig")
+            .map(|i| i.label_details.as_ref().and_then(|d| d.description.clone()).unwrap_or_default())
+            .collect();
+        descriptions.sort();
+        assert_eq!(descriptions, ["GameA::Shared", "GameB::Shared"]);
+
+        // 無効時は 1 つにまとまり、名前空間は付かない
+        let list = complete(&conn, &source);
+        let plain: Vec<_> = list.items.iter().filter(|i| i.label == "FConfig").collect();
+        assert_eq!(plain.len(), 1);
+        assert!(plain[0].label_details.is_none());
+    }
+
+    const NAMESPACE_HEADER: &str = r#"
+namespace MyGame
+{
+struct FSettings
+{
+    static int32 MaxPlayers;
+    static FSettings Get();
+};
+
+namespace Net
+{
+struct FPacket
+{
+    static int32 HeaderSize;
+    static FPacket Make();
+};
+}
+}
+
+namespace Legacy
+{
+struct FPacket
+{
+    static int32 OldSize;
+};
+}
+"#;
+
+    #[test]
+    fn multi_level_scopes() {
+        let conn = engine_with(&[("Game/Namespaces.h", NAMESPACE_HEADER)]);
+        let two = complete(&conn, &in_method("MyGame::FSettings::|"));
+        assert!(labels(&two).contains(&"MaxPlayers"));
+        assert!(labels(&two).contains(&"Get"));
+
+        let three = complete(&conn, &in_method("MyGame::Net::FPacket::|"));
+        assert!(labels(&three).contains(&"HeaderSize"));
+        assert!(!labels(&three).contains(&"OldSize"));
+
+        // 名前空間だけのスコープは中の型を出す
+        let namespace = complete(&conn, &in_method("MyGame::Net::|"));
+        assert_eq!(labels(&namespace), ["FPacket"]);
+    }
+
+    #[test]
+    fn optional_accessors_resolve_inner_type() {
+        let conn = engine();
+        for call in ["Opt.GetPtrOrNull()->|", "Opt.GetValue().|", "Opt.Get(FTransform()).|"] {
+            let list = complete(&conn, &in_method(&format!("TOptional<FTransform> Opt;\n    {}", call)));
+            assert!(labels(&list).contains(&"GetLocation"), "{}", call);
+        }
+    }
+
+    #[test]
+    fn enum_values_report_integers() {
+        let header = "UENUM()\nenum class EAmmoType : uint8\n{\n    None,\n    Light = 5,\n    Heavy,\n    Explosive = 1 << 4,\n    Mixed = Light | Explosive\n};\n";
+        let conn = indexed(&[("Game/Ammo.h", header)]);
+        let result = enum_values(&conn, "EAmmoType").unwrap();
+        let values: Vec<_> = result.as_array().unwrap().iter().map(|v| (v["name"].as_str().unwrap(), v["value"].as_i64())).collect();
+        assert_eq!(values, [("None", Some(0)), ("Light", Some(5)), ("Heavy", Some(6)), ("Explosive", Some(16)), ("Mixed", Some(21))]);
+        assert_eq!(result[3]["expression"], "1 << 4");
+
+        assert_eq!(enum_values(&conn, "EUnknown").unwrap(), json!([]));
+    }
+
+    #[test]
+    fn auto_range_for_over_containers() {
+        let conn = engine_with(&[("Game/Item.h", ITEM_HEADER)]);
+        let cases = [
+            // TArray<AActor*> のメンバー変数
+            ("for (auto Target : Targets)\n    {\n        Target->|\n    }", "GetActorLocation"),
+            ("TSet<USceneComponent*> Parts;\n    for (auto* Part : Parts)\n    {\n        Part->|\n    }", "GetComponentLocation"),
+            // TMap はキーと値で別の型
+            ("TMap<FInventoryItem, AActor*> Owners;\n    for (auto& Pair : Owners)\n    {\n        Pair.Key.|\n    }", "GetWeight"),
+            ("TMap<FInventoryItem, AActor*> Owners;\n    for (auto& Pair : Owners)\n    {\n        Pair.Value->|\n    }", "GetActorLocation"),
+            // 入れ子のコンテナ
+            ("TArray<TArray<AActor*>> Grid;\n    for (auto& Row : Grid)\n    {\n        for (auto* Cell : Row)\n        {\n            Cell->|\n        }\n    }", "GetActorLocation"),
+        ];
+        for (body, expected) in cases {
+            let list = complete(&conn, &in_method(body));
+            assert!(labels(&list).contains(&expected), "{}", body);
+        }
+    }
+
+    #[test]
+    fn gathered_components_resolve_element_type() {
+        let conn = engine_with(&[("Game/Health.h", HEALTH_HEADER)]);
+        let gather = "TArray<UHealthComponent*> Comps;\n    GetComponents<UHealthComponent>(Comps);\n    ";
+        for usage in [
+            "for (UHealthComponent* Comp : Comps)\n    {\n        Comp->|\n    }",
+            "for (auto* Comp : Comps)\n    {\n        Comp->|\n    }",
+            "Comps[0]->|",
+        ] {
+            let list = complete(&conn, &in_method(&format!("{}{}", gather, usage)));
+            assert!(labels(&list).contains(&"Heal"), "{}", usage);
+        }
+        let list = complete(&conn, &in_method("TInlineComponentArray<UHealthComponent*> Comps(this);\n    for (auto* Comp : Comps)\n    {\n        Comp->|\n    }"));
+        assert!(labels(&list).contains(&"Heal"));
+    }
+
+    #[test]
+    fn item_hook_rewrites_items() {
+        let conn = engine();
+        let hook = ItemHook::new(|item: &mut CompletionItem| {
+            item.label = format!("{} (game)", item.label);
+            item.sort_text = Some("0".to_string());
+        });
+        let config = CompletionConfig { item_hook: Some(hook), ..Default::default() };
+        let list = complete_with(&conn, &in_method("this->|"), &config);
+        assert!(labels(&list).contains(&"Health (game)"));
+        assert!(list.items.iter().all(|i| i.label.ends_with(" (game)") && i.sort_text.as_deref() == Some("0")));
+        // 挿入するテキストはフックが触らない限りそのまま
+        assert!(list.items.iter().any(|i| i.insert_text == "Health"));
+    }
+
+    #[test]
+    fn super_uses_first_parent_and_is_empty_without_one() {
+        let header = "class AHeroActor : public ACharacter, public ISelectable\n{\npublic:\n    void Rally();\n};\n\nclass FOrphan\n{\npublic:\n    void Run();\n};\n";
+        let conn = indexed(&[("Pawn.h", PAWN_HEADER), ("Game/Hero.h", header)]);
+        let list = complete(&conn, "void AHeroActor::Rally()\n{\n    Super::|\n}\n");
+        let parent = labels(&list);
+        assert!(parent.contains(&"Jump"));
+        assert!(parent.contains(&"GetActorLocation"));
+        assert!(!parent.contains(&"Select"));
+        assert!(!parent.contains(&"Rally"));
+
+        let list = complete(&conn, "void AHeroActor::Rally()\n{\n    Super::Super::|\n}\n");
+        let grandparent = labels(&list);
+        assert!(grandparent.contains(&"GetController"));
+        assert!(!grandparent.contains(&"Jump"));
+
+        let list = complete(&conn, "void FOrphan::Run()\n{\n    Super::|\n}\n");
+        assert!(list.items.is_empty());
+    }
+
+    #[test]
+    fn constexpr_static_member_chain() {
+        let header = "struct FColor\n{\n    uint8 R;\n    uint8 G;\n    uint8 B;\n    FColor WithAlpha(uint8 Alpha) const;\n};\n\nclass UPalette : public UObject\n{\npublic:\n    static constexpr FColor DefaultColor = FColor(255, 255, 255);\n    static inline constexpr FColor AccentColor{0, 128, 255};\n    static constexpr auto HighlightColor = FColor(255, 0, 0);\n};\n";
+        let conn = engine_with(&[("Game/Palette.h", header)]);
+        for body in ["UPalette::DefaultColor.|", "UPalette::AccentColor.|", "UPalette::HighlightColor.|", "UPalette::DefaultColor.WithAlpha(128).|"] {
+            let list = complete(&conn, &in_method(body));
+            let color = labels(&list);
+            assert!(color.contains(&"R"), "{}", body);
+            assert!(color.contains(&"WithAlpha"), "{}", body);
+        }
+    }
+
+    #[test]
+    fn partial_member_after_arrow_filters_by_prefix() {
+        let conn = engine();
+        let source = in_method("AActor* Actor = GetOwner();\n    Actor->Get|");
+        let config = CompletionConfig { prefix_filter: true, ..Default::default() };
+        let list = complete_with(&conn, &source, &config);
+        let getters = labels(&list);
+        assert!(getters.contains(&"GetActorLocation"));
+        assert!(getters.contains(&"GetRootComponent"));
+        assert!(getters.contains(&"GetWorld"));
+        assert!(getters.iter().all(|l| l.starts_with("Get")), "{:?}", getters);
+
+        // 絞り込みを有効にしなければクライアント側に任せる (Actor の型 AActor の全メンバー)
+        let list = complete(&conn, &source);
+        assert!(labels(&list).contains(&"Tick"));
+        assert!(!labels(&list).contains(&"Health"));
+    }
+
+    const SUBSYSTEM_HEADER: &str = r#"
+class UGameInstance : public UObject
+{
+public:
+    template <typename TSubsystemClass>
+    TSubsystemClass* GetSubsystem() const;
+};
+
+class UMySubsystem : public UObject
+{
+public:
+    void Save();
+};
+
+class AHolder : public AActor
+{
+public:
+    UGameInstance* Instance;
+
+    template <typename T>
+    T* FindThing() const;
+
+    void Run();
+};
+"#;
+
+    #[test]
+    fn template_returning_members_substitute_template_argument() {
+        let conn = engine_with(&[("Game/Holder.h", SUBSYSTEM_HEADER)]);
+        let run = |body: &str| complete(&conn, &format!("void AHolder::Run()\n{{\n    {}\n}}\n", body));
+        for body in [
+            "Instance->GetSubsystem<UMySubsystem>()->|",
+            "FindThing<UMySubsystem>()->|",
+            "this->FindThing<UMySubsystem>()->|",
+            "NewObject<UMySubsystem>(this)->|",
+        ] {
+            assert!(labels(&run(body)).contains(&"Save"), "{}", body);
+        }
+        let list = run("CreateDefaultSubobject<USceneComponent>(TEXT(\"Root\"))->|");
+        assert!(labels(&list).contains(&"GetComponentLocation"));
+    }
+
+    #[test]
+    fn duplicate_object_and_checked_casts() {
+        let conn = engine_with(&[("Game/Health.h", HEALTH_HEADER)]);
+        for body in [
+            "DuplicateObject<UHealthComponent>(Source, this)->|",
+            "CastChecked<UHealthComponent>(GetOwner())->|",
+            "CastChecked<UHealthComponent>(Source, ECastCheckedType::NullAllowed)->|",
+            "CastEnsured<UHealthComponent>(Source)->|",
+        ] {
+            let list = complete(&conn, &in_method(&format!("UObject* Source = nullptr;\n    {}", body)));
+            assert!(labels(&list).contains(&"Heal"), "{}", body);
+        }
+    }
+
+    #[test]
+    fn member_category_is_exposed_for_grouping() {
+        let header = "UCLASS()\nclass AGunner : public AActor\n{\n    GENERATED_BODY()\npublic:\n    UPROPERTY(EditAnywhere, Category = \"Combat|Weapon\")\n    int32 Ammo;\n\n    UFUNCTION(BlueprintCallable, Category=Combat)\n    void Reload();\n\n    void Helper();\n};\n";
+        let conn = engine_with(&[("Game/Gunner.h", header)]);
+        let source = "void Use(AGunner* Gunner)\n{\n    Gunner->|\n}\n";
+        let category = |list: &CompletionList, label: &str| {
+            let item = list.items.iter().find(|i| i.label == label).expect(label);
+            (item.data.as_ref().and_then(|d| d.category.clone()), item.label_details.as_ref().and_then(|d| d.description.clone()))
+        };
+
+        let list = complete(&conn, source);
+        assert_eq!(category(&list, "Ammo"), (Some("Combat|Weapon".to_string()), None));
+        assert_eq!(category(&list, "Reload"), (Some("Combat".to_string()), None));
+        assert_eq!(category(&list, "Helper"), (None, None));
+
+        let config = CompletionConfig { category_label_details: true, ..Default::default() };
+        let list = complete_with(&conn, source, &config);
+        assert_eq!(category(&list, "Ammo"), (Some("Combat|Weapon".to_string()), Some("Combat|Weapon".to_string())));
+    }
+
+    #[test]
+    fn cast_expressions_resolve_to_target_type() {
+        let conn = engine();
+        for body in [
+            "Cast<AMyActor>(Hit.GetActor())->|",
+            "CastChecked<AMyActor>(GetOwner())->|",
+            "StaticCast<AMyActor*>(GetOwner())->|",
+            "static_cast<AMyActor*>(GetOwner())->|",
+            "dynamic_cast<AMyActor*>(GetOwner())->|",
+            "((AMyActor*)GetOwner())->|",
+        ] {
+            let list = complete(&conn, &in_method(&format!("FHitResult Hit;\n    {}", body)));
+            assert!(labels(&list).contains(&"Health"), "{}", body);
+        }
+    }
+
+    #[test]
+    fn nested_container_double_subscript() {
+        let conn = engine_with(&[("Game/Item.h", ITEM_HEADER)]);
+        for body in [
+            "TMap<FName, TArray<FInventoryItem>> Bags;\n    Bags[Key][0].|",
+            "TArray<TArray<FInventoryItem>> Grid;\n    Grid[Row][Column].|",
+            "TMap<FName, TArray<FInventoryItem*>> Bags;\n    Bags[Key][Index]->|",
+        ] {
+            let list = complete(&conn, &in_method(body));
+            let item = labels(&list);
+            assert!(item.contains(&"Count"), "{}", body);
+            assert!(item.contains(&"GetWeight"), "{}", body);
+        }
+    }
+
+    #[test]
+    fn typedef_chain_strips_qualifiers_and_stops_at_concrete_types() {
+        let conn = indexed(&[("Alias.h", ALIAS_HEADER)]);
+        // typedef と using が混ざった途中からでも、修飾子を外して最後まで辿る
+        assert_eq!(resolve_typedef_chain(&conn, "const FAlias3&").unwrap(), ["FAlias3", "FAlias4", "FAlias5", "FTarget"]);
+        assert_eq!(resolve_typedef_chain(&conn, "TObjectPtr<FAlias5>").unwrap(), ["FAlias5", "FTarget"]);
+        assert_eq!(resolve_typedef_chain(&conn, "FTarget").unwrap(), ["FTarget"]);
+        assert!(resolve_typedef_chain(&conn, "").unwrap().is_empty());
+    }
+
+    #[test]
+    fn nested_namespace_types_static_and_instance_members() {
+        let header = "namespace UE::Geometry\n{\nclass FDynamicMesh\n{\npublic:\n    static FDynamicMesh Empty();\n    static int32 MaxVertices;\n    int32 VertexCount() const;\n};\n}\n\nnamespace Chaos\n{\nnamespace Private\n{\nclass FImplicitObject\n{\npublic:\n    static int32 GetTypeCount();\n    FVector GetCenter() const;\n};\n}\n}\n";
+        let conn = engine_with(&[("Engine/Geometry.h", header)]);
+        let cases = [
+            ("UE::Geometry::FDynamicMesh::|", "MaxVertices"),
+            ("UE::Geometry::FDynamicMesh Mesh;\n    Mesh.|", "VertexCount"),
+            ("UE::Geometry::FDynamicMesh* MeshPtr = nullptr;\n    MeshPtr->|", "VertexCount"),
+            ("UE::Geometry::FDynamicMesh::Empty().|", "VertexCount"),
+            ("Chaos::Private::FImplicitObject::|", "GetTypeCount"),
+            ("const Chaos::Private::FImplicitObject& Implicit = *Shape;\n    Implicit.|", "GetCenter"),
+        ];
+        for (body, expected) in cases {
+            let list = complete(&conn, &in_method(body));
+            assert!(labels(&list).contains(&expected), "{}", body);
+        }
+    }
+
+    #[test]
+    fn this_inside_capturing_lambdas() {
+        let conn = engine();
+        for capture in ["[this]", "[&]"] {
+            for usage in ["this->|", "GetOwner()->|"] {
+                let body = format!("auto OnDone = {}(int32 Count)\n    {{\n        {}\n    }};", capture, usage);
+                let list = complete(&conn, &in_method(&body));
+                assert!(labels(&list).contains(&"GetActorLocation"), "{} {}", capture, usage);
+            }
+            let body = format!("auto OnDone = {}()\n    {{\n        this->|\n    }};", capture);
+            assert!(labels(&complete(&conn, &in_method(&body))).contains(&"Health"), "{}", capture);
+        }
+    }
+
+    #[test]
+    fn non_templated_new_object_uses_static_class_argument() {
+        let conn = engine_with(&[("Game/Health.h", HEALTH_HEADER)]);
+        for body in [
+            "NewObject(this, UHealthComponent::StaticClass())->|",
+            "auto* Comp = NewObject(this, UHealthComponent::StaticClass(), TEXT(\"Health\"));\n    Comp->|",
+            "UActorComponent* Comp = NewObject(this, UHealthComponent::StaticClass());\n    NewObject(GetOwner(), UHealthComponent::StaticClass())->|",
+        ] {
+            let list = complete(&conn, &in_method(body));
+            assert!(labels(&list).contains(&"Heal"), "{}", body);
+        }
+    }
+
+    #[test]
+    fn member_types_come_from_constructor_assignments() {
+        let header = "class ATurret : public AActor\n{\npublic:\n    ATurret();\n    void Fire();\n    UActorComponent* Health;\n    UActorComponent* Armor;\n    UActorComponent* Shield;\n};\n";
+        let conn = engine_with(&[("Game/Health.h", HEALTH_HEADER), ("Game/Turret.h", header)]);
+        let source = |usage: &str| format!(
+            "ATurret::ATurret()\n    : Armor(CreateDefaultSubobject<UHealthComponent>(TEXT(\"Armor\")))\n{{\n    Health = CreateDefaultSubobject<UHealthComponent>(TEXT(\"Health\"));\n    this->Shield = CreateDefaultSubobject<UHealthComponent>(TEXT(\"Shield\"));\n}}\n\nvoid ATurret::Fire()\n{{\n    {}\n}}\n",
+            usage
+        );
+        for usage in ["Health->|", "Armor->|", "Shield->|"] {
+            let list = complete(&conn, &source(usage));
+            assert!(labels(&list).contains(&"Heal"), "{}", usage);
+        }
+        // 代入の無いメンバーは宣言どおりの型
+        let list = complete(&conn, "void ATurret::Fire()\n{\n    Health->|\n}\n");
+        assert!(labels(&list).contains(&"Activate"));
+        assert!(!labels(&list).contains(&"Heal"));
+    }
+
+    #[test]
+    fn qualified_auto_declarations_infer_from_initializer() {
+        let conn = engine_with(&[("Game/Health.h", HEALTH_HEADER)]);
+        let cases = [
+            ("auto* Comp = NewObject<UHealthComponent>(this);\n    Comp->|", "Heal"),
+            ("const auto* Comp = NewObject<UHealthComponent>(this);\n    Comp->|", "Heal"),
+            ("const auto& Ref = GetActorTransform();\n    Ref.|", "GetLocation"),
+            ("auto&& Forwarded = GetActorTransform();\n    Forwarded.|", "GetLocation"),
+            ("const auto& Root = Mesh;\n    Root->|", "GetComponentLocation"),
+        ];
+        for (body, expected) in cases {
+            let list = complete(&conn, &in_method(body));
+            assert!(labels(&list).contains(&expected), "{}", body);
+        }
+    }
+
+    const DELEGATE_HEADER: &str = r#"
+DECLARE_DYNAMIC_MULTICAST_DELEGATE_OneParam(FOnHealthChanged, float, NewHealth);
+DECLARE_DYNAMIC_DELEGATE(FOnRespawn);
+
+UCLASS()
+class AHealthActor : public AActor
+{
+    GENERATED_BODY()
+public:
+    UPROPERTY(BlueprintAssignable)
+    FOnHealthChanged OnHealthChanged;
+
+    UPROPERTY()
+    FOnRespawn OnRespawn;
+
+    void Bind();
+};
+"#;
+
+    #[test]
+    fn delegate_members_offer_delegate_api() {
+        let conn = engine_with(&[("Game/HealthActor.h", DELEGATE_HEADER)]);
+        let run = |body: &str| complete(&conn, &format!("void AHealthActor::Bind()\n{{\n    {}\n}}\n", body));
+
+        let multicast = run("OnHealthChanged.|");
+        for method in ["AddDynamic", "RemoveDynamic", "Broadcast"] {
+            assert!(labels(&multicast).contains(&method), "{}", method);
+        }
+        assert!(!labels(&multicast).contains(&"BindDynamic"));
+
+        let single = run("this->OnRespawn.|");
+        assert!(labels(&single).contains(&"BindDynamic"));
+        assert!(labels(&single).contains(&"ExecuteIfBound"));
+        assert!(!labels(&single).contains(&"AddDynamic"));
+    }
+
+    #[test]
+    fn container_subscript_element_and_map_value() {
+        let conn = engine_with(&[("Game/Health.h", HEALTH_HEADER)]);
+        let cases = [
+            ("TArray<UHealthComponent*> Comps;\n    Comps[0]->|", "Heal"),
+            ("TArray<UHealthComponent*> Comps;\n    Comps[Comps.Num() - 1]->|", "Heal"),
+            ("TMap<FName, UHealthComponent*> ByName;\n    ByName[TEXT(\"Head\")]->|", "Heal"),
+            ("TMap<FName, FTransform> Sockets;\n    Sockets[Name].|", "GetLocation"),
+            // メンバー変数 TArray<AActor*> Targets
+            ("Targets[0]->|", "GetActorLocation"),
+        ];
+        for (body, expected) in cases {
+            let list = complete(&conn, &in_method(body));
+            assert!(labels(&list).contains(&expected), "{}", body);
+        }
+    }
+
+    #[test]
+    fn parenthesized_casts_and_related_ternary() {
+        let conn = engine();
+        for body in ["((AMyActor*)GetOwner())->|", "(Cast<AMyActor>(GetOwner()))->|", "((this))->|"] {
+            assert!(labels(&complete(&conn, &in_method(body))).contains(&"Health"), "{}", body);
+        }
+        assert!(labels(&complete(&conn, &in_method("(Mesh)->|"))).contains(&"GetComponentLocation"));
+
+        // AMyActor と AActor の三項演算子は共通の基底 AActor
+        let list = complete(&conn, &in_method("bool bSelf = false;\n    (bSelf ? this : GetOwner())->|"));
+        let common = labels(&list);
+        assert!(common.contains(&"GetActorLocation"));
+        assert!(!common.contains(&"Health"));
+    }
+
+    #[test]
+    fn inline_header_methods_and_out_of_line_definitions() {
+        let header = "UCLASS()\nclass AInlineActor : public AActor\n{\n    GENERATED_BODY()\npublic:\n    int32 Ammo;\n    void Reload();\n    void Fire()\n    {\n        |\n    }\n};\n";
+        let conn = engine_with(&[("Game/InlineActor.h", &header.replace('|', ""))]);
+        let inline = complete(&conn, header);
+        assert!(labels(&inline).contains(&"Reload"));
+        assert!(labels(&inline).contains(&"GetActorLocation"));
+
+        let arrow = complete(&conn, &header.replace("        |", "        this->|"));
+        assert!(labels(&arrow).contains(&"Ammo"));
+
+        let out_of_line = complete(&conn, "void AInlineActor::Reload()\n{\n    |\n}\n");
+        assert!(labels(&out_of_line).contains(&"Ammo"));
+        assert!(labels(&out_of_line).contains(&"Fire"));
+    }
+
+    const NESTED_HEADER: &str = r#"
+class UWeaponConfig : public UObject
+{
+public:
+    enum class EMode : uint8
+    {
+        Single,
+        Burst
+    };
+
+    struct FStats
+    {
+        float Damage;
+        static FStats Default();
+    };
+};
+
+// 同じ短い名前の無関係な型
+enum class EMode : uint8
+{
+    Legacy
+};
+
+struct FStats
+{
+    int32 Unrelated;
+};
+"#;
+
+    #[test]
+    fn nested_enum_and_struct_prefer_qualified_match() {
+        let conn = engine_with(&[("Game/WeaponConfig.h", NESTED_HEADER)]);
+        let modes = complete(&conn, &in_method("auto Mode = UWeaponConfig::EMode::|"));
+        assert_eq!(labels(&modes), ["Single", "Burst"]);
+
+        let stats = complete(&conn, &in_method("UWeaponConfig::FStats::|"));
+        assert!(labels(&stats).contains(&"Default"));
+        assert!(!labels(&stats).contains(&"Unrelated"));
+
+        let outer = complete(&conn, &in_method("UWeaponConfig::|"));
+        assert!(labels(&outer).contains(&"EMode"));
+        assert!(labels(&outer).contains(&"FStats"));
+    }
+
+    #[test]
+    fn core_string_types_fall_back_to_builtin_members() {
+        let conn = engine();
+        let statics = complete(&conn, &in_method("FString Joined = FString::|"));
+        assert!(labels(&statics).contains(&"Printf"));
+        assert!(statics.items.iter().find(|i| i.label == "Printf").and_then(|i| i.data.as_ref()).map_or(false, |d| d.is_static));
+
+        let cases = [
+            ("FString Name;\n    Name.|", "Len"),
+            ("FString Name;\n    Name.ToUpper().|", "TrimStartAndEnd"),
+            ("const FString& Name = GetName();\n    Name.|", "IsEmpty"),
+            ("FName Tag;\n    Tag.ToString().|", "Len"),
+            ("FText Label;\n    Label.|", "IsEmptyOrWhitespace"),
+        ];
+        for (body, expected) in cases {
+            let list = complete(&conn, &in_method(body));
+            assert!(labels(&list).contains(&expected), "{}", body);
+        }
+    }
+
+    #[test]
+    fn annotate_access_marks_instead_of_hiding() {
+        let header = "class AVault : public AActor\n{\npublic:\n    void Open();\nprotected:\n    int32 Code;\nprivate:\n    FString Secret;\n};\n";
+        let conn = engine_with(&[("Game/Vault.h", header)]);
+        let source = "void Use(AVault* Vault)\n{\n    Vault->|\n}\n";
+
+        let hidden = complete(&conn, source);
+        assert!(labels(&hidden).contains(&"Open"));
+        assert!(!labels(&hidden).contains(&"Code"));
+        assert!(!labels(&hidden).contains(&"Secret"));
+
+        let config = CompletionConfig { annotate_access: true, ..Default::default() };
+        let list = complete_with(&conn, source, &config);
+        let item = |label: &str| list.items.iter().find(|i| i.label == label).expect(label);
+        assert_eq!(item("Open").detail.as_deref(), Some("void"));
+        assert_eq!(item("Code").detail.as_deref(), Some("protected int32"));
+        assert_eq!(item("Secret").detail.as_deref(), Some("private FString"));
+
+        let json = serde_json::to_value(item("Secret")).unwrap();
+        assert_eq!(json["data"]["access"], "private");
+    }
+
+    #[test]
+    fn scoped_enum_scope_lists_only_enumerators() {
+        let enum_header = "UENUM(BlueprintType)\nenum class EWeaponSlot : uint8\n{\n    Primary,\n    Secondary,\n    Melee\n};\n";
+        // 同名のクラス (前方宣言の取り違えなど) があってもそのメンバーや継承元は出さない
+        let class_header = "class EWeaponSlot : public AActor\n{\npublic:\n    void Helper();\n};\n";
+        let conn = engine_with(&[("Game/WeaponSlot.h", enum_header), ("Game/Stray.h", class_header)]);
+        let list = complete(&conn, &in_method("EWeaponSlot Slot = EWeaponSlot::|"));
+        assert_eq!(labels(&list), ["Primary", "Secondary", "Melee"]);
+        assert!(list.items.iter().all(|i| i.kind == 20));
+    }
+
+    #[test]
+    fn dereference_and_address_of_receivers() {
+        let conn = engine();
+        let list = complete(&conn, &in_method("AActor* Owner = GetOwner();\n    (*Owner).|"));
+        assert!(labels(&list).contains(&"GetActorLocation"));
+        let list = complete(&conn, &in_method("(*Mesh).|"));
+        assert!(labels(&list).contains(&"GetComponentLocation"));
+        let list = complete(&conn, &in_method("(*this).|"));
+        assert!(labels(&list).contains(&"Health"));
+        // (&Obj)-> は Obj と同じ型として扱う
+        let list = complete(&conn, &in_method("FTransform Transform;\n    (&Transform)->|"));
+        assert!(labels(&list).contains(&"GetLocation"));
+        let list = complete(&conn, &in_method("(&*this)->|"));
+        assert!(labels(&list).contains(&"Health"));
+    }
+
+    #[test]
+    fn callable_members_and_locals() {
+        let header = "class UTargeting : public UObject\n{\npublic:\n    TFunction<AActor*(int32)> Resolver;\n    TUniqueFunction<void()> OnDone;\n};\n";
+        let conn = engine_with(&[("Game/Targeting.h", header)]);
+        let list = complete(&conn, &in_method("UTargeting* Targeting = nullptr;\n    Targeting->Resolver.|"));
+        let call = list.items.iter().find(|i| i.label == "operator()").unwrap();
+        assert_eq!(call.detail.as_deref(), Some("AActor*(int32)"));
+        assert!(labels(&list).contains(&"IsSet"));
+        let list = complete(&conn, &in_method("UTargeting* Targeting = nullptr;\n    Targeting->OnDone.|"));
+        assert!(labels(&list).contains(&"Reset"));
+        // 呼び出した結果は戻り値型
+        let list = complete(&conn, &in_method("UTargeting* Targeting = nullptr;\n    Targeting->Resolver(0)->|"));
+        assert!(labels(&list).contains(&"GetActorLocation"));
+        // TFunctionRef のローカル変数には IsSet / Reset が無い
+        let list = complete(&conn, &in_method("TFunctionRef<bool(float)> Check = Predicate;\n    Check.|"));
+        assert_eq!(labels(&list), ["operator()", "CheckCallable"]);
+        assert_eq!(list.items[0].detail.as_deref(), Some("bool(float)"));
+    }
+
+    #[test]
+    fn partial_token_after_multibyte_punctuation() {
+        assert_eq!(partial_token_before("    // 体力「Get", 0, "    // 体力「Get".len()), "Get");
+        assert_eq!(partial_token_before("    Text = TEXT(\"回復、\")", 0, "    Text = TEXT(\"回復、".len()), "");
+        // コメント中で補完を要求してもパニックせずに絞り込みが走る
+        let conn = engine();
+        let config = CompletionConfig { fuzzy_min_prefix: Some(1), ..Default::default() };
+        let list = complete_with(&conn, &in_method("// 所有者、Get|"), &config);
+        assert!(list.items.iter().all(|i| i.label.to_lowercase().contains('g')));
+    }
+
+    #[test]
+    fn signature_help_tracks_the_active_parameter() {
+        let header = "class UAimComponent : public UActorComponent\n{\npublic:\n    void Aim(float Yaw, float Pitch, bool bSnap);\n    void Recenter();\n};\n";
+        let conn = engine_with(&[("Game/Aim.h", header)]);
+        let help = |body: &str| {
+            let (content, line, ch) = cursor(&in_method(&format!("UAimComponent* AimComp = nullptr;\n    {}", body)));
+            process_signature_help(&conn, &content, line, ch).unwrap()
+        };
+        let result = help("AimComp->Aim(|);");
+        assert_eq!(result["signatures"][0]["label"], "Aim(float Yaw, float Pitch, bool bSnap)");
+        assert_eq!(result["signatures"][0]["parameters"], json!([{ "label": "float Yaw" }, { "label": "float Pitch" }, { "label": "bool bSnap" }]));
+        assert_eq!(result["activeParameter"], 0);
+        assert_eq!(help("AimComp->Aim(GetOwner()->GetActorLocation().X, |);")["activeParameter"], 1);
+        // 最後のカンマより後ろ
+        assert_eq!(help("AimComp->Aim(1.f, 2.f, |);")["activeParameter"], 2);
+        // 引数の無い関数
+        let result = help("AimComp->Recenter(|);");
+        assert_eq!(result["signatures"][0]["parameters"], json!([]));
+        assert_eq!(result["activeParameter"], 0);
+        // 閉じ括弧の後ろは呼び出しの外
+        assert_eq!(help("AimComp->Recenter()|;"), Value::Null);
+    }
+
+    #[test]
+    fn top_level_commas_ignore_operators_and_literals() {
+        assert_eq!(count_top_level_commas("A->B, "), 1);
+        assert_eq!(count_top_level_commas("X > 0, Y < Z, "), 2);
+        assert_eq!(count_top_level_commas("',', TEXT(\"a, \\\"b\\\", c\"), "), 2);
+        assert_eq!(count_top_level_commas("'\\'', Foo(1, 2), {3, 4}, Arr[5], "), 4);
+        assert_eq!(count_top_level_commas(""), 0);
+    }
+
+    #[test]
+    fn workspace_symbols_rank_escape_and_share_the_limit() {
+        let header = concat!(
+            "class AGreatUnicorn\n{\n};\n\n",
+            "class AGun\n{\npublic:\n    int32 GunCount;\n};\n\n",
+            "class GunSmith\n{\n};\n\n",
+            "class UMy_Thing\n{\n};\n\n",
+            "class UMyXThing\n{\n};\n",
+        );
+        let conn = indexed(&[("/home/dev/My Game/Source/Weapon#1.h", header)]);
+        assert_eq!(process_workspace_symbols(&conn, "  ", None).unwrap(), json!([]));
+
+        let names = |result: &Value| -> Vec<String> {
+            result.as_array().unwrap().iter().map(|s| s["name"].as_str().unwrap().to_string()).collect()
+        };
+        // 前方一致 > 部分一致 > あいまい一致。クラスが上限を使い切ればメンバーは出ない
+        let result = process_workspace_symbols(&conn, "gun", Some(3)).unwrap();
+        assert_eq!(names(&result), ["GunSmith", "AGun", "AGreatUnicorn"]);
+        let result = process_workspace_symbols(&conn, "gun", Some(4)).unwrap();
+        assert_eq!(names(&result), ["GunSmith", "AGun", "AGreatUnicorn", "GunCount"]);
+        assert_eq!(result[3]["containerName"], "AGun");
+        assert_eq!(result[3]["kind"], 8);
+        assert_eq!(result[0]["location"]["uri"], "file:///home/dev/My%20Game/Source/Weapon%231.h");
+
+        // _ と % はワイルドカードではなく文字として扱う
+        assert_eq!(names(&process_workspace_symbols(&conn, "My_", None).unwrap()), ["UMy_Thing"]);
+        assert_eq!(process_workspace_symbols(&conn, "%", None).unwrap(), json!([]));
+    }
+
+    #[test]
+    fn location_uris_percent_encode_paths() {
+        assert_eq!(location_json("C:\\My Game\\Source\\A.h", 3)["uri"], "file:///C:/My%20Game/Source/A.h");
+        assert_eq!(location_json("/src/100%/\u{4f53}.h", 1)["uri"], "file:///src/100%25/%E4%BD%93.h");
+        assert_eq!(location_json("/src/A.h", 3)["range"]["start"], json!({ "line": 2, "character": 0 }));
+    }
+
+    #[test]
+    fn cached_patterns_match_the_requested_identifier() {
+        let constraints = "template<typename TOther, typename T> requires TIsDerivedFrom<TOther, UObject>::Value && TIsDerivedFrom<T, AActor>::Value";
+        assert_eq!(template_constraint_type(constraints, "T").as_deref(), Some("AActor"));
+        assert_eq!(template_constraint_type("std::is_base_of_v<APawn, TOther> && std::is_base_of_v<AActor, T>", "T").as_deref(), Some("AActor"));
+        assert_eq!(template_constraint_type(constraints, "TMissing"), None);
+
+        let content = "GetComponents<UStaticMeshComponent>(Meshes);\nGetComponents(ULightComponent::StaticClass(), Lights);\n";
+        assert_eq!(gathered_component_type("Lights", content, 1).as_deref(), Some("ULightComponent"));
+        assert_eq!(gathered_component_type("Meshes", content, 1).as_deref(), Some("UStaticMeshComponent"));
+        assert_eq!(gathered_component_type("Mesh", content, 1), None);
+
+        assert!(mentions_identifier("Comp->GetChild(Comp)", "Comp"));
+        assert!(!mentions_identifier("CompOwner->GetChild()", "Comp"));
+        assert!(!mentions_identifier("GetChild()", ""));
+    }
+
+    #[test]
+    fn tree_cache_hits_unchanged_content_and_misses_after_edits() {
+        let original = "class AHit\n{\n};\n";
+        let edited = "class AHit\n{\n    int32 X;\n};\n";
+        let mut cache = TreeCache::new();
+        assert!(cache.get(original).is_none());
+        cache.insert(original, parse_uncached(original).unwrap(), 4);
+        let hit = cache.get(original).unwrap();
+        assert_eq!(hit.root_node().end_byte(), original.len());
+        assert!(cache.get(edited).is_none());
+        cache.insert(edited, parse_uncached(edited).unwrap(), 4);
+        // ヒットした木は先頭に移り、同じ内容の行は増えない
+        assert_eq!(cache.get(original).unwrap().root_node().end_byte(), original.len());
+        let contents: Vec<&str> = cache.entries.iter().map(|(_, text, _)| text.as_str()).collect();
+        assert_eq!(contents, [original, edited]);
+        cache.insert("class AOther {};", parse_uncached("class AOther {};").unwrap(), 2);
+        assert_eq!(cache.entries.len(), 2);
+        assert!(cache.get(edited).is_none());
+
+        // tree_cache_size: 0 は共有のキャッシュを使わない
+        let bypassed = "class ABypassedTreeCache\n{\n};\n";
+        let config = CompletionConfig { tree_cache_size: Some(0), ..Default::default() };
+        let tree = with_config(&config, || parse_content(bypassed)).unwrap();
+        assert_eq!(tree.root_node().end_byte(), bypassed.len());
+        assert!(TREE_CACHE.lock().unwrap().entries.iter().all(|(_, text, _)| text != bypassed));
+    }
+
+    #[test]
+    fn include_scope_limits_known_types_to_included_headers() {
+        let scoped = "class AScopedActor : public AActor\n{\npublic:\n    void Scoped();\n};\n";
+        let unscoped = "class AUnscopedActor : public AActor\n{\npublic:\n    void Unscoped();\n};\n";
+        let conn = engine_with(&[("Source/Game/My_Actor.h", scoped), ("Source/Game/MyXActor.h", unscoped)]);
+        let known = |config: &CompletionConfig, name: &str| with_config(config, || is_known_type(&conn, name)).unwrap();
+
+        // インクルードの情報が無ければ全体から探す
+        let global = CompletionConfig { include_scope: true, ..Default::default() };
+        assert!(known(&global, "AScopedActor"));
+        assert!(known(&global, "AUnscopedActor"));
+
+        // 明示したインクルード。My_Actor.h の _ は MyXActor.h に一致しない
+        let explicit = CompletionConfig { include_scope: true, includes: Some(vec!["Game/My_Actor.h".to_string()]), ..Default::default() };
+        assert!(known(&explicit, "AScopedActor"));
+        assert!(!known(&explicit, "AUnscopedActor"));
+        // include_scope が無効なら includes は使わない
+        let disabled = CompletionConfig { include_scope: false, ..explicit.clone() };
+        assert!(known(&disabled, "AUnscopedActor"));
+
+        // バッファの #include から求める
+        let source = "#include \"Game/My_Actor.h\"\nvoid AMyActor::BeginPlay()\n{\n    AScopedActor->|\n}\n";
+        assert!(labels(&complete_with(&conn, source, &global)).contains(&"Scoped"));
+        let source = "#include \"Game/My_Actor.h\"\nvoid AMyActor::BeginPlay()\n{\n    AUnscopedActor->|\n}\n";
+        assert!(!labels(&complete_with(&conn, source, &global)).contains(&"Unscoped"));
+        assert!(labels(&complete(&conn, source)).contains(&"Unscoped"));
+    }
+
+    #[test]
+    fn hover_shows_members_locals_and_this() {
+        let conn = engine();
+        let hover = |body: &str| {
+            let (content, line, ch) = cursor(&in_method(body));
+            process_hover(&conn, &content, line, ch).unwrap()
+        };
+        let value = |result: &Value| result["contents"]["value"].as_str().unwrap().to_string();
+
+        let result = hover("GetRootComponent()->Acti|vate(true);");
+        assert_eq!(result["contents"]["kind"], "markdown");
+        assert_eq!(value(&result), "```cpp\npublic: void UActorComponent::Activate(bool bReset)\n```");
+        let text = value(&hover("GetRoot|Component()->Activate(true);"));
+        assert!(text.starts_with("```cpp\npublic: USceneComponent"), "{}", text);
+        assert!(text.contains("AActor::GetRootComponent()"), "{}", text);
+
+        // ローカル変数は推論した型
+        assert_eq!(value(&hover("AActor* Owner = GetOwner();\n    Own|er->Tick(0.f);")), "```cpp\nAActor Owner\n```");
+        // this は囲んでいるクラス
+        assert_eq!(value(&hover("th|is->Tick(0.f);")), "```cpp\nclass AMyActor\n```");
+        // 何も解決できなければ null
+        assert_eq!(hover("DoSomethingUnk|nown();"), Value::Null);
+    }
+
+    #[test]
+    fn definition_prefers_header_declarations() {
+        let header = "class AGun : public AActor\n{\npublic:\n    void Fire();\n};\n";
+        let source = "#include \"Gun.h\"\n\nvoid AGun::Fire()\n{\n}\n";
+        let conn = engine_with(&[("Game/Gun.cpp", source), ("Game/Gun.h", header)]);
+        let definition = |body: &str| {
+            let (content, line, ch) = cursor(&in_method(&format!("AGun* Gun = nullptr;\n    {}", body)));
+            process_definition(&conn, &content, line, ch).unwrap()
+        };
+        let location = definition("Gun->Fi|re();");
+        assert_eq!(location["uri"], "file:///Game/Gun.h");
+        assert_eq!(location["range"]["start"], json!({ "line": 3, "character": 0 }));
+
+        let (content, line, ch) = cursor(&in_method("AG|un* Gun = nullptr;"));
+        let location = process_definition(&conn, &content, line, ch).unwrap();
+        assert_eq!(location["uri"], "file:///Game/Gun.h");
+        assert_eq!(location["range"]["start"]["line"], 0);
+
+        assert_eq!(definition("Unkn|own();"), Value::Null);
+    }
+
+    #[test]
+    fn document_symbols_nest_members_and_skip_forward_declarations() {
+        let header = concat!(
+            "class UForward;\n\n",
+            "class AHolder : public AActor\n{\npublic:\n",
+            "    struct FSlot\n    {\n        int32 Index;\n    };\n",
+            "    void Fill(int32 Count);\n    int32 Total;\n};\n\n",
+            "enum class EMode : uint8\n{\n    Idle,\n    Busy\n};\n\n",
+            "void AHolder::Fill(int32 Count)\n{\n    int32 Local = Count;\n}\n",
+        );
+        let conn = indexed(&[]);
+        let result = process_document_symbols(&conn, header, Some("Game/Holder.h")).unwrap();
+        let outline = |symbols: &Value| -> Vec<(String, i64)> {
+            symbols.as_array().map_or(Vec::new(), |list| list.iter().map(|s| (s["name"].as_str().unwrap().to_string(), s["kind"].as_i64().unwrap())).collect())
+        };
+        let top = outline(&result);
+        assert_eq!(top, [("AHolder".to_string(), 5), ("EMode".to_string(), 10), ("AHolder::Fill".to_string(), 6)]);
+
+        let holder = &result[0];
+        assert_eq!(holder["selectionRange"]["start"], json!({ "line": 2, "character": 6 }));
+        assert_eq!(outline(&holder["children"]), [("FSlot".to_string(), 23), ("Fill".to_string(), 6), ("Total".to_string(), 8)]);
+        assert_eq!(outline(&holder["children"][0]["children"]), [("Index".to_string(), 8)]);
+        assert_eq!(holder["children"][1]["detail"], "(int32 Count)");
+        assert_eq!(holder["children"][2]["detail"], "int32");
+        assert_eq!(outline(&result[1]["children"]), [("Idle".to_string(), 22), ("Busy".to_string(), 22)]);
+        // 関数本体のローカル変数は出さない
+        assert!(result[2].get("children").is_none());
+        assert_eq!(result[2]["detail"], "(int32 Count)");
+    }
+
+    #[test]
+    fn lazy_resolve_defers_detail_until_completion_resolve() {
+        let enum_header = "UENUM()\nenum class EMode : uint8\n{\n    Idle UMETA(DisplayName = \"Idle State\"),\n    Busy = 4\n};\n";
+        let conn = engine_with(&[("Game/Mode.h", enum_header)]);
+        let lazy = CompletionConfig { lazy_resolve: true, ..Default::default() };
+        for body in ["GetRootComponent()->|", "EMode Mode = EMode::|"] {
+            let eager = complete(&conn, &in_method(body));
+            let deferred = complete_with(&conn, &in_method(body), &lazy);
+            assert_eq!(labels(&deferred), labels(&eager));
+            for (item, expected) in deferred.items.iter().zip(&eager.items) {
+                assert!(item.detail.is_none() && item.documentation.is_none(), "{}", item.label);
+                let data = item.data.as_ref().unwrap();
+                assert!(data.class_id.is_some());
+                assert_eq!(data.name.as_deref(), Some(item.label.as_str()));
+
+                let resolved: CompletionItem = serde_json::from_value(process_completion_resolve(&conn, json!(item)).unwrap()).unwrap();
+                assert_eq!(resolved.detail, expected.detail, "{}", item.label);
+                assert_eq!(resolved.documentation, expected.documentation.clone().filter(|d| !d.is_empty()), "{}", item.label);
+            }
+        }
+        let eager = complete(&conn, &in_method("EMode Mode = EMode::|"));
+        assert_eq!(eager.items[0].documentation.as_deref(), Some("Idle State"));
+
+        // data が無い (lazy でない) 候補はそのまま返す
+        let plain = json!({ "label": "Foo", "kind": 2, "insertText": "Foo" });
+        assert_eq!(process_completion_resolve(&conn, plain.clone()).unwrap(), plain);
+    }
+
+    #[test]
+    fn globals_are_offered_outside_member_context() {
+        let header = "#define GAME_MAX_PLAYERS 8\n#define GAME_CLAMP(X) (X)\nfloat GameClampHealth(float Value);\n";
+        let conn = engine_with(&[("Game/Util.h", header)]);
+        let list = complete(&conn, "void RunGame()\n{\n    Game|\n}\n");
+        let item = |label: &str| list.items.iter().find(|i| i.label == label).unwrap_or_else(|| panic!("missing {} in {:?}", label, labels(&list)));
+        assert_eq!((item("GAME_MAX_PLAYERS").kind, item("GAME_MAX_PLAYERS").detail.as_deref()), (21, Some("macro")));
+        assert_eq!((item("GAME_CLAMP").kind, item("GAME_CLAMP").detail.as_deref()), (3, Some("macro")));
+        assert_eq!((item("GameClampHealth").kind, item("GameClampHealth").detail.as_deref()), (3, Some("float")));
+        assert_eq!(item("GameClampHealth").documentation.as_deref(), Some("(float Value)"));
+        // 入力途中の名前が無ければ出さない
+        assert!(complete(&conn, "void RunGame()\n{\n    |\n}\n").items.is_empty());
+    }
+
+    #[test]
+    fn reflection_macro_specifiers_by_macro_and_across_lines() {
+        let conn = indexed(&[]);
+        let in_class = |body: &str| format!("UCLASS()\nclass AGadget : public AActor\n{{\n    GENERATED_BODY()\n    {}\n}};\n", body);
+        let list = complete(&conn, &in_class("UPROPERTY(|)\n    int32 Charges;"));
+        let property = labels(&list);
+        assert!(property.contains(&"EditAnywhere") && property.contains(&"BlueprintReadWrite"), "{:?}", property);
+        assert!(!property.contains(&"BlueprintPure"));
+        assert!(list.items.iter().all(|i| i.kind == 14 && i.detail.as_deref() == Some("UPROPERTY specifier")));
+
+        let list = complete(&conn, &in_class("UFUNCTION(|)\n    void Use();"));
+        let function = labels(&list);
+        assert!(function.contains(&"BlueprintPure") && function.contains(&"BlueprintCallable"), "{:?}", function);
+        assert!(!function.contains(&"EditAnywhere"));
+
+        // 複数行の引数。入力済みの指定子と入力途中の前方一致
+        let list = complete(&conn, &in_class("UPROPERTY(EditAnywhere,\n        BlueprintReadOnly,\n        Blue|)\n    int32 Charges;"));
+        let continued = labels(&list);
+        assert!(continued.contains(&"BlueprintReadWrite"), "{:?}", continued);
+        assert!(!continued.contains(&"BlueprintReadOnly") && !continued.contains(&"EditAnywhere"));
+        assert!(continued.iter().all(|l| l.starts_with("Blue")));
+
+        // Category = の値の位置や、SPECIFIER_LOOKBACK_LINES より前に開いたマクロでは出さない
+        let (content, line, ch) = cursor(&in_class("UPROPERTY(Category = |)"));
+        assert!(detect_specifier_context(&content, line as usize, ch as usize).is_none());
+        let far = format!("UPROPERTY(EditAnywhere,{}\n        |)", "\n".repeat(SPECIFIER_LOOKBACK_LINES + 1));
+        let (content, line, ch) = cursor(&in_class(&far));
+        assert!(detect_specifier_context(&content, line as usize, ch as usize).is_none());
+        let near = format!("UPROPERTY(EditAnywhere,{}\n        |)", "\n".repeat(SPECIFIER_LOOKBACK_LINES - 2));
+        let (content, line, ch) = cursor(&in_class(&near));
+        assert!(matches!(detect_specifier_context(&content, line as usize, ch as usize), Some(LiteralContext::Specifier { .. })));
+    }
+
+    #[test]
+    fn with_config_restores_the_previous_config() {
+        let outer = CompletionConfig { snippets: true, ..Default::default() };
+        let inner = CompletionConfig { strict: true, ..Default::default() };
+        with_config(&outer, || {
+            with_config(&inner, || assert!(active_config(|c| c.strict && !c.snippets)));
+            assert!(active_config(|c| c.snippets && !c.strict));
+
+            let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| with_config(&inner, || panic!("request failed"))));
+            assert!(panicked.is_err());
+            assert!(active_config(|c| c.snippets && !c.strict));
+        });
+        assert!(active_config(|c| !c.snippets && !c.strict));
+    }
+}
+
+#[cfg(all(test, feature = "msgpack"))]
+mod msgpack_tests {
+    use super::tests::{complete, indexed};
+    use super::*;
+
+    #[test]
+    fn msgpack_round_trips_completion_list() {
+        let conn = indexed(&[("Actor.h", "class AActor\n{\npublic:\n    FVector GetActorLocation() const;\n    int32 Health;\n};\n")]);
+        let source = "void AActor::Tick()\n{\n    this->|\n}\n";
+        let offset = source.find('|').unwrap();
+        let content = source.replacen('|', "", 1);
+        let character = (offset - source[..offset].rfind('\n').unwrap() - 1) as u32;
+        let bytes = process_completion_msgpack(&conn, &content, 2, character, None, &CompletionConfig::default()).unwrap();
+        let decoded = rmp_serde::from_slice::<CompletionList>(&bytes).unwrap();
+
+        // serde(skip) のフィールドはどちらの形式でも落ちるので、JSON を経由したものと比べる
+        let expected = complete(&conn, source);
+        let expected: CompletionList = serde_json::from_value(serde_json::to_value(&expected).unwrap()).unwrap();
+        assert!(!decoded.items.is_empty());
+        assert_eq!(decoded, expected);
+    }
+}
@@ -0,0 +1,292 @@
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+// 補完で導出したキャッシュ (typedef 解決結果, 継承閉包)。
+// DB の世代 ID (project_meta.db_generation) に紐づけて completion_cache テーブルに永続化し、
+// 世代が変わったもの (インデックス更新後) は破棄する。
+// 行の revision は保存のたびに上がるので、他のプロセスが保存 (reindex_file による個別の破棄など) したら読み直す。
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct CompletionCache {
+    pub generation: Option<String>,
+    // ペイロードの版 (CACHE_VERSION)。版の無い古いペイロードは 0 になる
+    #[serde(default)]
+    pub version: u32,
+    pub resolved_types: HashMap<String, String>,
+    pub ancestors: HashMap<String, Vec<String>>,
+    // 各エントリの導出に使ったクラスの定義ファイル (invalidate で個別に破棄するため)
+    #[serde(default)]
+    pub resolved_type_sources: HashMap<String, Vec<String>>,
+    #[serde(default)]
+    pub ancestor_sources: HashMap<String, Vec<String>>,
+    // 読み込んだ/保存した時点の completion_cache.revision
+    #[serde(skip)]
+    revision: i64,
+    #[serde(skip)]
+    dirty: bool,
+}
+
+const CACHE_NAME: &str = "completion";
+// ペイロードの形式や導出の仕方 (ancestors の並び順など) を変えたら上げる。版が異なるものは読み込まずに破棄する
+const CACHE_VERSION: u32 = 1;
+
+impl CompletionCache {
+    /// DB から読み込む。保存済みの世代が現在の世代と異なる場合、版が古い場合は空のキャッシュを返す
+    pub fn load(conn: &Connection) -> anyhow::Result<Self> {
+        let generation = crate::db::get_generation(conn)?;
+        let empty = CompletionCache { generation: generation.clone(), version: CACHE_VERSION, ..Default::default() };
+        // 古いスキーマの DB ではテーブルが無いので空のキャッシュで続行する
+        let mut stmt = match conn.prepare("SELECT generation, payload, revision FROM completion_cache WHERE name = ?") {
+            Ok(stmt) => stmt,
+            Err(_) => return Ok(empty),
+        };
+        let mut rows = stmt.query([CACHE_NAME])?;
+        if let Some(row) = rows.next()? {
+            let saved_generation: Option<String> = row.get(0)?;
+            let payload: String = row.get(1)?;
+            let revision: i64 = row.get(2)?;
+            if saved_generation.is_some() && saved_generation == generation {
+                if let Ok(cache) = serde_json::from_str::<CompletionCache>(&payload) {
+                    if cache.version == CACHE_VERSION { return Ok(CompletionCache { revision, ..cache }); }
+                }
+            }
+            // 破棄した場合も revision は合わせておき、次の保存まで読み直さない
+            return Ok(CompletionCache { revision, ..empty });
+        }
+        Ok(empty)
+    }
+
+    pub fn save(&mut self, conn: &Connection) -> anyhow::Result<()> {
+        conn.execute(
+            "INSERT INTO completion_cache (name, generation, payload, revision) VALUES (?1, ?2, ?3, 1)
+             ON CONFLICT(name) DO UPDATE SET generation = excluded.generation, payload = excluded.payload, revision = revision + 1",
+            rusqlite::params![CACHE_NAME, self.generation, serde_json::to_string(self)?],
+        )?;
+        self.revision = stored_revision(conn)?;
+        Ok(())
+    }
+
+    /// 世代が同じで、読み込んだ後に他のキャッシュ (別のプロセス) が保存していなければ true
+    pub fn is_current(&self, conn: &Connection) -> anyhow::Result<bool> {
+        Ok(self.generation == crate::db::get_generation(conn)? && self.revision == stored_revision(conn)?)
+    }
+
+    /// paths のいずれかで定義されたクラスから導出したエントリを破棄する。破棄した数を返す
+    pub fn invalidate(&mut self, paths: &[PathBuf]) -> usize {
+        let targets: Vec<String> = paths.iter().map(|p| normalize(&p.to_string_lossy())).collect();
+        let touched = |sources: &HashMap<String, Vec<String>>, key: &str| {
+            // 導出元が記録されていない (古いペイロードの) エントリは念のため破棄する
+            sources.get(key).map_or(true, |files| files.iter().any(|f| targets.contains(&normalize(f))))
+        };
+        let stale_types: Vec<String> = self.resolved_types.keys().filter(|k| touched(&self.resolved_type_sources, k)).cloned().collect();
+        let stale_ancestors: Vec<String> = self.ancestors.keys().filter(|k| touched(&self.ancestor_sources, k)).cloned().collect();
+        for key in &stale_types {
+            self.resolved_types.remove(key);
+            self.resolved_type_sources.remove(key);
+        }
+        for key in &stale_ancestors {
+            self.ancestors.remove(key);
+            self.ancestor_sources.remove(key);
+        }
+        let removed = stale_types.len() + stale_ancestors.len();
+        if removed > 0 { self.dirty = true; }
+        removed
+    }
+}
+
+// 古いスキーマの DB (テーブル/列が無い) や未保存なら 0
+fn stored_revision(conn: &Connection) -> anyhow::Result<i64> {
+    let mut stmt = match conn.prepare_cached("SELECT revision FROM completion_cache WHERE name = ?") {
+        Ok(stmt) => stmt,
+        Err(_) => return Ok(0),
+    };
+    let mut rows = stmt.query([CACHE_NAME])?;
+    Ok(match rows.next()? {
+        Some(row) => row.get(0)?,
+        None => 0,
+    })
+}
+
+fn normalize(path: &str) -> String {
+    path.replace('\\', "/")
+}
+
+thread_local! {
+    static ACTIVE: RefCell<Option<CompletionCache>> = const { RefCell::new(None) };
+}
+
+// 補完リクエストの開始時に呼ぶ。世代か revision が変わっていれば DB から読み直す
+pub(crate) fn sync(conn: &Connection) -> anyhow::Result<()> {
+    let current = ACTIVE.with(|c| match c.borrow().as_ref() {
+        Some(cache) => cache.is_current(conn),
+        None => Ok(false),
+    })?;
+    if !current {
+        let cache = CompletionCache::load(conn)?;
+        ACTIVE.with(|c| *c.borrow_mut() = Some(cache));
+    }
+    Ok(())
+}
+
+/// ファイル監視などで変更を検知したときに呼ぶ。変更されたファイルに依存するキャッシュだけを破棄して保存する
+/// (インデックスの更新で世代が変われば全体が破棄されるので、それまでの間の古い結果を防ぐ)
+pub fn invalidate(conn: &Connection, paths: &[PathBuf]) -> anyhow::Result<usize> {
+    sync(conn)?;
+    let removed = ACTIVE.with(|c| c.borrow_mut().as_mut().map_or(0, |cache| cache.invalidate(paths)));
+    persist(conn);
+    Ok(removed)
+}
+
+// 補完リクエストの終了時に呼ぶ。新しく導出した値があれば永続化する
+pub(crate) fn persist(conn: &Connection) {
+    ACTIVE.with(|c| {
+        if let Some(cache) = c.borrow_mut().as_mut() {
+            if !cache.dirty { return; }
+            match cache.save(conn) {
+                Ok(()) => cache.dirty = false,
+                Err(e) => tracing::warn!("Failed to persist completion cache: {}", e),
+            }
+        }
+    });
+}
+
+pub(crate) fn get_resolved_type(name: &str) -> Option<String> {
+    ACTIVE.with(|c| c.borrow().as_ref().and_then(|cache| cache.resolved_types.get(name).cloned()))
+}
+
+pub(crate) fn put_resolved_type(name: &str, resolved: &str, sources: Vec<String>) {
+    ACTIVE.with(|c| {
+        if let Some(cache) = c.borrow_mut().as_mut() {
+            cache.resolved_types.insert(name.to_string(), resolved.to_string());
+            cache.resolved_type_sources.insert(name.to_string(), sources);
+            cache.dirty = true;
+        }
+    });
+}
+
+pub(crate) fn get_ancestors(name: &str) -> Option<Vec<String>> {
+    ACTIVE.with(|c| c.borrow().as_ref().and_then(|cache| cache.ancestors.get(name).cloned()))
+}
+
+pub(crate) fn put_ancestors(name: &str, ancestors: &[String], sources: Vec<String>) {
+    ACTIVE.with(|c| {
+        if let Some(cache) = c.borrow_mut().as_mut() {
+            cache.ancestors.insert(name.to_string(), ancestors.to_vec());
+            cache.ancestor_sources.insert(name.to_string(), sources);
+            cache.dirty = true;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn connection() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        crate::db::init_db(&conn).unwrap();
+        crate::db::bump_generation(&conn).unwrap();
+        conn
+    }
+
+    fn sample(conn: &Connection) -> CompletionCache {
+        let mut cache = CompletionCache::load(conn).unwrap();
+        cache.resolved_types.insert("FMyAlias".to_string(), "FVector".to_string());
+        cache.resolved_type_sources.insert("FMyAlias".to_string(), vec!["Game/Types.h".to_string()]);
+        cache.ancestors.insert("AMyActor".to_string(), vec!["AActor".to_string(), "UObject".to_string()]);
+        cache.ancestor_sources.insert("AMyActor".to_string(), vec!["Game/MyActor.h".to_string(), "Engine/Actor.h".to_string()]);
+        cache
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let conn = connection();
+        let mut cache = sample(&conn);
+        cache.save(&conn).unwrap();
+
+        let loaded = CompletionCache::load(&conn).unwrap();
+        assert_eq!(loaded.generation, cache.generation);
+        assert_eq!(loaded.resolved_types, cache.resolved_types);
+        assert_eq!(loaded.resolved_type_sources, cache.resolved_type_sources);
+        assert_eq!(loaded.ancestors, cache.ancestors);
+        assert_eq!(loaded.ancestor_sources, cache.ancestor_sources);
+        assert!(loaded.is_current(&conn).unwrap());
+    }
+
+    #[test]
+    fn bump_generation_discards_saved_cache() {
+        let conn = connection();
+        let mut cache = sample(&conn);
+        cache.save(&conn).unwrap();
+
+        let generation = crate::db::bump_generation(&conn).unwrap();
+        assert!(!cache.is_current(&conn).unwrap());
+        let loaded = CompletionCache::load(&conn).unwrap();
+        assert_eq!(loaded.generation, Some(generation));
+        assert!(loaded.resolved_types.is_empty());
+        assert!(loaded.ancestors.is_empty());
+    }
+
+    #[test]
+    fn payload_from_older_version_is_discarded() {
+        let conn = connection();
+        let mut cache = CompletionCache { version: CACHE_VERSION - 1, ..sample(&conn) };
+        cache.save(&conn).unwrap();
+
+        let loaded = CompletionCache::load(&conn).unwrap();
+        assert_eq!(loaded.version, CACHE_VERSION);
+        assert!(loaded.ancestors.is_empty());
+    }
+
+    #[test]
+    fn invalidate_drops_entries_from_changed_files() {
+        let conn = connection();
+        let mut cache = sample(&conn);
+        cache.resolved_types.insert("FOther".to_string(), "FRotator".to_string());
+        cache.resolved_type_sources.insert("FOther".to_string(), vec!["Game\\Other.h".to_string()]);
+
+        assert_eq!(cache.invalidate(&[PathBuf::from("Game/Unrelated.h")]), 0);
+        assert!(!cache.dirty);
+        // 区切り文字の違いは同じファイルとして扱う
+        assert_eq!(cache.invalidate(&[PathBuf::from("Game/Other.h"), PathBuf::from("Engine/Actor.h")]), 2);
+        assert!(cache.dirty);
+        assert!(!cache.resolved_types.contains_key("FOther"));
+        assert!(!cache.ancestors.contains_key("AMyActor"));
+        assert!(!cache.ancestor_sources.contains_key("AMyActor"));
+        assert_eq!(cache.resolved_types.get("FMyAlias").map(String::as_str), Some("FVector"));
+    }
+
+    #[test]
+    fn invalidate_drops_entries_without_recorded_sources() {
+        let conn = connection();
+        let mut cache = sample(&conn);
+        cache.ancestors.insert("ALegacy".to_string(), vec!["AActor".to_string()]);
+
+        assert_eq!(cache.invalidate(&[PathBuf::from("Game/Unrelated.h")]), 1);
+        assert!(!cache.ancestors.contains_key("ALegacy"));
+        assert!(cache.ancestors.contains_key("AMyActor"));
+    }
+
+    #[test]
+    fn save_from_another_cache_makes_loaded_cache_stale() {
+        // unl-server と unl-lsp のように、同じ DB から読み込んだ 2 つのキャッシュ
+        let conn = connection();
+        let mut first = sample(&conn);
+        first.save(&conn).unwrap();
+        let mut second = CompletionCache::load(&conn).unwrap();
+        assert!(first.is_current(&conn).unwrap());
+        assert!(second.is_current(&conn).unwrap());
+
+        assert_eq!(second.invalidate(&[PathBuf::from("Engine/Actor.h")]), 1);
+        second.save(&conn).unwrap();
+        assert!(second.is_current(&conn).unwrap());
+        assert!(!first.is_current(&conn).unwrap());
+
+        let reloaded = CompletionCache::load(&conn).unwrap();
+        assert!(reloaded.is_current(&conn).unwrap());
+        assert!(!reloaded.ancestors.contains_key("AMyActor"));
+        assert!(reloaded.resolved_types.contains_key("FMyAlias"));
+    }
+}
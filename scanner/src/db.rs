@@ -1,6 +1,7 @@
 use std::path::Path;
 use std::sync::Arc;
 use rusqlite::{params, Connection};
+use crate::scanner;
 use crate::types::{ParseResult, ProgressReporter};
 
 pub fn init_db(conn: &Connection) -> rusqlite::Result<()> {
@@ -78,12 +79,14 @@ pub fn init_db(conn: &Connection) -> rusqlite::Result<()> {
             detail TEXT,
             return_type TEXT,
             is_static INTEGER,
+            category TEXT,
             FOREIGN KEY(class_id) REFERENCES classes(id) ON DELETE CASCADE
         )",
         [],
     )?;
     // Migrations
     let _ = conn.execute("ALTER TABLE members ADD COLUMN line_number INTEGER", []);
+    let _ = conn.execute("ALTER TABLE members ADD COLUMN category TEXT", []);
     
     conn.execute("CREATE INDEX IF NOT EXISTS idx_members_name ON members(name)", [])?;
     conn.execute("CREATE INDEX IF NOT EXISTS idx_members_class_id ON members(class_id)", [])?;
@@ -95,10 +98,18 @@ pub fn init_db(conn: &Connection) -> rusqlite::Result<()> {
             id INTEGER PRIMARY KEY AUTOINCREMENT,
             enum_id INTEGER NOT NULL,
             name TEXT NOT NULL,
+            value TEXT,
+            documentation TEXT,
+            display_name TEXT,
+            flags TEXT,
             FOREIGN KEY(enum_id) REFERENCES classes(id) ON DELETE CASCADE
         )",
         [],
     )?;
+    let _ = conn.execute("ALTER TABLE enum_values ADD COLUMN value TEXT", []);
+    let _ = conn.execute("ALTER TABLE enum_values ADD COLUMN documentation TEXT", []);
+    let _ = conn.execute("ALTER TABLE enum_values ADD COLUMN display_name TEXT", []);
+    let _ = conn.execute("ALTER TABLE enum_values ADD COLUMN flags TEXT", []);
     conn.execute("CREATE INDEX IF NOT EXISTS idx_enum_values_id ON enum_values(enum_id)", [])?;
     conn.execute("CREATE UNIQUE INDEX IF NOT EXISTS idx_enum_values_unique ON enum_values(enum_id, name)", [])?;
 
@@ -143,9 +154,102 @@ pub fn init_db(conn: &Connection) -> rusqlite::Result<()> {
     conn.execute("CREATE INDEX IF NOT EXISTS idx_components_type ON components(type)", [])?;
     conn.execute("CREATE INDEX IF NOT EXISTS idx_components_owner ON components(owner_name)", [])?;
 
+    // 9. Log Categories
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS log_categories (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            file_id INTEGER,
+            line_number INTEGER,
+            FOREIGN KEY(file_id) REFERENCES files(id) ON DELETE CASCADE
+        )",
+        [],
+    )?;
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_log_categories_name ON log_categories(name)", [])?;
+    conn.execute("CREATE UNIQUE INDEX IF NOT EXISTS idx_log_categories_unique ON log_categories(name, file_id)", [])?;
+
+    // 10. Console Variables / Commands
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS cvars (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            kind TEXT,
+            file_id INTEGER,
+            line_number INTEGER,
+            FOREIGN KEY(file_id) REFERENCES files(id) ON DELETE CASCADE
+        )",
+        [],
+    )?;
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_cvars_name ON cvars(name)", [])?;
+    conn.execute("CREATE UNIQUE INDEX IF NOT EXISTS idx_cvars_unique ON cvars(name, file_id)", [])?;
+
+    // 11. Localization Texts (NSLOCTEXT / LOCTEXT)
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS loc_texts (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            namespace TEXT NOT NULL,
+            key TEXT NOT NULL,
+            file_id INTEGER,
+            line_number INTEGER,
+            FOREIGN KEY(file_id) REFERENCES files(id) ON DELETE CASCADE
+        )",
+        [],
+    )?;
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_loc_texts_namespace ON loc_texts(namespace)", [])?;
+    conn.execute("CREATE UNIQUE INDEX IF NOT EXISTS idx_loc_texts_unique ON loc_texts(namespace, key, file_id)", [])?;
+
+    // 12. Global Functions / Macros (クラス外の関数宣言と #define)
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS globals (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            kind TEXT NOT NULL,
+            return_type TEXT,
+            detail TEXT,
+            file_id INTEGER,
+            line_number INTEGER,
+            FOREIGN KEY(file_id) REFERENCES files(id) ON DELETE CASCADE
+        )",
+        [],
+    )?;
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_globals_name ON globals(name)", [])?;
+    conn.execute("CREATE UNIQUE INDEX IF NOT EXISTS idx_globals_unique ON globals(name, kind, file_id)", [])?;
+
+    // 13. Completion Cache (db_generation に紐づく導出キャッシュ。revision は保存のたびに上がる)
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS completion_cache (
+            name TEXT PRIMARY KEY,
+            generation TEXT,
+            payload TEXT,
+            revision INTEGER NOT NULL DEFAULT 0
+        )",
+        [],
+    )?;
+    let _ = conn.execute("ALTER TABLE completion_cache ADD COLUMN revision INTEGER NOT NULL DEFAULT 0", []);
+
     Ok(())
 }
 
+// インデックス内容が変わるたびに更新し、導出キャッシュの無効化に使う
+pub fn bump_generation(conn: &Connection) -> rusqlite::Result<String> {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    let generation = format!("{:x}-{:x}", nanos, std::process::id());
+    conn.execute("INSERT OR REPLACE INTO project_meta (key, value) VALUES ('db_generation', ?)", [&generation])?;
+    Ok(generation)
+}
+
+pub fn get_generation(conn: &Connection) -> rusqlite::Result<Option<String>> {
+    let mut stmt = conn.prepare("SELECT value FROM project_meta WHERE key = 'db_generation'")?;
+    let mut rows = stmt.query([])?;
+    match rows.next()? {
+        Some(row) => row.get(0),
+        None => Ok(None),
+    }
+}
+
 pub fn save_to_db(conn: &mut Connection, results: &[ParseResult], reporter: Arc<dyn ProgressReporter>) -> anyhow::Result<()> {
     conn.busy_timeout(std::time::Duration::from_millis(30000))?;
     let _ = conn.pragma_update(None, "journal_mode", "WAL");
@@ -166,77 +270,11 @@ pub fn save_to_db(conn: &mut Connection, results: &[ParseResult], reporter: Arc<
         let batch = &results[current_idx..end_idx];
 
         let tx = conn.transaction()?;
-        {
-            let mut stmt_file = tx.prepare("INSERT OR REPLACE INTO files (path, filename, extension, mtime, file_hash, module_id, is_header) VALUES (?, ?, ?, ?, ?, ?, ?)")?;
-            let mut stmt_class = tx.prepare("INSERT OR IGNORE INTO classes (name, namespace, base_class, file_id, line_number, symbol_type, end_line_number) VALUES (?, ?, ?, ?, ?, ?, ?)")?;
-            let mut stmt_class_id = tx.prepare("SELECT id FROM classes WHERE name = ? AND file_id = ? LIMIT 1")?;
-            let mut stmt_inheritance = tx.prepare("INSERT OR IGNORE INTO inheritance (child_id, parent_name) VALUES (?, ?)")?;
-            let mut stmt_enum = tx.prepare("INSERT OR IGNORE INTO enum_values (enum_id, name) VALUES (?, ?)")?;
-            let mut stmt_member = tx.prepare("INSERT OR IGNORE INTO members (class_id, name, type, flags, access, detail, return_type, is_static, line_number) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)")?;
-
-            for (i, result) in batch.iter().enumerate() {
-                let global_i = current_idx + i;
-                if global_i % 200 == 0 {
-                    reporter.report("db_sync", global_i, total, &format!("Saving results ({}/{})", global_i, total));
-                }
-                
-                if result.status != "parsed" { continue; }
-                let data = match &result.data {
-                    Some(d) => d,
-                    None => continue,
-                };
-
-                let path_obj = Path::new(&result.path);
-                let filename = path_obj.file_name().and_then(|s| s.to_str()).unwrap_or("unknown");
-                let extension = path_obj.extension().and_then(|s| s.to_str()).unwrap_or("").to_lowercase();
-                
-                let safe_module_id = if let Some(id) = result.module_id {
-                    if id <= 0 { None } else { Some(id) }
-                } else {
-                    None
-                };
-
-                let file_res = stmt_file.execute(params![
-                    result.path, filename, extension, result.mtime as i64, data.new_hash, safe_module_id,
-                    if extension == "h" || extension == "hpp" { 1 } else { 0 }
-                ]);
-
-                if file_res.is_ok() {
-                    let file_id: i64 = tx.last_insert_rowid();
-
-                    for cls in &data.classes {
-                        let _ = stmt_class.execute(params![
-                            cls.class_name, cls.namespace, cls.base_classes.first(), file_id, cls.line as i64, cls.symbol_type, cls.end_line as i64
-                        ]);
-                        
-                        let class_id_res: rusqlite::Result<i64> = stmt_class_id.query_row(
-                            params![cls.class_name, file_id],
-                            |row| row.get(0),
-                        );
-
-                        if let Ok(class_id) = class_id_res {
-                            for parent in &cls.base_classes {
-                                let _ = stmt_inheritance.execute(params![class_id, parent]);
-                            }
-
-                            for mem in &cls.members {
-                                if mem.mem_type == "enum_item" {
-                                    let _ = stmt_enum.execute(params![class_id, mem.name]);
-                                } else {
-                                    let is_static = if mem.flags.contains("static") { 1 } else { 0 };
-                                    let _ = stmt_member.execute(params![
-                                        class_id, mem.name, mem.mem_type, mem.flags, mem.access, mem.detail, mem.return_type, is_static, mem.line as i64
-                                    ]);
-                                }
-                            }
-                        }
-                    }
-                }
-            }
-        }
+        insert_results(&tx, batch, current_idx, total, reporter.as_ref())?;
         tx.commit()?;
         current_idx = end_idx;
     }
+    bump_generation(conn)?;
 
     // Finalize: Integrate WAL faster
     reporter.report("finalizing", 50, 100, "Finalizing database (Integrating WAL)...");
@@ -251,6 +289,137 @@ pub fn save_to_db(conn: &mut Connection, results: &[ParseResult], reporter: Arc<
     Ok(())
 }
 
+// ParseResult を files / classes / members などに書き込む。同じパスの files 行は置き換えられ、古い行は CASCADE で消える
+fn insert_results(tx: &rusqlite::Transaction, batch: &[ParseResult], offset: usize, total: usize, reporter: &dyn ProgressReporter) -> anyhow::Result<()> {
+    let mut stmt_file = tx.prepare("INSERT OR REPLACE INTO files (path, filename, extension, mtime, file_hash, module_id, is_header) VALUES (?, ?, ?, ?, ?, ?, ?)")?;
+    let mut stmt_class = tx.prepare("INSERT OR IGNORE INTO classes (name, namespace, base_class, file_id, line_number, symbol_type, end_line_number) VALUES (?, ?, ?, ?, ?, ?, ?)")?;
+    let mut stmt_class_id = tx.prepare("SELECT id FROM classes WHERE name = ? AND file_id = ? LIMIT 1")?;
+    let mut stmt_inheritance = tx.prepare("INSERT OR IGNORE INTO inheritance (child_id, parent_name) VALUES (?, ?)")?;
+    let mut stmt_enum = tx.prepare("INSERT OR IGNORE INTO enum_values (enum_id, name, value, documentation, display_name, flags) VALUES (?, ?, ?, ?, ?, ?)")?;
+    let mut stmt_member = tx.prepare("INSERT OR IGNORE INTO members (class_id, name, type, flags, access, detail, return_type, is_static, line_number, category) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")?;
+    let mut stmt_log_category = tx.prepare("INSERT OR IGNORE INTO log_categories (name, file_id, line_number) VALUES (?, ?, ?)")?;
+    let mut stmt_cvar = tx.prepare("INSERT OR IGNORE INTO cvars (name, kind, file_id, line_number) VALUES (?, ?, ?, ?)")?;
+    let mut stmt_global = tx.prepare("INSERT OR IGNORE INTO globals (name, kind, return_type, detail, file_id, line_number) VALUES (?, ?, ?, ?, ?, ?)")?;
+    let mut stmt_loc_text = tx.prepare("INSERT OR IGNORE INTO loc_texts (namespace, key, file_id, line_number) VALUES (?, ?, ?, ?)")?;
+
+    for (i, result) in batch.iter().enumerate() {
+        let global_i = offset + i;
+        if global_i % 200 == 0 {
+            reporter.report("db_sync", global_i, total, &format!("Saving results ({}/{})", global_i, total));
+        }
+        
+        if result.status != "parsed" { continue; }
+        let data = match &result.data {
+            Some(d) => d,
+            None => continue,
+        };
+
+        let path_obj = Path::new(&result.path);
+        let filename = path_obj.file_name().and_then(|s| s.to_str()).unwrap_or("unknown");
+        let extension = path_obj.extension().and_then(|s| s.to_str()).unwrap_or("").to_lowercase();
+        
+        let safe_module_id = if let Some(id) = result.module_id {
+            if id <= 0 { None } else { Some(id) }
+        } else {
+            None
+        };
+
+        let file_res = stmt_file.execute(params![
+            result.path, filename, extension, result.mtime as i64, data.new_hash, safe_module_id,
+            if extension == "h" || extension == "hpp" { 1 } else { 0 }
+        ]);
+
+        if file_res.is_ok() {
+            let file_id: i64 = tx.last_insert_rowid();
+
+            for cat in &data.log_categories {
+                let _ = stmt_log_category.execute(params![cat.name, file_id, cat.line as i64]);
+            }
+            for cvar in &data.cvars {
+                let _ = stmt_cvar.execute(params![cvar.name, cvar.kind, file_id, cvar.line as i64]);
+            }
+            for global in &data.globals {
+                let _ = stmt_global.execute(params![global.name, global.mem_type, global.return_type, global.detail, file_id, global.line as i64]);
+            }
+            for loc in &data.loc_texts {
+                let _ = stmt_loc_text.execute(params![loc.namespace, loc.key, file_id, loc.line as i64]);
+            }
+
+            for cls in &data.classes {
+                let _ = stmt_class.execute(params![
+                    cls.class_name, cls.namespace, cls.base_classes.first(), file_id, cls.line as i64, cls.symbol_type, cls.end_line as i64
+                ]);
+                
+                let class_id_res: rusqlite::Result<i64> = stmt_class_id.query_row(
+                    params![cls.class_name, file_id],
+                    |row| row.get(0),
+                );
+
+                if let Ok(class_id) = class_id_res {
+                    for parent in &cls.base_classes {
+                        let _ = stmt_inheritance.execute(params![class_id, parent]);
+                    }
+
+                    for mem in &cls.members {
+                        if mem.mem_type == "enum_item" {
+                            let _ = stmt_enum.execute(params![class_id, mem.name, mem.detail, mem.documentation, mem.display_name, mem.flags]);
+                        } else {
+                            let is_static = if mem.flags.contains("static") { 1 } else { 0 };
+                            let _ = stmt_member.execute(params![
+                                class_id, mem.name, mem.mem_type, mem.flags, mem.access, mem.detail, mem.return_type, is_static, mem.line as i64, mem.category
+                            ]);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 1 ファイルだけを解析し直し、そのファイル由来の classes / members / inheritance / enum_values などを置き換える
+/// 保存時にフルスキャンせず DB を最新に保つ用途。ファイルから消えたクラスの行も残らない。保存したクラス数を返す
+pub fn reindex_file(conn: &Connection, file_path: &str, content: &str) -> anyhow::Result<usize> {
+    // 保存のたびにクエリをコンパイルし直さない
+    static QUERY: std::sync::OnceLock<tree_sitter::Query> = std::sync::OnceLock::new();
+    let language: tree_sitter::Language = tree_sitter_unreal_cpp::LANGUAGE.into();
+    let query = QUERY.get_or_init(|| tree_sitter::Query::new(&language, scanner::QUERY_STR).expect("Failed to parse query"));
+    let data = scanner::parse_data(content, file_path, scanner::content_hash(content), &language, query)?;
+    let class_count = data.classes.len();
+    let mtime = std::fs::metadata(file_path).and_then(|m| m.modified()).ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_secs());
+    let result = ParseResult {
+        path: file_path.to_string(), status: "parsed".to_string(), mtime,
+        data: Some(data),
+        module_id: get_module_id_for_path(conn, file_path)?,
+    };
+
+    conn.busy_timeout(std::time::Duration::from_millis(30000))?;
+    let tx = conn.unchecked_transaction()?;
+    // 呼び出し側の接続の外部キー設定には頼らず (変えもせず)、このファイルに属する行をすべて先に消す
+    let owned_classes = "SELECT c.id FROM classes c JOIN files f ON c.file_id = f.id WHERE f.path = ?";
+    tx.execute(&format!("DELETE FROM members WHERE class_id IN ({})", owned_classes), [file_path])?;
+    tx.execute(&format!("DELETE FROM inheritance WHERE child_id IN ({})", owned_classes), [file_path])?;
+    tx.execute(&format!("DELETE FROM enum_values WHERE enum_id IN ({})", owned_classes), [file_path])?;
+    for table in ["classes", "log_categories", "cvars", "loc_texts", "globals"] {
+        tx.execute(&format!("DELETE FROM {} WHERE file_id IN (SELECT id FROM files WHERE path = ?)", table), [file_path])?;
+    }
+    insert_results(&tx, std::slice::from_ref(&result), 0, 1, &NullReporter)?;
+    tx.commit()?;
+
+    // 世代は変えず、このファイルに依存する補完キャッシュだけを破棄する
+    // (保存し直した行の revision が上がるので、同じ DB を開いている他のプロセスも次の補完で読み直す)
+    crate::completion_cache::invalidate(conn, &[std::path::PathBuf::from(file_path)])?;
+    Ok(class_count)
+}
+
+struct NullReporter;
+
+impl ProgressReporter for NullReporter {
+    fn report(&self, _stage: &str, _current: usize, _total: usize, _message: &str) {}
+}
+
 pub fn get_module_id_for_path(conn: &Connection, file_path: &str) -> anyhow::Result<Option<i64>> {
     let mut stmt = conn.prepare(
         "SELECT id, root_path FROM modules ORDER BY length(root_path) DESC"
@@ -270,4 +439,80 @@ pub fn get_module_id_for_path(conn: &Connection, file_path: &str) -> anyhow::Res
     }
     
     Ok(best_id)
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::completion_cache::CompletionCache;
+
+    fn class_names(conn: &Connection) -> Vec<String> {
+        let mut stmt = conn.prepare("SELECT name FROM classes ORDER BY name").unwrap();
+        stmt.query_map([], |row| row.get(0)).unwrap().collect::<Result<_, _>>().unwrap()
+    }
+
+    fn count(conn: &Connection, sql: &str) -> i64 {
+        conn.query_row(sql, [], |row| row.get(0)).unwrap()
+    }
+
+    #[test]
+    fn reindex_file_drops_removed_class() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_db(&conn).unwrap();
+        bump_generation(&conn).unwrap();
+        let before = "class AKeep\n{\npublic:\n    void Stay();\n};\n\nclass ARemoved : public AKeep\n{\npublic:\n    void Leave();\n};\n";
+        assert_eq!(reindex_file(&conn, "Game/Actors.h", before).unwrap(), 2);
+        reindex_file(&conn, "Game/Other.h", "class AOther\n{\npublic:\n    void Wait();\n};\n").unwrap();
+        assert_eq!(class_names(&conn), ["AKeep", "AOther", "ARemoved"]);
+
+        // 同じ DB を開いている別のプロセスが保存したキャッシュ
+        let mut other = CompletionCache::load(&conn).unwrap();
+        other.ancestors.insert("ARemoved".to_string(), vec!["ARemoved".to_string(), "AKeep".to_string()]);
+        other.ancestor_sources.insert("ARemoved".to_string(), vec!["Game/Actors.h".to_string()]);
+        other.save(&conn).unwrap();
+        assert!(other.is_current(&conn).unwrap());
+
+        let after = "class AKeep\n{\npublic:\n    void Stay();\n};\n";
+        assert_eq!(reindex_file(&conn, "Game/Actors.h", after).unwrap(), 1);
+        assert_eq!(class_names(&conn), ["AKeep", "AOther"]);
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM members WHERE name = 'Leave'"), 0);
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM members WHERE name IN ('Stay', 'Wait')"), 2);
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM inheritance"), 0);
+
+        // 世代は同じままでも、他のプロセスのキャッシュは古いと分かり、読み直すと破棄済みになっている
+        assert!(!other.is_current(&conn).unwrap());
+        assert!(!CompletionCache::load(&conn).unwrap().ancestors.contains_key("ARemoved"));
+    }
+
+    #[test]
+    fn reindex_file_replaces_file_scoped_rows_without_foreign_keys() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_db(&conn).unwrap();
+        conn.execute("PRAGMA foreign_keys = OFF", []).unwrap();
+        let before = concat!(
+            "DEFINE_LOG_CATEGORY_STATIC(LogOld, Log, All);\n",
+            "static TAutoConsoleVariable<int32> CVarOld(TEXT(\"game.Old\"), 0, TEXT(\"\"));\n",
+            "FText Old = NSLOCTEXT(\"Game\", \"OldKey\", \"Old\");\n",
+            "#define OLD_MACRO 1\n",
+        );
+        reindex_file(&conn, "Game/Settings.cpp", before).unwrap();
+        let after = concat!(
+            "DEFINE_LOG_CATEGORY_STATIC(LogNew, Log, All);\n",
+            "static TAutoConsoleVariable<int32> CVarNew(TEXT(\"game.New\"), 0, TEXT(\"\"));\n",
+            "FText New = NSLOCTEXT(\"Game\", \"NewKey\", \"New\");\n",
+            "#define NEW_MACRO 1\n",
+        );
+        reindex_file(&conn, "Game/Settings.cpp", after).unwrap();
+
+        let names = |sql: &str| -> Vec<String> {
+            let mut stmt = conn.prepare(sql).unwrap();
+            stmt.query_map([], |row| row.get(0)).unwrap().collect::<Result<_, _>>().unwrap()
+        };
+        assert_eq!(names("SELECT name FROM log_categories"), ["LogNew"]);
+        assert_eq!(names("SELECT name FROM cvars"), ["game.New"]);
+        assert_eq!(names("SELECT key FROM loc_texts"), ["NewKey"]);
+        assert_eq!(names("SELECT name FROM globals WHERE kind = 'macro'"), ["NEW_MACRO"]);
+        // 呼び出し側の設定はそのまま
+        assert_eq!(count(&conn, "PRAGMA foreign_keys"), 0);
+    }
+}
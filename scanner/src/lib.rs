@@ -4,3 +4,4 @@ pub mod db;
 pub mod refresh;
 pub mod query;
 pub mod completion;
+pub mod completion_cache;
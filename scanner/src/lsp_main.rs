@@ -0,0 +1,330 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use rusqlite::Connection;
+use serde_json::{json, Value};
+use tracing::info;
+use tracing_subscriber::filter::LevelFilter;
+use unl_core::types::CompletionConfig;
+use unl_core::{completion, db};
+
+// stdio で LSP を話すエントリーポイント。DB は initialize で一度だけ開き、開いているバッファの内容はメモリに持つ
+//   initializationOptions: { "db_path": "...", "completion": { CompletionConfig } }
+//   db_path が無ければ環境変数 UNL_DB_PATH を使う
+
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INTERNAL_ERROR: i64 = -32603;
+const SERVER_NOT_INITIALIZED: i64 = -32002;
+
+const WINDOWS: bool = cfg!(target_os = "windows");
+
+struct LspState {
+    conn: Option<Connection>,
+    documents: HashMap<String, String>, // uri -> 現在のバッファ内容
+    config: CompletionConfig,
+    utf8_positions: bool, // initialize で utf-8 を取り決めたら position.character はそのままバイト列
+    shutdown: bool,
+}
+
+fn main() -> anyhow::Result<()> {
+    // stdout はプロトコルに使うのでログは stderr に出す (UNL_LOG_LEVEL で上限を変えられる)
+    let max_level = std::env::var("UNL_LOG_LEVEL").ok()
+        .and_then(|s| s.parse::<LevelFilter>().ok())
+        .unwrap_or(LevelFilter::WARN);
+    tracing_subscriber::fmt().with_writer(io::stderr).with_max_level(max_level).init();
+    info!("--- UNL LSP Starting (stdio) ---");
+
+    let stdin = io::stdin();
+    let mut reader = stdin.lock();
+    let stdout = io::stdout();
+    let mut writer = stdout.lock();
+    let mut state = LspState { conn: None, documents: HashMap::new(), config: CompletionConfig::default(), utf8_positions: false, shutdown: false };
+
+    while let Some(message) = read_message(&mut reader)? {
+        let method = message.get("method").and_then(|m| m.as_str()).unwrap_or("").to_string();
+        let params = message.get("params").cloned().unwrap_or(Value::Null);
+        match message.get("id").cloned() {
+            // id があればリクエスト、無ければ通知
+            Some(id) => {
+                let response = match handle_request(&mut state, &method, &params) {
+                    Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                    Err((code, msg)) => {
+                        tracing::error!("Request '{}' failed: {}", method, msg);
+                        json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": msg } })
+                    }
+                };
+                write_message(&mut writer, &response)?;
+            }
+            None => {
+                if method == "exit" {
+                    std::process::exit(if state.shutdown { 0 } else { 1 });
+                }
+                if let Err(e) = handle_notification(&mut state, &method, &params) {
+                    tracing::error!("Notification '{}' failed: {}", method, e);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn read_message(reader: &mut impl BufRead) -> anyhow::Result<Option<Value>> {
+    let mut content_length: Option<usize> = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 { return Ok(None); }
+        let line = line.trim_end();
+        if line.is_empty() { break; }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("Content-Length") {
+                content_length = value.trim().parse().ok();
+            }
+        }
+    }
+    let length = content_length.ok_or_else(|| anyhow::anyhow!("Missing Content-Length header"))?;
+    let mut body = vec![0u8; length];
+    reader.read_exact(&mut body)?;
+    Ok(Some(serde_json::from_slice(&body)?))
+}
+
+fn write_message(writer: &mut impl Write, message: &Value) -> anyhow::Result<()> {
+    let body = serde_json::to_string(message)?;
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()?;
+    Ok(())
+}
+
+fn handle_request(state: &mut LspState, method: &str, params: &Value) -> Result<Value, (i64, String)> {
+    let internal = |e: anyhow::Error| (INTERNAL_ERROR, e.to_string());
+    match method {
+        "initialize" => initialize(state, params).map_err(internal),
+        "shutdown" => {
+            state.shutdown = true;
+            Ok(Value::Null)
+        }
+        "textDocument/completion" | "textDocument/hover" | "textDocument/definition" => {
+            let conn = state.conn.as_ref().ok_or((SERVER_NOT_INITIALIZED, "Server not initialized".to_string()))?;
+            let uri = params.pointer("/textDocument/uri").and_then(|u| u.as_str())
+                .ok_or((INVALID_PARAMS, "Missing textDocument.uri".to_string()))?;
+            let line = params.pointer("/position/line").and_then(|v| v.as_u64()).unwrap_or(0) as u32;
+            let character = params.pointer("/position/character").and_then(|v| v.as_u64()).unwrap_or(0) as u32;
+            // 開いていないファイルはディスクの内容を使う
+            let content = match state.documents.get(uri) {
+                Some(c) => c.clone(),
+                None => std::fs::read_to_string(uri_to_path(uri, WINDOWS)).unwrap_or_default(),
+            };
+            // エンジンの列はバイト単位なので、既定の UTF-16 の列は行の内容から変換する
+            let character = if state.utf8_positions { character } else { utf16_to_byte_column(&content, line, character) };
+            match method {
+                "textDocument/completion" => completion::process_completion_with_config(conn, &content, line, character, Some(uri_to_path(uri, WINDOWS)), &state.config),
+                "textDocument/hover" => completion::process_hover(conn, &content, line, character),
+                _ => completion::process_definition(conn, &content, line, character),
+            }.map_err(internal)
+        }
+        "textDocument/documentSymbol" => {
+            let conn = state.conn.as_ref().ok_or((SERVER_NOT_INITIALIZED, "Server not initialized".to_string()))?;
+            let uri = params.pointer("/textDocument/uri").and_then(|u| u.as_str())
+                .ok_or((INVALID_PARAMS, "Missing textDocument.uri".to_string()))?;
+            let path = uri_to_path(uri, WINDOWS);
+            let content = match state.documents.get(uri) {
+                Some(c) => c.clone(),
+                None => std::fs::read_to_string(&path).unwrap_or_default(),
+            };
+            completion::process_document_symbols(conn, &content, Some(&path)).map_err(internal)
+        }
+        "completionItem/resolve" => {
+            let conn = state.conn.as_ref().ok_or((SERVER_NOT_INITIALIZED, "Server not initialized".to_string()))?;
+            completion::process_completion_resolve(conn, params.clone()).map_err(internal)
+        }
+        _ => Err((METHOD_NOT_FOUND, format!("Unknown method: {}", method))),
+    }
+}
+
+fn initialize(state: &mut LspState, params: &Value) -> anyhow::Result<Value> {
+    let options = params.get("initializationOptions").cloned().unwrap_or(Value::Null);
+    let db_path = options.get("db_path").and_then(|p| p.as_str()).map(|s| s.to_string())
+        .or_else(|| std::env::var("UNL_DB_PATH").ok())
+        .ok_or_else(|| anyhow::anyhow!("initializationOptions.db_path (or UNL_DB_PATH) is required"))?;
+    if let Some(config) = options.get("completion") {
+        state.config = serde_json::from_value(config.clone())?;
+    }
+    info!("Opening database: {}", db_path);
+    let conn = Connection::open(&db_path)?;
+    let _ = conn.pragma_update(None, "journal_mode", "WAL");
+    let _ = conn.pragma_update(None, "synchronous", "NORMAL");
+    let _ = conn.pragma_update(None, "temp_store", "MEMORY");
+    db::init_db(&conn)?;
+    state.conn = Some(conn);
+    // クライアントが utf-8 を扱えるなら列の変換を省く (LSP 3.17 positionEncoding)
+    let encodings = params.pointer("/capabilities/general/positionEncodings").and_then(|e| e.as_array());
+    state.utf8_positions = encodings.map_or(false, |e| e.iter().any(|v| v.as_str() == Some("utf-8")));
+    let position_encoding = if state.utf8_positions { "utf-8" } else { "utf-16" };
+
+    Ok(json!({
+        "capabilities": {
+            // 1: 毎回バッファ全体を受け取る
+            "textDocumentSync": { "openClose": true, "change": 1, "save": { "includeText": false } },
+            "positionEncoding": position_encoding,
+            "completionProvider": { "triggerCharacters": [".", ">", ":"], "resolveProvider": true },
+            "hoverProvider": true,
+            "definitionProvider": true,
+            "documentSymbolProvider": true,
+        },
+        "serverInfo": { "name": "unl-lsp", "version": env!("CARGO_PKG_VERSION") },
+    }))
+}
+
+fn handle_notification(state: &mut LspState, method: &str, params: &Value) -> anyhow::Result<()> {
+    let uri = params.pointer("/textDocument/uri").and_then(|u| u.as_str()).unwrap_or("").to_string();
+    match method {
+        "textDocument/didOpen" => {
+            let text = params.pointer("/textDocument/text").and_then(|t| t.as_str()).unwrap_or("");
+            state.documents.insert(uri, text.to_string());
+        }
+        "textDocument/didChange" => {
+            // 全体同期なので最後の変更がそのまま現在の内容
+            let changes = params.get("contentChanges").and_then(|c| c.as_array());
+            if let Some(text) = changes.and_then(|c| c.last()).and_then(|c| c.get("text")).and_then(|t| t.as_str()) {
+                state.documents.insert(uri, text.to_string());
+            }
+        }
+        "textDocument/didClose" => {
+            state.documents.remove(&uri);
+        }
+        "textDocument/didSave" => {
+            // 保存したファイルだけを解析し直して DB を最新にする
+            let Some(conn) = state.conn.as_ref() else { return Ok(()) };
+            let path = uri_to_path(&uri, WINDOWS);
+            let content = match params.get("text").and_then(|t| t.as_str()) {
+                Some(text) => text.to_string(),
+                None => std::fs::read_to_string(&path)?,
+            };
+            state.documents.insert(uri, content.clone());
+            let count = db::reindex_file(conn, &path, &content)?;
+            info!("Reindexed {} ({} classes)", path, count);
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+// file:///C:/Foo%20Bar/A.h -> C:\Foo Bar\A.h (windows) / file:///home/a.h -> /home/a.h
+fn uri_to_path(uri: &str, windows: bool) -> String {
+    let raw = uri.strip_prefix("file://").unwrap_or(uri);
+    let decoded = percent_decode(raw);
+    if windows {
+        let trimmed = match decoded.strip_prefix('/') {
+            Some(rest) if rest.as_bytes().get(1) == Some(&b':') => rest,
+            _ => decoded.as_str(),
+        };
+        trimmed.replace('/', "\\")
+    } else {
+        decoded
+    }
+}
+
+// LSP 既定の UTF-16 の列を、その行のバイト位置に直す。行末を越える列は行末にまとめる
+fn utf16_to_byte_column(content: &str, line: u32, character: u32) -> u32 {
+    let text = content.split('\n').nth(line as usize).unwrap_or("");
+    let mut units = 0;
+    for (index, c) in text.char_indices() {
+        if units >= character as usize { return index as u32; }
+        units += c.len_utf16();
+    }
+    text.trim_end_matches('\r').len() as u32
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or("");
+            if let Ok(b) = u8::from_str_radix(hex, 16) {
+                out.push(b);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn uri_to_path_decodes_unix_paths() {
+        assert_eq!(uri_to_path("file:///home/dev/My%20Game/Source/A.h", false), "/home/dev/My Game/Source/A.h");
+        assert_eq!(uri_to_path("/already/a/path.h", false), "/already/a/path.h");
+    }
+
+    #[test]
+    fn uri_to_path_handles_windows_drive_letters() {
+        assert_eq!(uri_to_path("file:///C:/Foo%20Bar/A.h", true), "C:\\Foo Bar\\A.h");
+        assert_eq!(uri_to_path("file:///c%3A/Game/Source/B.cpp", true), "c:\\Game\\Source\\B.cpp");
+        // ドライブレターが無ければ先頭の / は残す
+        assert_eq!(uri_to_path("file:///server/share/C.h", true), "\\server\\share\\C.h");
+    }
+
+    #[test]
+    fn uri_to_path_keeps_multibyte_leading_characters() {
+        assert_eq!(uri_to_path("file://%C3%A9:/Game/A.h", true), "\u{e9}:\\Game\\A.h");
+        assert_eq!(uri_to_path("file:///%C3%A9:/Game/A.h", true), "\\\u{e9}:\\Game\\A.h");
+    }
+
+    #[test]
+    fn utf16_columns_convert_to_bytes() {
+        let content = "// \u{4f53}\u{529b}\n    A->B\n    \u{1f600}X.\r\n";
+        assert_eq!(utf16_to_byte_column(content, 0, 3), 3);
+        assert_eq!(utf16_to_byte_column(content, 0, 4), 6);
+        assert_eq!(utf16_to_byte_column(content, 0, 5), 9);
+        assert_eq!(utf16_to_byte_column(content, 1, 8), 8);
+        // サロゲートペアは UTF-16 で 2 単位
+        assert_eq!(utf16_to_byte_column(content, 2, 6), 8);
+        assert_eq!(utf16_to_byte_column(content, 2, 8), 10);
+        // 行末を越えた列と存在しない行
+        assert_eq!(utf16_to_byte_column(content, 2, 50), 10);
+        assert_eq!(utf16_to_byte_column(content, 9, 4), 0);
+    }
+
+    #[test]
+    fn percent_decode_handles_escapes() {
+        assert_eq!(percent_decode("A%20B"), "A B");
+        assert_eq!(percent_decode("%E3%81%82.h"), "\u{3042}.h");
+        assert_eq!(percent_decode("%2f%2F"), "//");
+        // 不完全/不正なエスケープはそのまま残す
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%4"), "%4");
+        assert_eq!(percent_decode("%zz%41"), "%zzA");
+    }
+
+    #[test]
+    fn messages_round_trip_through_framing() {
+        let first = json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": { "rootUri": "file:///C:/My%20Game" } });
+        let second = json!({ "jsonrpc": "2.0", "method": "textDocument/didOpen", "params": { "textDocument": { "text": "// \u{3042}\r\n" } } });
+        let mut buffer = Vec::new();
+        write_message(&mut buffer, &first).unwrap();
+        write_message(&mut buffer, &second).unwrap();
+
+        let mut reader = Cursor::new(buffer);
+        assert_eq!(read_message(&mut reader).unwrap(), Some(first));
+        assert_eq!(read_message(&mut reader).unwrap(), Some(second));
+        assert_eq!(read_message(&mut reader).unwrap(), None);
+    }
+
+    #[test]
+    fn read_message_accepts_extra_headers() {
+        let body = r#"{"jsonrpc":"2.0","method":"exit"}"#;
+        let framed = format!("content-length: {}\r\nContent-Type: application/vscode-jsonrpc; charset=utf-8\r\n\r\n{}", body.len(), body);
+        let message = read_message(&mut Cursor::new(framed.into_bytes())).unwrap().unwrap();
+        assert_eq!(message["method"], "exit");
+
+        assert!(read_message(&mut Cursor::new(b"Content-Type: x\r\n\r\n{}".to_vec())).is_err());
+    }
+}
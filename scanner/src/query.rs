@@ -1302,7 +1302,7 @@ pub fn process_query(conn: &Connection, req: QueryRequest) -> anyhow::Result<Val
                     p
                 }
             }).unwrap_or_else(|| "buffer.cpp".to_string());
-            tracing::info!("Parsing buffer for: {}", path);
+            tracing::debug!("Parsing buffer for: {}", path);
             let language: tree_sitter::Language = tree_sitter_unreal_cpp::LANGUAGE.into();
             let query = tree_sitter::Query::new(&language, crate::scanner::QUERY_STR)?;
             
@@ -1390,6 +1390,7 @@ pub fn process_query(conn: &Connection, req: QueryRequest) -> anyhow::Result<Val
                  WHERE name = ? AND class_id = (SELECT id FROM classes WHERE name = ?)"
              )?;
              let count = stmt.execute(params![return_type, member_name, class_name])?;
+             if count > 0 { crate::db::bump_generation(conn)?; }
              Ok(json!({ "updated": count }))
         },
         QueryRequest::GetTargetFiles => {
@@ -1426,8 +1427,51 @@ pub fn process_query(conn: &Connection, req: QueryRequest) -> anyhow::Result<Val
                              QueryRequest::GetClassesInModulesAsync { .. } => {
                                  Err(anyhow::anyhow!("Async queries must be processed via process_query_streaming"))
                              },
-        QueryRequest::GetCompletions { content, line, character, file_path } => {
-            crate::completion::process_completion(conn, &content, line, character, file_path)
+        QueryRequest::GetCompletions { content, line, character, file_path, config } => {
+            crate::completion::process_completion_with_config(conn, &content, line, character, file_path, &config)
+        }
+        QueryRequest::GetCompletionsDetailed { content, line, character } => {
+            crate::completion::process_completion_detailed(conn, &content, line, character)
+        }
+        QueryRequest::GetDiagnostics { content, file_path } => {
+            crate::completion::process_diagnostics(conn, &content, file_path.as_deref())
+        }
+        QueryRequest::GetVirtualMembers { class_name } => {
+            crate::completion::virtual_members(conn, &class_name)
+        }
+        QueryRequest::ExplainResolution { content, line, character } => {
+            crate::completion::explain_resolution(conn, &content, line, character)
+        }
+        QueryRequest::GetHover { content, line, character } => {
+            crate::completion::process_hover(conn, &content, line, character)
+        }
+        QueryRequest::GetDefinition { content, line, character } => {
+            crate::completion::process_definition(conn, &content, line, character)
+        }
+        QueryRequest::GetSignatureHelp { content, line, character } => {
+            crate::completion::process_signature_help(conn, &content, line, character)
+        }
+        QueryRequest::VerifyTypeComplete { class_name } => {
+            Ok(json!(crate::completion::verify_type_complete(conn, &class_name)?))
+        }
+        QueryRequest::GetEnumValueDetails { enum_name } => {
+            crate::completion::enum_values(conn, &enum_name)
+        }
+        QueryRequest::ResolveCompletionItem { item } => {
+            crate::completion::process_completion_resolve(conn, item)
+        }
+        QueryRequest::InvalidateCompletionCache { paths } => {
+            let paths: Vec<std::path::PathBuf> = paths.into_iter().map(std::path::PathBuf::from).collect();
+            Ok(json!({ "removed": crate::completion_cache::invalidate(conn, &paths)? }))
+        }
+        QueryRequest::ReindexFile { file_path, content } => {
+            Ok(json!({ "classes": crate::db::reindex_file(conn, &file_path, &content)? }))
+        }
+        QueryRequest::GetWorkspaceSymbols { query, limit } => {
+            crate::completion::process_workspace_symbols(conn, &query, limit)
+        }
+        QueryRequest::GetDocumentSymbols { content, file_path } => {
+            crate::completion::process_document_symbols(conn, &content, file_path.as_deref())
         }
                          }
                      }
@@ -1,8 +1,9 @@
 use std::fs;
+use std::sync::OnceLock;
 use tree_sitter::{Parser, Query, QueryCursor, Node};
 use streaming_iterator::StreamingIterator;
 use sha2::{Sha256, Digest};
-use crate::types::{InputFile, ParseResult, ParseData, ClassInfo, MemberInfo};
+use crate::types::{InputFile, ParseResult, ParseData, ClassInfo, MemberInfo, LogCategoryInfo, CVarInfo, LocTextInfo};
 
 pub const QUERY_STR: &str = r#"
   (class_specifier name: (type_identifier) @class_name) @class_def
@@ -31,15 +32,13 @@ pub const QUERY_STR: &str = r#"
   (unreal_function_declaration) @ufunc_node
   (field_declaration) @field_node
   (enumerator name: (identifier) @enum_val_name) @enum_item
+  (preproc_def name: (identifier) @macro_name)
+  (preproc_function_def name: (identifier) @macro_func_name)
 "#;
 
 pub fn process_file(input: &InputFile, language: &tree_sitter::Language, query: &Query) -> anyhow::Result<ParseResult> {
     let content = fs::read_to_string(&input.path)?;
-    let content_bytes = content.as_bytes();
-    
-    let mut hasher = Sha256::new();
-    hasher.update(content_bytes);
-    let new_hash = format!("{:x}", hasher.finalize());
+    let new_hash = content_hash(&content);
 
     if let Some(old) = &input.old_hash {
         if old == &new_hash {
@@ -53,16 +52,33 @@ pub fn process_file(input: &InputFile, language: &tree_sitter::Language, query:
         }
     }
 
-    let classes = parse_content(&content, &input.path, language, query)?;
-
     Ok(ParseResult {
         path: input.path.clone(), status: "parsed".to_string(), mtime: input.mtime,
-        data: Some(ParseData { classes, parser: "treesitter".to_string(), new_hash }),
+        data: Some(parse_data(&content, &input.path, new_hash, language, query)?),
         module_id: input.module_id,
     })
 }
 
-pub fn parse_content(content: &str, _path: &str, language: &tree_sitter::Language, query: &Query) -> anyhow::Result<Vec<ClassInfo>> {
+pub fn content_hash(content: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(content.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+/// ファイル 1 つ分の DB に保存する内容 (クラス, グローバル, ログカテゴリ, CVar, LOCTEXT)
+pub fn parse_data(content: &str, path: &str, new_hash: String, language: &tree_sitter::Language, query: &Query) -> anyhow::Result<ParseData> {
+    let (classes, globals) = parse_content_with_globals(content, path, language, query)?;
+    let (log_categories, cvars) = parse_console_symbols(content);
+    let loc_texts = parse_localization_symbols(content);
+    Ok(ParseData { classes, parser: "treesitter".to_string(), new_hash, log_categories, cvars, loc_texts, globals })
+}
+
+pub fn parse_content(content: &str, path: &str, language: &tree_sitter::Language, query: &Query) -> anyhow::Result<Vec<ClassInfo>> {
+    Ok(parse_content_with_globals(content, path, language, query)?.0)
+}
+
+/// クラスに加えて、どのクラスにも属さない関数宣言と #define マクロ (グローバル補完用) を返す
+pub fn parse_content_with_globals(content: &str, _path: &str, language: &tree_sitter::Language, query: &Query) -> anyhow::Result<(Vec<ClassInfo>, Vec<MemberInfo>)> {
     let content_bytes = content.as_bytes();
     let mut parser = Parser::new();
     parser.set_language(&language).unwrap();
@@ -73,7 +89,9 @@ pub fn parse_content(content: &str, _path: &str, language: &tree_sitter::Languag
     let mut captures = cursor.captures(query, root, content_bytes);
     
     let mut classes: Vec<ClassInfo> = Vec::new();
-    let mut members: Vec<(MemberInfo, usize, usize)> = Vec::new();
+    // (メンバー, 開始, 終了, 関数本体の外にある関数か)
+    let mut members: Vec<(MemberInfo, usize, usize, bool)> = Vec::new();
+    let mut globals: Vec<MemberInfo> = Vec::new();
 
     while let Some((m, capture_index)) = captures.next() {
         let capture = m.captures[*capture_index];
@@ -256,6 +274,7 @@ pub fn parse_content(content: &str, _path: &str, language: &tree_sitter::Languag
             let definition_node = node;
             
             let mut member_name = String::new();
+            let mut member_name_start = None;
             let mut scope_name = None;
             let mut is_function = *capture_name == "func_node" || *capture_name == "ufunc_node";
             
@@ -265,9 +284,41 @@ pub fn parse_content(content: &str, _path: &str, language: &tree_sitter::Languag
                     match current.kind() {
                         "identifier" | "field_identifier" => {
                             member_name = get_node_text(&current, content_bytes).to_string();
+                            member_name_start = Some(current.start_byte());
+                            break;
+                        },
+                        // T& operator[](int32 Index) は添字アクセスの型解決に使う。"operator []" は "operator[]"、変換演算子は "operator bool"
+                        "operator_name" => {
+                            let text = get_node_text(&current, content_bytes);
+                            let rest = text.trim_start_matches("operator").trim();
+                            member_name = if rest.starts_with(|c: char| c.is_alphanumeric() || c == '_') {
+                                format!("operator {}", rest.split_whitespace().collect::<Vec<_>>().join(" "))
+                            } else {
+                                format!("operator{}", rest.split_whitespace().collect::<String>())
+                            };
+                            member_name_start = Some(current.start_byte());
+                            break;
+                        },
+                        // 変換演算子 (operator bool() const) は operator_name ではなく operator_cast になる
+                        "operator_cast" => {
+                            let target = match current.child_by_field_name("type") {
+                                Some(t) => get_node_text(&t, content_bytes),
+                                None => get_node_text(&current, content_bytes).trim_start_matches("operator").split('(').next().unwrap_or(""),
+                            };
+                            member_name = format!("operator {}", target.split_whitespace().collect::<Vec<_>>().join(" "));
+                            member_name_start = Some(current.start_byte());
+                            is_function = true;
+                            break;
+                        },
+                        // ~AMyActor() はチルダ付きの名前で索引する (補完では隠す)
+                        "destructor_name" => {
+                            member_name = get_node_text(&current, content_bytes).split_whitespace().collect::<String>();
+                            member_name_start = Some(current.start_byte());
+                            is_function = true;
                             break;
                         },
                         "qualified_identifier" => {
+                            member_name_start = Some(current.start_byte());
                             if let Some(s) = current.child_by_field_name("scope") {
                                 scope_name = Some(get_node_text(&s, content_bytes).to_string());
                             }
@@ -327,15 +378,25 @@ pub fn parse_content(content: &str, _path: &str, language: &tree_sitter::Languag
             }
 
             let node_text = get_node_text(&definition_node, content_bytes);
-            if node_text.contains("virtual") { flags.push("virtual"); }
-            if node_text.contains("static") { flags.push("static"); }
-            if node_text.contains("override") { flags.push("override"); }
+            // インライン定義の本体内の単語を拾わないよう、宣言部分だけを見る
+            let head = node_text.split('{').next().unwrap_or(node_text);
+            if find_word(head, "virtual").is_some() { flags.push("virtual"); }
+            if find_word(head, "static").is_some() { flags.push("static"); }
+            if find_word(head, "override").is_some() { flags.push("override"); }
+            if find_word(head, "final").is_some() { flags.push("final"); }
+            if is_deprecated_member(head, &member_name) { flags.push("deprecated"); }
+            if is_editor_only(definition_node, content_bytes) { flags.push("editor_only"); }
 
             let mut detail = None;
             let mut return_type = None;
             let mem_type = if is_function { "function" } else { "property" };
 
-            if let Some(idx) = node_text.find(&member_name) {
+            // 型名が変数名を含むケース (AController* Controller) があるため、宣言子の位置で区切る
+            let name_idx = member_name_start
+                .and_then(|start| start.checked_sub(definition_node.start_byte()))
+                .filter(|&idx| node_text.is_char_boundary(idx))
+                .or_else(|| find_word(node_text, &member_name));
+            if let Some(idx) = name_idx {
                 let prefix = &node_text[..idx];
                 let mut actual_prefix = prefix;
                 if let Some(macro_end) = prefix.rfind(')') {
@@ -348,6 +409,10 @@ pub fn parse_content(content: &str, _path: &str, language: &tree_sitter::Languag
                         cleaned = cleaned[..s_idx].trim().to_string();
                     }
                 }
+                // static constexpr auto DefaultColor = FColor(...) は初期化式の型
+                if cleaned == "auto" && !is_function {
+                    if let Some(init_type) = initializer_type(&node_text[idx..]) { cleaned = init_type; }
+                }
                 if !cleaned.is_empty() { return_type = Some(cleaned); }
             }
 
@@ -367,6 +432,9 @@ pub fn parse_content(content: &str, _path: &str, language: &tree_sitter::Languag
                     end_line: definition_node.end_position().row + 1,
                     detail,
                     return_type,
+                    documentation: None,
+                    display_name: None,
+                    category: specifier_category(head),
                 };
 
                 if let Some(sn) = scope_name {
@@ -393,27 +461,64 @@ pub fn parse_content(content: &str, _path: &str, language: &tree_sitter::Languag
                     member.access = "impl".to_string();
                     cls.members.push(member);
                 } else {
-                    members.push((member, definition_node.start_byte(), definition_node.end_byte()));
+                    // 関数本体の中の `FFoo Bar(Baz);` は呼び出しと区別できないのでグローバル関数として扱わない
+                    let free_function = is_function && !has_ancestor(definition_node, "compound_statement");
+                    members.push((member, definition_node.start_byte(), definition_node.end_byte(), free_function));
                 }
             }
         } else if *capture_name == "enum_val_name" {
             let name = get_node_text(&node, content_bytes).to_string();
             if !name.is_empty() {
+                // enum class の値は EnumName:: の後でだけ候補にするため印を付ける
+                let scoped = node.parent().and_then(|e| e.parent()).and_then(|list| list.parent())
+                    .map_or(false, |def| {
+                        let text = get_node_text(&def, content_bytes);
+                        let head = text.split('{').next().unwrap_or("");
+                        head.split_whitespace().any(|w| w == "class" || w == "struct")
+                    });
                 members.push((MemberInfo {
                     name,
                     mem_type: "enum_item".to_string(),
+                    flags: if scoped { "scoped".to_string() } else { String::new() },
+                    access: "public".to_string(),
+                    line: node.start_position().row + 1,
+                    end_line: node.end_position().row + 1,
+                    // 明示的な値 (= 5) があれば detail に保持
+                    detail: node.parent()
+                        .and_then(|e| e.child_by_field_name("value"))
+                        .map(|v| get_node_text(&v, content_bytes).trim().to_string()),
+                    return_type: None,
+                    documentation: node.parent().and_then(|e| enumerator_comment(&e, content_bytes)),
+                    display_name: node.parent().and_then(|e| umeta_display_name(&e, content_bytes)),
+                    category: None,
+                }, node.start_byte(), node.end_byte(), false));
+            }
+        } else if *capture_name == "macro_name" || *capture_name == "macro_func_name" {
+            let name = get_node_text(&node, content_bytes).to_string();
+            // インクルードガード (FOO_H) や UE の生成マクロ (CURRENT_FILE_ID) は候補にしない
+            let is_guard = name.ends_with("_H") || name.ends_with("_H_") || name.ends_with("_INCLUDED");
+            if !name.is_empty() && !is_guard && name != "CURRENT_FILE_ID" && !globals.iter().any(|g| g.name == name) {
+                let params = node.parent()
+                    .and_then(|def| def.child_by_field_name("parameters"))
+                    .map(|p| get_node_text(&p, content_bytes).to_string());
+                globals.push(MemberInfo {
+                    name,
+                    mem_type: if *capture_name == "macro_func_name" { "macro_function" } else { "macro" }.to_string(),
                     flags: String::new(),
                     access: "public".to_string(),
                     line: node.start_position().row + 1,
                     end_line: node.end_position().row + 1,
-                    detail: None,
+                    detail: params,
                     return_type: None,
-                }, node.start_byte(), node.end_byte()));
+                    documentation: None,
+                    display_name: None,
+                    category: None,
+                });
             }
         }
     }
     
-    for (member, m_start, m_end) in members {
+    for (member, m_start, m_end, free_function) in members {
         let mut best_class_idx = None;
         let mut min_size = usize::MAX;
         for (i, cls) in classes.iter().enumerate() {
@@ -422,14 +527,148 @@ pub fn parse_content(content: &str, _path: &str, language: &tree_sitter::Languag
                 if size < min_size { min_size = size; best_class_idx = Some(i); }
             }
         }
-        if let Some(idx) = best_class_idx { classes[idx].members.push(member); }
+        match best_class_idx {
+            Some(idx) => classes[idx].members.push(member),
+            None if free_function && !globals.iter().any(|g| g.name == member.name && g.mem_type == "function") => globals.push(member),
+            None => {}
+        }
     }
     
-    Ok(classes)
+    Ok((classes, globals))
+}
+
+fn has_ancestor(node: Node, kind: &str) -> bool {
+    let mut curr = node.parent();
+    while let Some(p) = curr {
+        if p.kind() == kind { return true; }
+        curr = p.parent();
+    }
+    false
+}
+
+// ログカテゴリ・コンソール変数/コマンドの定義はマクロ引数や文字列リテラルなので正規表現で拾う
+pub fn parse_console_symbols(content: &str) -> (Vec<LogCategoryInfo>, Vec<CVarInfo>) {
+    let mut categories = Vec::new();
+    let mut cvars = Vec::new();
+    if !content.contains("LOG_CATEGORY") && !content.contains("Console") {
+        return (categories, cvars);
+    }
+
+    // 全ファイルの走査で呼ばれるので、正規表現は一度だけコンパイルする
+    static CATEGORY_RE: OnceLock<regex::Regex> = OnceLock::new();
+    static CVAR_RE: OnceLock<regex::Regex> = OnceLock::new();
+    let category_re = CATEGORY_RE.get_or_init(|| regex::Regex::new(
+        r"\b(?:DECLARE_LOG_CATEGORY_EXTERN|DECLARE_LOG_CATEGORY_CLASS|DEFINE_LOG_CATEGORY_STATIC|DEFINE_LOG_CATEGORY)\s*\(\s*([A-Za-z_][A-Za-z0-9_]*)"
+    ).unwrap());
+    let cvar_re = CVAR_RE.get_or_init(|| regex::Regex::new(
+        r#"\b(TAutoConsoleVariable\s*<[^>]*>|FAutoConsoleVariableRef|FAutoConsoleVariable|FAutoConsoleCommand\w*|RegisterConsoleVariable\w*|RegisterConsoleCommand)\s*(?:[A-Za-z_][A-Za-z0-9_]*\s*)?[({]\s*(?:TEXT\s*\(\s*)?"([^"]+)""#
+    ).unwrap());
+
+    // 定義は複数行にまたがることが多いので、行単位ではなく全文に対してマッチさせる
+    let line_of = |offset: usize| content[..offset].matches('\n').count() + 1;
+    for cap in category_re.captures_iter(content) {
+        let m = cap.get(1).unwrap();
+        let name = m.as_str();
+        if name != "CategoryName" && !categories.iter().any(|c: &LogCategoryInfo| c.name == name) {
+            categories.push(LogCategoryInfo { name: name.to_string(), line: line_of(m.start()) });
+        }
+    }
+    for cap in cvar_re.captures_iter(content) {
+        let kind = if cap.get(1).unwrap().as_str().contains("Command") { "command" } else { "variable" };
+        let m = cap.get(2).unwrap();
+        let name = m.as_str();
+        if !cvars.iter().any(|c: &CVarInfo| c.name == name) {
+            cvars.push(CVarInfo { name: name.to_string(), kind: kind.to_string(), line: line_of(m.start()) });
+        }
+    }
+    (categories, cvars)
+}
+
+// NSLOCTEXT("Namespace", "Key", ...) と、#define LOCTEXT_NAMESPACE 配下の LOCTEXT("Key", ...)
+pub fn parse_localization_symbols(content: &str) -> Vec<LocTextInfo> {
+    let mut texts = Vec::new();
+    if !content.contains("LOCTEXT") {
+        return texts;
+    }
+
+    static LOC_RE: OnceLock<regex::Regex> = OnceLock::new();
+    let loc_re = LOC_RE.get_or_init(|| regex::Regex::new(
+        r#"#\s*define\s+LOCTEXT_NAMESPACE\s+"([^"]*)"|#\s*undef\s+LOCTEXT_NAMESPACE|\bLOCTEXT\s*\(\s*"([^"]*)"|\bNSLOCTEXT\s*\(\s*"([^"]*)"\s*,\s*"([^"]*)""#
+    ).unwrap());
+
+    let line_of = |offset: usize| content[..offset].matches('\n').count() + 1;
+    let push = |texts: &mut Vec<LocTextInfo>, namespace: &str, key: &str, offset: usize| {
+        if !texts.iter().any(|t: &LocTextInfo| t.namespace == namespace && t.key == key) {
+            texts.push(LocTextInfo { namespace: namespace.to_string(), key: key.to_string(), line: line_of(offset) });
+        }
+    };
+    // マッチはファイル先頭から順に返るので、直近の LOCTEXT_NAMESPACE を追跡できる
+    let mut current_namespace: Option<String> = None;
+    for cap in loc_re.captures_iter(content) {
+        if let Some(ns) = cap.get(1) {
+            current_namespace = Some(ns.as_str().to_string());
+            push(&mut texts, ns.as_str(), "", ns.start());
+        } else if let Some(key) = cap.get(2) {
+            if let Some(ns) = &current_namespace {
+                push(&mut texts, ns, key.as_str(), key.start());
+            }
+        } else if let (Some(ns), Some(key)) = (cap.get(3), cap.get(4)) {
+            push(&mut texts, ns.as_str(), key.as_str(), ns.start());
+        } else {
+            current_namespace = None;
+        }
+    }
+    texts
 }
 
 // --- Internal Helpers ---
 
+// "Name = FColor(255, 0, 0)" / "Name{FLinearColor{...}}" の FColor / FLinearColor 部分 (型名らしい識別子のみ)
+fn initializer_type(decl_text: &str) -> Option<String> {
+    let value = decl_text.split_once(['=', '{']).map(|(_, v)| v.trim_start())?;
+    let name: String = value.chars().take_while(|c| c.is_alphanumeric() || *c == '_').collect();
+    let rest = value[name.len()..].trim_start();
+    // TEXT("...") のような全大文字のマクロは除く
+    let type_like = name.starts_with(|c: char| c.is_ascii_uppercase()) && name.chars().any(|c| c.is_ascii_lowercase());
+    if type_like && (rest.starts_with('(') || rest.starts_with('{')) { Some(name) } else { None }
+}
+
+// 列挙子に付いた UMETA(DisplayName = "...") の表示名
+fn umeta_display_name(enumerator: &Node, source: &[u8]) -> Option<String> {
+    // UMETA は列挙子ノードの外に出ることがあるので次の列挙子の手前までを見る
+    let mut end = enumerator.parent().map_or(enumerator.end_byte(), |p| p.end_byte());
+    let mut next = enumerator.next_named_sibling();
+    while let Some(n) = next {
+        if n.kind() == "enumerator" { end = n.start_byte(); break; }
+        next = n.next_named_sibling();
+    }
+    let text = std::str::from_utf8(&source[enumerator.start_byte()..end]).ok()?;
+    let meta = &text[text.find("UMETA")?..];
+    let after_key = &meta[meta.find("DisplayName")? + "DisplayName".len()..];
+    let value = after_key.trim_start().strip_prefix('=')?.trim_start().strip_prefix('"')?;
+    Some(value[..value.find('"')?].to_string())
+}
+
+// 列挙子の直前の行コメント、なければ同じ行の後ろのコメント
+fn enumerator_comment(enumerator: &Node, source: &[u8]) -> Option<String> {
+    let row = enumerator.start_position().row;
+    let strip = |n: &Node| {
+        let text = get_node_text(n, source).trim();
+        let text = text.trim_start_matches('/').trim_start_matches('*').trim_end_matches("*/").trim();
+        if text.is_empty() { None } else { Some(text.to_string()) }
+    };
+    if let Some(prev) = enumerator.prev_named_sibling() {
+        let own_line = prev.prev_named_sibling().map_or(true, |p| p.end_position().row < prev.start_position().row);
+        if prev.kind() == "comment" && prev.end_position().row + 1 == row && own_line {
+            return strip(&prev);
+        }
+    }
+    match enumerator.next_named_sibling() {
+        Some(n) if n.kind() == "comment" && n.start_position().row == row => strip(&n),
+        _ => None,
+    }
+}
+
 fn get_node_text<'a>(node: &Node, source: &'a [u8]) -> &'a str {
     node.utf8_text(source).unwrap_or("")
 }
@@ -440,7 +679,9 @@ fn get_namespace<'a>(node: &Node<'a>, source: &'a [u8]) -> Option<String> {
     
     while let Some(n) = current {
         let kind = n.kind();
-        if kind == "namespace_definition" || kind == "class_specifier" || kind == "struct_specifier" {
+        // namespace UE::Chaos { は name が "UE::Chaos" の nested_namespace_specifier になる
+        if kind == "namespace_definition" || kind == "class_specifier" || kind == "struct_specifier"
+            || kind == "unreal_class_declaration" || kind == "unreal_struct_declaration" {
             if let Some(name_node) = n.child_by_field_name("name") {
                 ns_parts.push(get_node_text(&name_node, source).to_string());
             }
@@ -488,13 +729,71 @@ fn find_declarator_node<'a>(node: Node<'a>) -> Option<Node<'a>> {
     None
 }
 
+// UE_DEPRECATED(...) / [[deprecated]] / meta=(DeprecatedFunction) / Foo_DEPRECATED
+fn is_deprecated_member(head: &str, member_name: &str) -> bool {
+    head.contains("UE_DEPRECATED") || head.contains("[[deprecated") ||
+    head.contains("DeprecatedFunction") || head.contains("DeprecatedProperty") ||
+    member_name.ends_with("_DEPRECATED")
+}
+
+// UPROPERTY(EditAnywhere, Category = "Combat|Weapon") / UFUNCTION(Category=Combat) の Category
+fn specifier_category(head: &str) -> Option<String> {
+    let start = head.find("UPROPERTY").or_else(|| head.find("UFUNCTION"))?;
+    let specifiers = &head[start..];
+    let idx = find_word(specifiers, "Category")?;
+    let value = specifiers[idx + "Category".len()..].trim_start().strip_prefix('=')?.trim_start();
+    let category = match value.strip_prefix('"') {
+        Some(quoted) => &quoted[..quoted.find('"')?],
+        None => value.split(|c: char| c == ',' || c == ')').next()?.trim(),
+    };
+    if category.is_empty() { None } else { Some(category.to_string()) }
+}
+
+// #if WITH_EDITOR / WITH_EDITORONLY_DATA の内側 (#else 側は除く)
+fn is_editor_only(node: Node, content_bytes: &[u8]) -> bool {
+    let mut in_else = false;
+    let mut curr = node;
+    while let Some(parent) = curr.parent() {
+        match parent.kind() {
+            "preproc_else" | "preproc_elif" => in_else = true,
+            "preproc_if" | "preproc_ifdef" => {
+                if !in_else {
+                    if let Some(cond) = parent.child_by_field_name("condition").or_else(|| parent.child_by_field_name("name")) {
+                        let cond_text = get_node_text(&cond, content_bytes);
+                        if cond_text.contains("WITH_EDITOR") && !cond_text.contains('!') { return true; }
+                    }
+                }
+                in_else = false;
+            }
+            _ => {}
+        }
+        curr = parent;
+    }
+    false
+}
+
+pub fn find_word(text: &str, word: &str) -> Option<usize> {
+    let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+    let mut from = 0;
+    while let Some(pos) = text[from..].find(word) {
+        let idx = from + pos;
+        let end = idx + word.len();
+        let before_ok = text[..idx].chars().next_back().map_or(true, |c| !is_ident(c));
+        let after_ok = text[end..].chars().next().map_or(true, |c| !is_ident(c));
+        if before_ok && after_ok { return Some(idx); }
+        from = end;
+    }
+    None
+}
+
 fn clean_type_string(s: &str) -> String {
     let mut words: Vec<String> = Vec::new();
     for word in s.split_whitespace() {
         let w = word.trim();
         if w.is_empty() { continue; }
         
-        if w == "virtual" || w == "static" || w == "inline" || w == "FORCEINLINE" || 
+        if w == "virtual" || w == "static" || w == "thread_local" || w == "inline" || w == "FORCEINLINE" || 
+           w == "constexpr" || w == "constinit" || w == "consteval" || w == "mutable" ||
            w == "FORCEINLINE_DEBUGGABLE" ||
            w == "const" || w == "friend" || w == "class" || w == "struct" || w == "enum" ||
            w.starts_with("UE_DEPRECATED") || 
@@ -510,3 +809,61 @@ fn clean_type_string(s: &str) -> String {
     }
     words.join(" ")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn member_names(content: &str) -> Vec<String> {
+        let language: tree_sitter::Language = tree_sitter_unreal_cpp::LANGUAGE.into();
+        let query = Query::new(&language, QUERY_STR).unwrap();
+        let classes = parse_content(content, "Handle.h", &language, &query).unwrap();
+        classes.into_iter().flat_map(|c| c.members).map(|m| m.name).collect()
+    }
+
+    #[test]
+    fn operator_names_are_normalised() {
+        let names = member_names(r#"
+struct FHandle
+{
+    FHandle();
+    ~FHandle();
+    int32& operator [](int32 Index);
+    bool operator == (const FHandle& Other) const;
+    operator bool() const;
+    explicit operator  FString() const;
+};
+"#);
+        for expected in ["FHandle", "~FHandle", "operator[]", "operator==", "operator bool", "operator FString"] {
+            assert!(names.iter().any(|n| n == expected), "missing {} in {:?}", expected, names);
+        }
+    }
+
+    #[test]
+    fn globals_skip_include_guards_and_local_declarations() {
+        let language: tree_sitter::Language = tree_sitter_unreal_cpp::LANGUAGE.into();
+        let query = Query::new(&language, QUERY_STR).unwrap();
+        let content = r#"
+#ifndef GAME_UTIL_H
+#define GAME_UTIL_H
+#define GAME_MAX_PLAYERS 8
+#define GAME_CLAMP(X) (X)
+
+float GameClampHealth(float Value);
+
+void RunGame()
+{
+    FGameState GameSaved(Slot);
+}
+#endif
+"#;
+        let (_, globals) = parse_content_with_globals(content, "Util.h", &language, &query).unwrap();
+        let names: Vec<(&str, &str)> = globals.iter().map(|g| (g.name.as_str(), g.mem_type.as_str())).collect();
+        assert!(names.contains(&("GAME_MAX_PLAYERS", "macro")), "{:?}", names);
+        assert!(names.contains(&("GAME_CLAMP", "macro_function")), "{:?}", names);
+        assert!(names.contains(&("GameClampHealth", "function")), "{:?}", names);
+        assert!(!names.iter().any(|(name, _)| *name == "GAME_UTIL_H" || *name == "GameSaved"), "{:?}", names);
+        let clamp = globals.iter().find(|g| g.name == "GAME_CLAMP").unwrap();
+        assert_eq!(clamp.detail.as_deref(), Some("(X)"));
+    }
+}
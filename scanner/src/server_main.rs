@@ -6,6 +6,7 @@ use tokio::net::{TcpListener, TcpStream};
 use tokio::sync::mpsc;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tracing::info;
+use tracing_subscriber::filter::LevelFilter;
 use notify::{Watcher, RecursiveMode, EventKind};
 use unl_core::types::{RefreshRequest, ScanRequest, ParseResult, InputFile, WatchRequest, QueryRequest, SetupRequest, Progress, ProgressReporter};
 use unl_core::{scanner, db, refresh};
@@ -134,7 +135,11 @@ async fn main() -> anyhow::Result<()> {
     let registry_path = args.get(2).map(PathBuf::from);
     let log_path = if let Some(ref p) = registry_path { p.parent().unwrap().join("unl-server.log") } else { PathBuf::from("unl-server.log") };
     let log_file = std::fs::OpenOptions::new().create(true).append(true).open(&log_path)?;
-    tracing_subscriber::fmt().with_writer(Arc::new(log_file)).init();
+    // 3 番目の引数 (無ければ UNL_LOG_LEVEL) で出力の上限を決める (off/error/warn/info/debug/trace, 既定は info)
+    let max_level = args.get(3).cloned().or_else(|| std::env::var("UNL_LOG_LEVEL").ok())
+        .and_then(|s| s.parse::<LevelFilter>().ok())
+        .unwrap_or(LevelFilter::INFO);
+    tracing_subscriber::fmt().with_writer(Arc::new(log_file)).with_max_level(max_level).init();
     info!("--- UNL Server Starting (MsgPack) ---");
 
     let (tx, mut rx) = mpsc::channel::<PathBuf>(100);
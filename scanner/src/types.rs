@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 
 #[derive(Deserialize, Debug)]
 #[serde(tag = "type")]
@@ -74,6 +76,34 @@ pub struct ParseData {
     pub classes: Vec<ClassInfo>,
     pub parser: String,
     pub new_hash: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub log_categories: Vec<LogCategoryInfo>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub cvars: Vec<CVarInfo>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub loc_texts: Vec<LocTextInfo>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub globals: Vec<MemberInfo>, // クラス外の関数と #define マクロ
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct LogCategoryInfo {
+    pub name: String,
+    pub line: usize,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct CVarInfo {
+    pub name: String,
+    pub kind: String, // "variable", "command"
+    pub line: usize,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct LocTextInfo {
+    pub namespace: String,
+    pub key: String, // LOCTEXT_NAMESPACE の定義のみの場合は空
+    pub line: usize,
 }
 
 #[derive(Serialize, Clone, Debug)]
@@ -106,6 +136,12 @@ pub struct MemberInfo {
     pub detail: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub return_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub documentation: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
 }
 
 #[derive(Serialize, Debug)]
@@ -220,7 +256,219 @@ pub enum QueryRequest {
         line: u32,
         character: u32,
         file_path: Option<String>,
+        #[serde(default)]
+        config: CompletionConfig,
+    },
+    GetCompletionsDetailed {
+        content: String,
+        line: u32,
+        character: u32,
+    },
+    GetDiagnostics {
+        content: String,
+        #[serde(default)]
+        file_path: Option<String>,
+    },
+    GetVirtualMembers { class_name: String },
+    ExplainResolution {
+        content: String,
+        line: u32,
+        character: u32,
+    },
+    VerifyTypeComplete { class_name: String },
+    GetEnumValueDetails { enum_name: String },
+    ResolveCompletionItem { item: serde_json::Value },
+    InvalidateCompletionCache { paths: Vec<String> },
+    ReindexFile { file_path: String, content: String },
+    GetWorkspaceSymbols {
+        query: String,
+        #[serde(default)]
+        limit: Option<usize>,
+    },
+    GetDocumentSymbols {
+        content: String,
+        #[serde(default)]
+        file_path: Option<String>,
+    },
+    GetHover {
+        content: String,
+        line: u32,
+        character: u32,
+    },
+    GetDefinition {
+        content: String,
+        line: u32,
+        character: u32,
     },
+    GetSignatureHelp {
+        content: String,
+        line: u32,
+        character: u32,
+    },
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct CompletionConfig {
+    #[serde(default, rename = "timeout_ms", with = "duration_ms")]
+    pub timeout: Option<Duration>, // 超過時は収集済みの候補を isIncomplete: true で返す
+    #[serde(default)]
+    pub unprefixed_filter_text: bool, // UFoo の filterText を "Foo UFoo" にする
+    #[serde(default)]
+    pub recency_boost: bool, // 編集中/最近更新されたファイルで定義された候補を sortText で上位にする
+    #[serde(default)]
+    pub subobject_name_heuristic: bool, // GetDefaultSubobjectByName(TEXT("Mesh")) を同名メンバーの型に解決する
+    #[serde(default)]
+    pub qualified_insert_text: bool, // スコープ未入力なら enum 値/静的メンバーの insertText を EMyEnum::Rifle にする
+    #[serde(default)]
+    pub item_defaults: bool, // 全候補で共通の値を CompletionList.itemDefaults にまとめる
+    #[serde(default)]
+    pub deprecated_penalty: u32, // 非推奨メンバーの sortText を下げる重み (0 で無効)
+    #[serde(default)]
+    pub editor_only_penalty: u32, // WITH_EDITOR 内のメンバーの sortText を下げる重み (0 で無効)
+    #[serde(default)]
+    pub strict: bool, // 未対応のノード種別で空の結果ではなくエラーを返す (コーパス検証用)
+    #[serde(default)]
+    pub tree_cache_size: Option<usize>, // パース済みの木を保持するバッファ数 (既定 4, 0 で無効)
+    #[serde(default)]
+    pub namespace_label_details: bool, // 型候補の labelDetails.description に名前空間を入れる (同名の型の区別用)
+    #[serde(default)]
+    pub fuzzy_min_prefix: Option<usize>, // 入力途中の識別子がこの文字数以上ならサーバー側で部分列一致の絞り込みをする
+    #[serde(default)]
+    pub prefix_filter: bool, // fuzzy_min_prefix が無いとき、入力途中の識別子の前方一致 (大文字小文字無視) で絞り込む
+    #[serde(default)]
+    pub snippets: bool, // 関数の insertText を引数プレースホルダー付きのスニペット (insertTextFormat: 2) にする
+    #[serde(default)]
+    pub max_typedef_steps: Option<usize>, // typedef/using の連鎖をたどる上限 (既定 32)
+    #[serde(default)]
+    pub category_label_details: bool, // メンバー候補の labelDetails.description に Category を入れる
+    #[serde(default)]
+    pub lazy_resolve: bool, // detail/documentation を省き、completionItem/resolve で後から埋める
+    #[serde(default)]
+    pub include_scope: bool, // 既知の型へのフォールバックをインクルードしたヘッダーで宣言されたクラスに限る
+    #[serde(default)]
+    pub includes: Option<Vec<String>>, // include_scope で使うヘッダー ("GameFramework/Actor.h" など)。None ならバッファの #include から求める
+    #[serde(default)]
+    pub annotate_access: bool, // アクセスできない private/protected メンバーを隠さず、detail の先頭に "private " などを付けて出す
+    #[serde(skip)]
+    pub item_hook: Option<ItemHook>, // 組み込み側で各候補を書き換えるコールバック (JSON の設定からは指定できない)
+}
+
+/// 返す直前に各候補へ適用される後処理 (ラベルの変更や data の追加など)
+#[derive(Clone)]
+pub struct ItemHook(pub Arc<dyn Fn(&mut CompletionItem) + Send + Sync>);
+
+impl ItemHook {
+    pub fn new(f: impl Fn(&mut CompletionItem) + Send + Sync + 'static) -> Self {
+        ItemHook(Arc::new(f))
+    }
+}
+
+impl std::fmt::Debug for ItemHook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ItemHook(..)")
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+pub struct CompletionItem {
+    pub label: String,
+    #[serde(rename = "labelDetails", default, skip_serializing_if = "Option::is_none")]
+    pub label_details: Option<CompletionItemLabelDetails>,
+    pub kind: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub documentation: Option<String>,
+    #[serde(rename = "insertText")]
+    pub insert_text: String,
+    #[serde(rename = "insertTextFormat", default, skip_serializing_if = "Option::is_none")]
+    pub insert_text_format: Option<i64>, // 1: PlainText, 2: Snippet
+    #[serde(rename = "filterText", default, skip_serializing_if = "Option::is_none")]
+    pub filter_text: Option<String>,
+    #[serde(rename = "sortText", default, skip_serializing_if = "Option::is_none")]
+    pub sort_text: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<CompletionItemData>,
+}
+
+impl CompletionItem {
+    pub fn new(label: &str, kind: i64, detail: &str) -> Self {
+        CompletionItem {
+            label: label.to_string(),
+            kind,
+            detail: Some(detail.to_string()),
+            insert_text: label.to_string(),
+            insert_text_format: Some(1),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+pub struct CompletionItemLabelDetails {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+// 候補の定義元 (completionItem/resolve やランキングで使う)
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+pub struct CompletionItemData {
+    pub class: String,
+    pub file: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub class_id: Option<i64>, // lazy_resolve 時の completionItem/resolve 用
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>, // UPROPERTY/UFUNCTION の Category (クライアント側のグループ分け用)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub access: Option<String>, // メンバーの public/protected/private (annotate_access で表示を変える用)
+    #[serde(skip)]
+    pub is_static: bool,
+    #[serde(skip)]
+    pub flags: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+pub struct CompletionList {
+    #[serde(rename = "isIncomplete")]
+    pub is_incomplete: bool,
+    #[serde(rename = "itemDefaults", default, skip_serializing_if = "Option::is_none")]
+    pub item_defaults: Option<CompletionItemDefaults>,
+    pub items: Vec<CompletionItem>,
+}
+
+// LSP 3.17 CompletionList.itemDefaults
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+pub struct CompletionItemDefaults {
+    #[serde(rename = "insertTextFormat", default, skip_serializing_if = "Option::is_none")]
+    pub insert_text_format: Option<i64>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+pub struct TypeCompleteness {
+    pub class_name: String,
+    pub indexed: bool,
+    pub indexed_ancestors: Vec<String>,
+    pub missing_ancestors: Vec<String>, // 継承元として参照されているが classes に無いもの
+}
+
+mod duration_ms {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+
+    pub fn serialize<S: Serializer>(value: &Option<Duration>, s: S) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(d) => s.serialize_some(&(d.as_millis() as u64)),
+            None => s.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Duration>, D::Error> {
+        Ok(Option::<u64>::deserialize(d)?.map(Duration::from_millis))
+    }
 }
 
 use std::io::{self, Write};